@@ -0,0 +1,88 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::stream::ResultSink;
+use crate::types::{IndexStats, SearchQuery, SearchResult};
+
+/// What a backend can do natively, so the engine knows what to compensate for
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackendCapabilities {
+    /// Regular expressions are evaluated by the backend
+    pub regex: bool,
+    /// File contents can be searched
+    pub content_search: bool,
+    /// Results reflect file system changes without a rescan
+    pub live_updates: bool,
+    /// `total_count` is the true number of matches rather than the number returned
+    pub exact_total_count: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "status", content = "message", rename_all = "snake_case")]
+pub enum BackendHealth {
+    Healthy,
+    /// Usable, but results may be incomplete
+    Degraded(String),
+    Unavailable(String),
+}
+
+impl BackendHealth {
+    pub fn is_available(&self) -> bool {
+        !matches!(self, BackendHealth::Unavailable(_))
+    }
+}
+
+/// Health and capabilities of one backend, as reported to the frontend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackendStatus {
+    pub name: String,
+    pub health: BackendHealth,
+    pub capabilities: BackendCapabilities,
+}
+
+/// A source of search results. Implementations must honour every field of `SearchQuery`.
+#[async_trait]
+pub trait SearchBackend: Send + Sync {
+    /// Short identifier used in logs and status reports
+    fn name(&self) -> &'static str;
+
+    fn capabilities(&self) -> BackendCapabilities;
+
+    async fn search(&self, query: &SearchQuery) -> Result<SearchResult>;
+
+    /// Like `search`, but also sends matches to `sink` as they are found, and stops early
+    /// once it is cancelled. By default the whole result is sent as one batch.
+    async fn search_streaming(&self, query: &SearchQuery, sink: &ResultSink) -> Result<SearchResult> {
+        let result = self.search(query).await?;
+        sink.send(result.entries.clone());
+        Ok(result)
+    }
+
+    /// A counter that changes whenever the results of any query might have, so results can
+    /// be cached until it does. `None` when the backend cannot tell, which disables caching.
+    fn generation(&self) -> Option<u64> {
+        None
+    }
+
+    /// Whether the backend has nothing to search right now, so the engine can skip it
+    fn is_empty(&self) -> bool {
+        false
+    }
+
+    async fn stats(&self) -> Result<IndexStats>;
+
+    async fn health(&self) -> BackendHealth;
+}
+
+/// Backends that can be selected in `AppConfig::backends`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendKind {
+    /// The Everything DLL (Windows only)
+    Everything,
+    /// The native SQLite index kept current by `FileIndexer`
+    Index,
+    /// A live directory walk, no index required
+    Walk,
+}
@@ -0,0 +1,300 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+use crate::backend::BackendKind;
+use crate::hashing::HashAlgorithm;
+
+const APP_DIR_NAME: &str = "everything-plus";
+const CONFIG_FILE_NAME: &str = "config.json";
+const DATABASE_FILE_NAME: &str = "index.db";
+
+/// Backend configuration, read from `config.json` in the platform config directory.
+/// Missing fields fall back to their defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    /// Location of the SQLite index database
+    pub database_path: PathBuf,
+    /// Roots crawled and watched by the native indexer
+    pub index_roots: Vec<PathBuf>,
+    /// Path fragments that are never indexed
+    pub exclude_patterns: Vec<String>,
+    /// Settings for the live directory walk backend
+    pub walk: WalkConfig,
+    /// Settings for indexing file contents
+    pub content: ContentConfig,
+    /// Settings for hashing indexed files
+    pub hashing: HashConfig,
+    /// Weights of the signals that order relevance-sorted results
+    pub ranking: RankingConfig,
+    /// Settings for the search history
+    pub history: HistoryConfig,
+    /// Settings for the cache of recent query results
+    pub cache: CacheConfig,
+    /// Settings for seeding new roots from a locate database
+    pub locate: LocateConfig,
+    /// Backends to search; earlier backends win when results share a path. Empty
+    /// selects Everything when its DLL is available and the native index otherwise.
+    pub backends: Vec<BackendKind>,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        let data_dir = dirs::data_local_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(APP_DIR_NAME);
+
+        Self {
+            database_path: data_dir.join(DATABASE_FILE_NAME),
+            index_roots: dirs::home_dir().into_iter().collect(),
+            exclude_patterns: vec![
+                "System Volume Information".to_string(),
+                "$Recycle.Bin".to_string(),
+                "Windows/WinSxS".to_string(),
+                "ProgramData/Microsoft".to_string(),
+                ".git".to_string(),
+                "node_modules".to_string(),
+                ".vs".to_string(),
+                ".vscode".to_string(),
+                "target".to_string(), // Rust build directory
+                "dist".to_string(),
+                "build".to_string(),
+            ],
+            walk: WalkConfig::default(),
+            content: ContentConfig::default(),
+            hashing: HashConfig::default(),
+            ranking: RankingConfig::default(),
+            history: HistoryConfig::default(),
+            cache: CacheConfig::default(),
+            locate: LocateConfig::default(),
+            backends: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WalkConfig {
+    /// Roots scanned on every query, by default the home directory and mounted volumes
+    pub roots: Vec<PathBuf>,
+    /// Directory levels below a root to descend into
+    pub max_depth: usize,
+    /// Wall-clock limit for one walk; the results found so far are returned when it runs out
+    pub time_budget_ms: u64,
+}
+
+impl Default for WalkConfig {
+    fn default() -> Self {
+        Self {
+            roots: default_walk_roots(),
+            max_depth: 32,
+            time_budget_ms: 2000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContentConfig {
+    /// Extract and index the text of files with one of `extensions`
+    pub enabled: bool,
+    /// Larger files are never read
+    pub max_file_size: u64,
+    /// Lowercase extensions, without the dot, of files treated as text
+    pub extensions: Vec<String>,
+}
+
+impl Default for ContentConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_file_size: 1024 * 1024,
+            extensions: [
+                "txt", "md", "rst", "log", "csv", "tsv", "json", "jsonc", "yaml", "yml", "toml", "ini",
+                "cfg", "conf", "config", "env", "properties", "xml", "html", "htm", "css", "scss", "js",
+                "jsx", "mjs", "cjs", "ts", "tsx", "vue", "svelte", "rs", "go", "py", "rb", "java", "kt",
+                "c", "h", "cpp", "hpp", "cc", "cs", "swift", "php", "sh", "bash", "zsh", "fish", "ps1",
+                "bat", "cmd", "sql", "graphql", "proto", "gradle", "tex",
+            ]
+            .iter()
+            .map(|ext| ext.to_string())
+            .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HashConfig {
+    /// Hash every indexed file in the background, so `hash:` can find it
+    pub enabled: bool,
+    /// Changing it hashes every file again
+    pub algorithm: HashAlgorithm,
+}
+
+/// Each signal scores a result between 0 and 1; a result's rank is the weighted sum
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RankingConfig {
+    /// Rank relevance-sorted results by the weighted signals rather than match quality alone
+    pub enabled: bool,
+    /// Where the query matches the name: a prefix, the start of a word or mid-word
+    pub match_weight: f64,
+    /// The name is the query, with or without its extension
+    pub exact_weight: f64,
+    /// How recently the file was modified
+    pub recency_weight: f64,
+    /// How few directories deep the file is
+    pub depth_weight: f64,
+    /// How often and how recently the file was opened from the results
+    pub usage_weight: f64,
+    /// Days after which the recency and usage signals have halved
+    pub half_life_days: f64,
+}
+
+impl Default for RankingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            match_weight: 1.0,
+            exact_weight: 1.0,
+            recency_weight: 0.3,
+            depth_weight: 0.2,
+            usage_weight: 0.8,
+            half_life_days: 30.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// Start in private mode, where searches are not recorded
+    pub private: bool,
+    /// Distinct queries kept; the least recently used are dropped beyond this
+    pub max_entries: usize,
+    /// Days after which a past query's weight in the history order has halved
+    pub half_life_days: f64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            private: false,
+            max_entries: 1000,
+            half_life_days: 14.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Query results kept until the index changes; 0 disables the cache
+    pub capacity: usize,
+    /// Rows kept across all cached results; a larger result is not cached
+    pub max_rows: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self { capacity: 64, max_rows: 200_000 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LocateConfig {
+    /// Fill a newly added root from the system's `plocate` or `mlocate` database, so it is
+    /// searchable before its first crawl has finished
+    pub enabled: bool,
+    /// Databases tried in order; the first that can be read is used
+    pub databases: Vec<PathBuf>,
+}
+
+impl Default for LocateConfig {
+    fn default() -> Self {
+        let databases = if cfg!(target_os = "linux") {
+            vec![
+                PathBuf::from("/var/lib/plocate/plocate.db"),
+                PathBuf::from("/var/lib/mlocate/mlocate.db"),
+            ]
+        } else {
+            Vec::new()
+        };
+        Self { enabled: true, databases }
+    }
+}
+
+/// The home directory followed by mounted volumes
+fn default_walk_roots() -> Vec<PathBuf> {
+    let mut roots: Vec<PathBuf> = dirs::home_dir().into_iter().collect();
+
+    for volume in mounted_volumes() {
+        if !roots.iter().any(|root| volume.starts_with(root) || root.starts_with(&volume)) {
+            roots.push(volume);
+        }
+    }
+
+    roots
+}
+
+#[cfg(windows)]
+fn mounted_volumes() -> Vec<PathBuf> {
+    // Skip A: and B:, probing floppy drives can block
+    ('C'..='Z')
+        .map(|letter| PathBuf::from(format!("{}:\\", letter)))
+        .filter(|root| root.exists())
+        .collect()
+}
+
+#[cfg(target_os = "macos")]
+fn mounted_volumes() -> Vec<PathBuf> {
+    std::fs::read_dir("/Volumes")
+        .map(|dir| dir.flatten().map(|entry| entry.path()).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn mounted_volumes() -> Vec<PathBuf> {
+    // Removable and manually mounted volumes; system mounts are covered by their parents
+    const VOLUME_PREFIXES: &[&str] = &["/mnt/", "/media/", "/run/media/"];
+
+    let Ok(mounts) = std::fs::read_to_string("/proc/mounts") else {
+        return Vec::new();
+    };
+
+    mounts
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(|mount_point| mount_point.replace("\\040", " "))
+        .filter(|mount_point| VOLUME_PREFIXES.iter().any(|prefix| mount_point.starts_with(prefix)))
+        .map(PathBuf::from)
+        .collect()
+}
+
+impl AppConfig {
+    /// Path of the configuration file, if the platform has a config directory
+    pub fn config_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(APP_DIR_NAME).join(CONFIG_FILE_NAME))
+    }
+
+    /// Load the configuration file, or return the defaults if there is none
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::config_path() else {
+            warn!("No config directory available, using default configuration");
+            return Ok(Self::default());
+        };
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(&path)?;
+        let config = serde_json::from_str(&contents)?;
+        info!("Loaded configuration from {}", path.display());
+        Ok(config)
+    }
+}
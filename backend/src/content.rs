@@ -0,0 +1,218 @@
+use anyhow::Result;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+use crate::config::ContentConfig;
+use crate::database::Database;
+use crate::types::FileEntry;
+
+/// Bytes inspected for NUL characters when deciding whether a file is binary
+const BINARY_SNIFF_LEN: usize = 8192;
+/// Entries fetched per round of `ContentIndexer::index_pending`
+const PENDING_BATCH_SIZE: i64 = 500;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ContentIndexReport {
+    /// Files whose text was added to or replaced in the content index
+    pub indexed: u64,
+    /// Files that were read again but whose text had not changed
+    pub unchanged: u64,
+    /// Binary or unreadable files
+    pub skipped: u64,
+}
+
+/// Extracts the text of indexed files into the database's full-text content index
+pub struct ContentIndexer {
+    db: Arc<Database>,
+    config: ContentConfig,
+}
+
+impl ContentIndexer {
+    pub fn new(db: Arc<Database>, config: ContentConfig) -> Self {
+        Self { db, config }
+    }
+
+    /// Whether the contents of `entry` should be indexed
+    pub fn is_candidate(&self, entry: &FileEntry) -> bool {
+        !entry.is_directory
+            && entry.size as u64 <= self.config.max_file_size
+            && entry
+                .extension
+                .as_ref()
+                .is_some_and(|ext| self.config.extensions.contains(ext))
+    }
+
+    /// Index every candidate entry that is new or whose size or modification time changed
+    pub async fn index_pending(&self) -> Result<ContentIndexReport> {
+        let mut report = ContentIndexReport::default();
+
+        loop {
+            let pending = self
+                .db
+                .pending_content_entries(&self.config.extensions, self.config.max_file_size as i64, PENDING_BATCH_SIZE)
+                .await?;
+            if pending.is_empty() {
+                break;
+            }
+
+            for entry in &pending {
+                match self.index_entry(entry).await {
+                    Ok(Some(true)) => report.indexed += 1,
+                    Ok(Some(false)) => report.unchanged += 1,
+                    Ok(None) => report.skipped += 1,
+                    Err(e) => {
+                        warn!("Failed to index content of {}: {}", entry.path, e);
+                        // Not retried until the file changes
+                        self.db.mark_content_unreadable(entry).await?;
+                        report.skipped += 1;
+                    }
+                }
+            }
+        }
+
+        info!(
+            "Content indexing completed: {} indexed, {} unchanged, {} skipped",
+            report.indexed, report.unchanged, report.skipped
+        );
+        Ok(report)
+    }
+
+    /// Read the file behind `entry` and store its text. Returns whether the text changed,
+    /// or `None` if the entry is not a text file.
+    pub async fn index_entry(&self, entry: &FileEntry) -> Result<Option<bool>> {
+        if !self.is_candidate(entry) {
+            return Ok(None);
+        }
+
+        let path = entry.path.clone();
+        let max_size = self.config.max_file_size;
+        let text = tokio::task::spawn_blocking(move || extract_text(Path::new(&path), max_size)).await??;
+
+        let Some(text) = text else {
+            self.db.mark_content_unreadable(entry).await?;
+            return Ok(None);
+        };
+
+        let hash = blake3::hash(text.as_bytes()).to_hex().to_string();
+        let changed = self.db.upsert_content(entry, &hash, &text).await?;
+        if changed {
+            debug!("Indexed content: {}", entry.path);
+        }
+        Ok(Some(changed))
+    }
+}
+
+/// Read a file as text, or `None` if it is larger than `max_size` bytes or looks binary
+pub fn extract_text(path: &Path, max_size: u64) -> Result<Option<String>> {
+    let file = std::fs::File::open(path)?;
+    if file.metadata()?.len() > max_size {
+        return Ok(None);
+    }
+
+    let mut bytes = Vec::new();
+    file.take(max_size + 1).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > max_size || bytes[..bytes.len().min(BINARY_SNIFF_LEN)].contains(&0) {
+        return Ok(None);
+    }
+
+    let text = String::from_utf8_lossy(&bytes);
+    Ok(Some(text.trim_start_matches('\u{feff}').to_string()))
+}
+
+/// Read the file at `path` and check it for `needle`, for backends without a content index.
+/// Only files `config` would index are read.
+pub fn file_contains(path: &Path, needle: &str, case_sensitive: bool, config: &ContentConfig) -> bool {
+    let is_text = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| config.extensions.contains(&ext));
+    if !is_text {
+        return false;
+    }
+
+    match extract_text(path, config.max_file_size) {
+        Ok(Some(text)) if case_sensitive => text.contains(needle),
+        Ok(Some(text)) => text.to_lowercase().contains(&needle.to_lowercase()),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{on_disk, query};
+    use crate::types::{SearchFilters, SearchQuery};
+    use std::path::PathBuf;
+
+    struct Fixture {
+        _dir: tempfile::TempDir,
+        db: Arc<Database>,
+        indexer: ContentIndexer,
+        settings: PathBuf,
+    }
+
+    /// A TOML and a Markdown file with text, and a `.txt` file that is binary, all indexed
+    async fn fixture() -> Fixture {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = dir.path().join("settings.toml");
+        let notes = dir.path().join("notes.md");
+        let binary = dir.path().join("blob.txt");
+        std::fs::write(&settings, "[pool]\nmax_connections = 20\n").unwrap();
+        std::fs::write(&notes, "Tune the pool before release").unwrap();
+        std::fs::write(&binary, b"max_connections\0\x01\x02").unwrap();
+
+        let db = Arc::new(Database::new(&dir.path().join("index.db")).await.unwrap());
+        let entries: Vec<FileEntry> = [&settings, &notes, &binary].iter().map(|path| on_disk(path)).collect();
+        db.batch_insert_file_entries(&entries).await.unwrap();
+        let indexer = ContentIndexer::new(db.clone(), ContentConfig::default());
+        Fixture { _dir: dir, db, indexer, settings }
+    }
+
+    fn content_query(text: &str) -> SearchQuery {
+        SearchQuery { filters: SearchFilters { search_content: true, ..Default::default() }, ..query(text) }
+    }
+
+    #[tokio::test]
+    async fn test_pending_files_are_read_once_and_binary_ones_skipped() {
+        let fixture = fixture().await;
+        let report = fixture.indexer.index_pending().await.unwrap();
+        assert_eq!((report.indexed, report.skipped), (2, 1));
+        // Nothing changed on disk, so nothing is read again
+        assert_eq!(fixture.indexer.index_pending().await.unwrap().indexed, 0);
+    }
+
+    #[tokio::test]
+    async fn test_contents_are_searched_only_when_asked_for() {
+        let fixture = fixture().await;
+        fixture.indexer.index_pending().await.unwrap();
+
+        let result = fixture.db.search(&content_query("MAX_CONNECTIONS")).await.unwrap();
+        assert_eq!(result.entries.len(), 1);
+        assert_eq!(result.entries[0].name, "settings.toml");
+        assert_eq!(fixture.db.search(&query("max_connections")).await.unwrap().total_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_content_terms_combine_with_other_terms() {
+        let fixture = fixture().await;
+        fixture.indexer.index_pending().await.unwrap();
+
+        assert_eq!(fixture.db.search(&query("content:pool ext:md")).await.unwrap().total_count, 1);
+        assert_eq!(fixture.db.search(&content_query("notes|pool")).await.unwrap().total_count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_rewritten_text_replaces_the_old_content() {
+        let fixture = fixture().await;
+        fixture.indexer.index_pending().await.unwrap();
+
+        std::fs::write(&fixture.settings, "[pool]\nmin_idle = 2\n").unwrap();
+        let changed = on_disk(&fixture.settings);
+        assert_eq!(fixture.indexer.index_entry(&changed).await.unwrap(), Some(true));
+        assert_eq!(fixture.indexer.index_entry(&changed).await.unwrap(), Some(false));
+        assert_eq!(fixture.db.search(&content_query("max_connections")).await.unwrap().total_count, 0);
+        assert_eq!(fixture.db.search(&content_query("min_idle")).await.unwrap().total_count, 1);
+    }
+}
@@ -1,27 +1,77 @@
 use anyhow::Result;
-use chrono::Utc;
-use sqlx::{Row, Sqlite, SqlitePool};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use regex::Regex;
 use sqlx::query::Query;
+use sqlx::sqlite::{
+    SqliteArguments, SqliteConnectOptions, SqliteConnection, SqliteJournalMode, SqlitePoolOptions, SqliteRow,
+    SqliteSynchronous,
+};
+use sqlx::{QueryBuilder, Row, Sqlite, SqlitePool};
+use std::collections::HashMap;
+use std::ffi::{c_int, CString};
 use std::path::Path;
-use tracing::{debug, info, warn};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use tracing::{debug, info};
 
-use crate::types::{FileEntry, SearchError, SearchFilters, SearchQuery, SearchResult};
+use crate::backend::{BackendCapabilities, BackendHealth, SearchBackend};
+use crate::hashing::HashAlgorithm;
+use crate::search::query::{parent_of, CompiledQuery, DateRange, Expr, Term, TextPattern};
+use crate::search::sort::{natural_cmp, relevance_needle, Sorter, NATURAL_COLLATION};
+use crate::types::{
+    FileEntry, FileOperationKind, FileOperationRecord, FileOperationStep, HistoryEntry, IndexStats, SavedSearch,
+    SearchFilters, SearchQuery, SearchResult, SortField,
+};
+
+const ENTRY_COLUMNS: &str =
+    "id, name, path, size, modified, created, is_directory, extension, attributes";
+
+/// Fuzzy matches buffered, at least, before the worst ones are dropped
+const FUZZY_BATCH_SIZE: usize = 1024;
+
+/// Matching rows examined per completion query, which keeps completion fast for short prefixes
+const COMPLETION_SCAN_LIMIT: i64 = 2000;
+
+/// Ids looked up per usage query, well below SQLite's limit on bound parameters
+const USAGE_BATCH_SIZE: usize = 500;
+
+/// Bumped on incompatible changes to the index tables, which are then rebuilt
+const SCHEMA_VERSION: i64 = 2;
 
 pub struct Database {
     pool: SqlitePool,
+    /// Bumped after every write to the index tables, so cached results can be invalidated
+    generation: AtomicU64,
+}
+
+/// How often and when a file was last opened
+#[derive(Debug, Clone, Copy)]
+pub struct FileUsage {
+    pub open_count: u64,
+    pub last_opened: DateTime<Utc>,
 }
 
 impl Database {
-    pub async fn new(database_path: &str) -> Result<Self> {
+    pub async fn new(database_path: &Path) -> Result<Self> {
         // Create database directory if it doesn't exist
-        if let Some(parent) = Path::new(database_path).parent() {
+        if let Some(parent) = database_path.parent() {
             tokio::fs::create_dir_all(parent).await?;
         }
 
-        let database_url = format!("sqlite:{}", database_path);
-        let pool = SqlitePool::connect(&database_url).await?;
+        let options = SqliteConnectOptions::new()
+            .filename(database_path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal)
+            .collation(NATURAL_COLLATION, natural_cmp);
+        let pool = SqlitePoolOptions::new()
+            .after_connect(|conn, _| Box::pin(register_fold(conn)))
+            .connect_with(options)
+            .await?;
 
-        let db = Self { pool };
+        let db = Self { pool, generation: AtomicU64::new(0) };
         db.initialize_schema().await?;
         db.optimize_database().await?;
 
@@ -29,27 +79,60 @@ impl Database {
     }
 
     async fn initialize_schema(&self) -> Result<()> {
+        let version: i64 = sqlx::query_scalar("PRAGMA user_version").fetch_one(&self.pool).await?;
+        let has_index: bool =
+            sqlx::query_scalar("SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'file_entries'")
+                .fetch_one(&self.pool)
+                .await?;
+        if has_index && version < SCHEMA_VERSION {
+            // The index only mirrors the file system, so it is rebuilt rather than migrated
+            info!("Index schema version {} is outdated, rebuilding the index", version);
+            sqlx::query(
+                r#"
+                DROP TABLE IF EXISTS file_entries;
+                DROP TABLE IF EXISTS file_search;
+                DROP TABLE IF EXISTS file_contents;
+                DROP TABLE IF EXISTS content_search;
+                DROP TABLE IF EXISTS indexed_roots;
+                "#,
+            )
+            .execute(&self.pool)
+            .await?;
+        }
+
         sqlx::query(
             r#"
+            -- id is not unique: a file moved while unwatched keeps its id, and is briefly
+            -- stored at both paths until the old one is reconciled
             CREATE TABLE IF NOT EXISTS file_entries (
-                id TEXT PRIMARY KEY,
+                id TEXT NOT NULL,
                 name TEXT NOT NULL,
                 path TEXT NOT NULL UNIQUE,
+                parent TEXT NOT NULL,
                 size INTEGER NOT NULL,
                 modified TEXT NOT NULL,
                 created TEXT NOT NULL,
                 is_directory BOOLEAN NOT NULL,
                 extension TEXT,
-                attributes INTEGER NOT NULL
+                attributes INTEGER NOT NULL,
+                -- Hex hash of the contents, empty if the file could not be read, with the
+                -- algorithm and the size and modification time it was computed for
+                hash TEXT,
+                hash_algorithm TEXT,
+                hashed_size INTEGER,
+                hashed_modified TEXT
             );
 
+            CREATE INDEX IF NOT EXISTS idx_id ON file_entries(id);
             CREATE INDEX IF NOT EXISTS idx_name ON file_entries(name);
             CREATE INDEX IF NOT EXISTS idx_path ON file_entries(path);
+            CREATE INDEX IF NOT EXISTS idx_parent ON file_entries(parent);
             CREATE INDEX IF NOT EXISTS idx_extension ON file_entries(extension);
             CREATE INDEX IF NOT EXISTS idx_size ON file_entries(size);
             CREATE INDEX IF NOT EXISTS idx_modified ON file_entries(modified);
             CREATE INDEX IF NOT EXISTS idx_is_directory ON file_entries(is_directory);
             CREATE INDEX IF NOT EXISTS idx_name_lower ON file_entries(LOWER(name));
+            CREATE INDEX IF NOT EXISTS idx_hash ON file_entries(hash);
             
             -- Full-text search virtual table for content search
             CREATE VIRTUAL TABLE IF NOT EXISTS file_search USING fts5(
@@ -61,33 +144,104 @@ impl Database {
                 INSERT INTO file_search(rowid, name, path) VALUES (NEW.rowid, NEW.name, NEW.path);
             END;
 
+            -- file_search reads its text from file_entries, where the old row is already gone,
+            -- so removals pass the old values through the FTS5 'delete' command
             CREATE TRIGGER IF NOT EXISTS file_entries_ad AFTER DELETE ON file_entries BEGIN
-                DELETE FROM file_search WHERE rowid = OLD.rowid;
+                INSERT INTO file_search(file_search, rowid, name, path)
+                VALUES ('delete', OLD.rowid, OLD.name, OLD.path);
             END;
 
             CREATE TRIGGER IF NOT EXISTS file_entries_au AFTER UPDATE ON file_entries BEGIN
-                DELETE FROM file_search WHERE rowid = OLD.rowid;
+                INSERT INTO file_search(file_search, rowid, name, path)
+                VALUES ('delete', OLD.rowid, OLD.name, OLD.path);
                 INSERT INTO file_search(rowid, name, path) VALUES (NEW.rowid, NEW.name, NEW.path);
             END;
+
+            -- Files whose contents have been read, with the size and modification time seen
+            -- then. content_hash is NULL for files that turned out to be binary or unreadable.
+            CREATE TABLE IF NOT EXISTS file_contents (
+                id INTEGER PRIMARY KEY,
+                path TEXT NOT NULL UNIQUE,
+                size INTEGER NOT NULL,
+                modified TEXT NOT NULL,
+                content_hash TEXT,
+                indexed_at TEXT NOT NULL
+            );
+
+            -- Extracted text keyed by file_contents.id. The trigram tokenizer lets LIKE
+            -- find substrings such as config keys inside longer identifiers.
+            CREATE VIRTUAL TABLE IF NOT EXISTS content_search USING fts5(
+                content, tokenize='trigram'
+            );
+
+            CREATE TRIGGER IF NOT EXISTS file_contents_ad AFTER DELETE ON file_contents BEGIN
+                DELETE FROM content_search WHERE rowid = OLD.id;
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS file_entries_content_ad AFTER DELETE ON file_entries BEGIN
+                DELETE FROM file_contents WHERE path = OLD.path;
+            END;
+
+            -- Roots crawled by the indexer and when each was last completed
+            CREATE TABLE IF NOT EXISTS indexed_roots (
+                path TEXT PRIMARY KEY,
+                last_indexed TEXT NOT NULL
+            );
+
+            -- Files opened from the results, keyed by file id so the history survives renames.
+            -- Not part of the index mirror, so it is kept when the index is rebuilt.
+            CREATE TABLE IF NOT EXISTS file_usage (
+                id TEXT PRIMARY KEY,
+                open_count INTEGER NOT NULL,
+                last_opened TEXT NOT NULL
+            );
+
+            -- Past queries, one row per distinct query text, also kept across rebuilds
+            CREATE TABLE IF NOT EXISTS search_history (
+                query TEXT PRIMARY KEY,
+                filters TEXT NOT NULL,
+                use_count INTEGER NOT NULL,
+                first_used TEXT NOT NULL,
+                last_used TEXT NOT NULL
+            );
+
+            -- Searches saved by name, also kept across rebuilds
+            CREATE TABLE IF NOT EXISTS saved_searches (
+                name TEXT PRIMARY KEY COLLATE NOCASE,
+                query TEXT NOT NULL,
+                filters TEXT NOT NULL,
+                sort TEXT NOT NULL
+            );
+
+            -- Everything file lists to load at startup, by name, also kept across rebuilds
+            CREATE TABLE IF NOT EXISTS file_lists (
+                name TEXT PRIMARY KEY COLLATE NOCASE,
+                path TEXT NOT NULL
+            );
+
+            -- File operations performed from the app, latest last, kept across rebuilds so
+            -- they can still be undone
+            CREATE TABLE IF NOT EXISTS file_operations (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                kind TEXT NOT NULL,
+                steps TEXT NOT NULL,
+                performed_at TEXT NOT NULL
+            );
             "#,
         )
         .execute(&self.pool)
         .await?;
 
+        sqlx::query(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))
+            .execute(&self.pool)
+            .await?;
+
         info!("Database schema initialized");
         Ok(())
     }
 
     async fn optimize_database(&self) -> Result<()> {
-        // SQLite optimization settings
-        sqlx::query("PRAGMA journal_mode = WAL")
-            .execute(&self.pool)
-            .await?;
-        
-        sqlx::query("PRAGMA synchronous = NORMAL")
-            .execute(&self.pool)
-            .await?;
-        
+        // SQLite optimization settings (journal mode and sync level are set on connect)
         sqlx::query("PRAGMA cache_size = 10000")
             .execute(&self.pool)
             .await?;
@@ -104,60 +258,816 @@ impl Database {
         Ok(())
     }
 
+    /// Number of writes to the index so far; results read at one generation stay valid
+    /// until it changes
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    fn changed(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+    }
+
     pub async fn insert_file_entry(&self, entry: &FileEntry) -> Result<()> {
-        sqlx::query(
+        upsert_entry(entry).execute(&self.pool).await?;
+        self.changed();
+        Ok(())
+    }
+
+    pub async fn batch_insert_file_entries(&self, entries: &[FileEntry]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for entry in entries {
+            upsert_entry(entry).execute(&mut *tx).await?;
+        }
+
+        tx.commit().await?;
+        self.changed();
+        Ok(())
+    }
+
+    pub async fn get_entry(&self, path: &str) -> Result<Option<FileEntry>> {
+        let row = sqlx::query(&format!("SELECT {} FROM file_entries WHERE path = ?", ENTRY_COLUMNS))
+            .bind(path)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(row_to_entry).transpose()
+    }
+
+    /// Entries directly inside the directory at `path`
+    pub async fn children_of(&self, path: &str) -> Result<Vec<FileEntry>> {
+        // A file system root is stored with an empty parent, like its children
+        let rows = sqlx::query(&format!("SELECT {} FROM file_entries WHERE parent = ? AND path != ?", ENTRY_COLUMNS))
+            .bind(path.trim_end_matches(['/', '\\']))
+            .bind(path)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(row_to_entry).collect()
+    }
+
+    /// Remove the entry at `path`, with its usage. Returns the number of entries removed.
+    pub async fn delete_by_path(&self, path: &str) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let ids: Vec<String> = sqlx::query_scalar("SELECT id FROM file_entries WHERE path = ?")
+            .bind(path)
+            .fetch_all(&mut *tx)
+            .await?;
+        let result = sqlx::query("DELETE FROM file_entries WHERE path = ?")
+            .bind(path)
+            .execute(&mut *tx)
+            .await?;
+        delete_orphaned_usage(&mut tx, &ids).await?;
+        tx.commit().await?;
+        self.changed();
+        Ok(result.rows_affected())
+    }
+
+    /// Remove every entry below the directory at `prefix`, but not the directory itself,
+    /// with their usage. Returns the number of entries removed.
+    pub async fn delete_by_prefix(&self, prefix: &str) -> Result<u64> {
+        let (lower, upper) = descendant_range(prefix);
+        let mut tx = self.pool.begin().await?;
+        let ids: Vec<String> = sqlx::query_scalar("SELECT DISTINCT id FROM file_entries WHERE path >= ? AND path < ?")
+            .bind(&lower)
+            .bind(&upper)
+            .fetch_all(&mut *tx)
+            .await?;
+        let result = sqlx::query("DELETE FROM file_entries WHERE path >= ? AND path < ?")
+            .bind(lower)
+            .bind(upper)
+            .execute(&mut *tx)
+            .await?;
+        delete_orphaned_usage(&mut tx, &ids).await?;
+        tx.commit().await?;
+        self.changed();
+        Ok(result.rows_affected())
+    }
+
+    pub async fn search(&self, query: &SearchQuery) -> Result<SearchResult> {
+        let start_time = Instant::now();
+
+        if query.query.is_empty() {
+            return Ok(SearchResult {
+                entries: Vec::new(),
+                total_count: 0,
+                query_time_ms: 0,
+            });
+        }
+
+        let limit = query.limit.unwrap_or(1000) as usize;
+        let offset = query.offset.unwrap_or(0) as usize;
+
+        let compiled = CompiledQuery::compile(query)?;
+        let sorter = Sorter::new(query.sort, relevance_needle(&compiled));
+
+        let (entries, total_count) = if let Some(regex) = &compiled.regex {
+            self.search_regex(&compiled, &sorter, regex, limit, offset).await?
+        } else if compiled.fuzzy {
+            self.search_fuzzy(&compiled, &sorter, limit, offset).await?
+        } else {
+            let mut count_builder = QueryBuilder::new("SELECT COUNT(*) FROM file_entries WHERE ");
+            push_query(&mut count_builder, &compiled);
+            let total_count: i64 = count_builder.build_query_scalar().fetch_one(&self.pool).await?;
+
+            let mut builder = QueryBuilder::new(format!("SELECT {} FROM file_entries WHERE ", ENTRY_COLUMNS));
+            push_query(&mut builder, &compiled);
+            push_order_by(&mut builder, &sorter);
+            builder.push(" LIMIT ");
+            builder.push_bind(limit as i64);
+            builder.push(" OFFSET ");
+            builder.push_bind(offset as i64);
+
+            let rows = builder.build().fetch_all(&self.pool).await?;
+            let entries = rows.iter().map(row_to_entry).collect::<Result<Vec<_>>>()?;
+            (entries, total_count as u64)
+        };
+
+        Ok(SearchResult {
+            entries,
+            total_count,
+            query_time_ms: start_time.elapsed().as_millis() as u64,
+        })
+    }
+
+    /// SQLite has no built-in REGEXP, so regex queries stream the filtered rows and match in Rust
+    async fn search_regex(
+        &self,
+        compiled: &CompiledQuery,
+        sorter: &Sorter,
+        regex: &Regex,
+        limit: usize,
+        offset: usize,
+    ) -> Result<(Vec<FileEntry>, u64)> {
+        let mut builder = QueryBuilder::new(format!("SELECT {} FROM file_entries WHERE ", ENTRY_COLUMNS));
+        push_query(&mut builder, compiled);
+        push_order_by(&mut builder, sorter);
+
+        let mut rows = builder.build().fetch(&self.pool);
+        let mut entries = Vec::new();
+        let mut total_count = 0u64;
+
+        while let Some(row) = rows.try_next().await? {
+            let name: String = row.get("name");
+            if !regex.is_match(&name) {
+                continue;
+            }
+
+            if total_count as usize >= offset && entries.len() < limit {
+                entries.push(row_to_entry(&row)?);
+            }
+            total_count += 1;
+        }
+
+        Ok((entries, total_count))
+    }
+
+    /// Fuzzy candidates are narrowed down in SQL, then scored and ordered in Rust. Only the
+    /// best `offset + limit` rows are kept while streaming.
+    async fn search_fuzzy(
+        &self,
+        compiled: &CompiledQuery,
+        sorter: &Sorter,
+        limit: usize,
+        offset: usize,
+    ) -> Result<(Vec<FileEntry>, u64)> {
+        let mut builder = QueryBuilder::new(format!("SELECT {} FROM file_entries WHERE ", ENTRY_COLUMNS));
+        push_query(&mut builder, compiled);
+
+        let wanted = offset + limit;
+        let mut rows = builder.build().fetch(&self.pool);
+        let mut entries = Vec::new();
+        let mut total_count = 0u64;
+
+        while let Some(row) = rows.try_next().await? {
+            let mut entry = row_to_entry(&row)?;
+            entry.score = compiled.score(&entry);
+            entries.push(entry);
+            total_count += 1;
+
+            if entries.len() >= 2 * wanted.max(FUZZY_BATCH_SIZE) {
+                entries.select_nth_unstable_by(wanted, |a, b| sorter.compare(a, b));
+                entries.truncate(wanted);
+            }
+        }
+
+        sorter.sort_entries(&mut entries);
+        let entries = entries.into_iter().skip(offset).take(limit).collect();
+        Ok((entries, total_count))
+    }
+
+    /// Record that a crawl of `path` has completed
+    pub async fn record_indexed_root(&self, path: &str) -> Result<()> {
+        sqlx::query("INSERT OR REPLACE INTO indexed_roots (path, last_indexed) VALUES (?, ?)")
+            .bind(path)
+            .bind(Utc::now().to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Text-file entries whose contents were never read or have changed since
+    pub async fn pending_content_entries(
+        &self,
+        extensions: &[String],
+        max_size: i64,
+        limit: i64,
+    ) -> Result<Vec<FileEntry>> {
+        if extensions.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let columns = ENTRY_COLUMNS
+            .split(", ")
+            .map(|column| format!("e.{}", column))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut builder = QueryBuilder::new(format!(
+            "SELECT {} FROM file_entries e LEFT JOIN file_contents c ON c.path = e.path \
+             WHERE e.is_directory = 0 AND e.size <= ",
+            columns
+        ));
+        builder.push_bind(max_size);
+        builder.push(" AND e.extension IN (");
+        let mut separated = builder.separated(", ");
+        for extension in extensions {
+            separated.push_bind(extension.clone());
+        }
+        separated.push_unseparated(")");
+        builder.push(" AND (c.path IS NULL OR c.size != e.size OR c.modified != e.modified) LIMIT ");
+        builder.push_bind(limit);
+
+        let rows = builder.build().fetch_all(&self.pool).await?;
+        rows.iter().map(row_to_entry).collect()
+    }
+
+    /// Files of at least `min_size` bytes that share their size with another file, by size
+    pub async fn duplicate_candidates(&self, min_size: u64) -> Result<Vec<FileEntry>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM file_entries WHERE is_directory = 0 AND size >= ?1 AND size IN \
+             (SELECT size FROM file_entries WHERE is_directory = 0 AND size >= ?1 \
+              GROUP BY size HAVING COUNT(*) > 1) \
+             ORDER BY size, path",
+            ENTRY_COLUMNS
+        ))
+        .bind(min_size as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(row_to_entry).collect()
+    }
+
+    /// Up to `limit` files without an `algorithm` hash of their current size and
+    /// modification time
+    pub async fn pending_hash_entries(&self, algorithm: HashAlgorithm, limit: i64) -> Result<Vec<FileEntry>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM file_entries WHERE is_directory = 0 AND (hash IS NULL OR hash_algorithm != ? \
+             OR hashed_size != size OR hashed_modified != modified) LIMIT ?",
+            ENTRY_COLUMNS
+        ))
+        .bind(algorithm.name())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(row_to_entry).collect()
+    }
+
+    /// Store the `algorithm` hash of each entry as it is now, or `None` for files that could
+    /// not be read, which are not tried again until they change
+    pub async fn set_hashes(&self, algorithm: HashAlgorithm, hashes: &[(FileEntry, Option<String>)]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for (entry, hash) in hashes {
+            sqlx::query(
+                "UPDATE file_entries SET hash = ?, hash_algorithm = ?, hashed_size = ?, hashed_modified = ? \
+                 WHERE path = ?",
+            )
+            .bind(hash.as_deref().unwrap_or(""))
+            .bind(algorithm.name())
+            .bind(entry.size)
+            .bind(entry.modified.to_rfc3339())
+            .bind(&entry.path)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        self.changed();
+        Ok(())
+    }
+
+    /// Store the extracted text of `entry`. Returns false if the stored text already had `content_hash`.
+    pub async fn upsert_content(&self, entry: &FileEntry, content_hash: &str, text: &str) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let existing = sqlx::query("SELECT id, content_hash FROM file_contents WHERE path = ?")
+            .bind(&entry.path)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let changed = existing
+            .as_ref()
+            .is_none_or(|row| row.get::<Option<String>, _>("content_hash").as_deref() != Some(content_hash));
+
+        let id: i64 = sqlx::query_scalar(
             r#"
-            INSERT OR REPLACE INTO file_entries 
-            (id, name, path, size, modified, created, is_directory, extension, attributes)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            INSERT INTO file_contents (path, size, modified, content_hash, indexed_at)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT(path) DO UPDATE SET
+                size = excluded.size,
+                modified = excluded.modified,
+                content_hash = excluded.content_hash,
+                indexed_at = excluded.indexed_at
+            RETURNING id
             "#,
         )
-        .bind(entry.id.to_string())
-        .bind(&entry.name)
         .bind(&entry.path)
         .bind(entry.size)
         .bind(entry.modified.to_rfc3339())
-        .bind(entry.created.to_rfc3339())
-        .bind(entry.is_directory)
-        .bind(&entry.extension)
-        .bind(entry.attributes)
-        .execute(&self.pool)
+        .bind(content_hash)
+        .bind(Utc::now().to_rfc3339())
+        .fetch_one(&mut *tx)
         .await?;
 
+        if changed {
+            sqlx::query("DELETE FROM content_search WHERE rowid = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("INSERT INTO content_search (rowid, content) VALUES (?, ?)")
+                .bind(id)
+                .bind(text)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        if changed {
+            self.changed();
+        }
+        Ok(changed)
+    }
+
+    /// Record that `entry` has no searchable text, so it is skipped until it changes
+    pub async fn mark_content_unreadable(&self, entry: &FileEntry) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let id: i64 = sqlx::query_scalar(
+            r#"
+            INSERT INTO file_contents (path, size, modified, content_hash, indexed_at)
+            VALUES (?, ?, ?, NULL, ?)
+            ON CONFLICT(path) DO UPDATE SET
+                size = excluded.size,
+                modified = excluded.modified,
+                content_hash = NULL,
+                indexed_at = excluded.indexed_at
+            RETURNING id
+            "#,
+        )
+        .bind(&entry.path)
+        .bind(entry.size)
+        .bind(entry.modified.to_rfc3339())
+        .bind(Utc::now().to_rfc3339())
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query("DELETE FROM content_search WHERE rowid = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        self.changed();
         Ok(())
     }
 
-    pub async fn batch_insert_file_entries(&self, entries: &[FileEntry]) -> Result<()> {
+    /// Move the entry at `from`, and everything below it, to `to`. Inode-based ids are kept,
+    /// so data attached to an entry follows it. Returns the number of entries moved.
+    pub async fn rename_path(&self, from: &str, to: &str) -> Result<u64> {
+        let from = from.trim_end_matches(['/', '\\']);
+        let to = to.trim_end_matches(['/', '\\']);
+        let (from_lower, from_upper) = descendant_range(from);
+        let (to_lower, to_upper) = descendant_range(to);
+
         let mut tx = self.pool.begin().await?;
 
-        for entry in entries {
-            sqlx::query(
-                r#"
-                INSERT OR REPLACE INTO file_entries 
-                (id, name, path, size, modified, created, is_directory, extension, attributes)
-                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
-                "#,
-            )
-            .bind(entry.id.to_string())
-            .bind(&entry.name)
-            .bind(&entry.path)
-            .bind(entry.size)
-            .bind(entry.modified.to_rfc3339())
-            .bind(entry.created.to_rfc3339())
-            .bind(entry.is_directory)
-            .bind(&entry.extension)
-            .bind(entry.attributes)
+        let rows = sqlx::query(
+            "SELECT rowid, id, path, name, is_directory, extension FROM file_entries \
+             WHERE path = ? OR (path >= ? AND path < ?)",
+        )
+        .bind(from)
+        .bind(&from_lower)
+        .bind(&from_upper)
+        .fetch_all(&mut *tx)
+        .await?;
+        if rows.is_empty() {
+            return Ok(0);
+        }
+
+        // Whatever was stored at the destination has been replaced, usage included
+        let replaced: Vec<String> = sqlx::query_scalar(
+            "SELECT DISTINCT id FROM file_entries WHERE path = ? OR (path >= ? AND path < ?)",
+        )
+        .bind(to)
+        .bind(&to_lower)
+        .bind(&to_upper)
+        .fetch_all(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM file_entries WHERE path = ? OR (path >= ? AND path < ?)")
+            .bind(to)
+            .bind(&to_lower)
+            .bind(&to_upper)
             .execute(&mut *tx)
             .await?;
+
+        for row in &rows {
+            let old_path: String = row.get("path");
+            let new_path = format!("{}{}", to, &old_path[from.len()..]);
+            let is_directory: bool = row.get("is_directory");
+
+            let (name, extension) = if old_path == from {
+                let name = Path::new(&new_path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let extension = match is_directory {
+                    true => None,
+                    false => Path::new(&name).extension().map(|ext| ext.to_string_lossy().to_lowercase()),
+                };
+                (name, extension)
+            } else {
+                (row.get("name"), row.get("extension"))
+            };
+
+            // Path-based ids follow the path, and so does their usage
+            let mut id: String = row.get("id");
+            if id == FileEntry::path_id(Path::new(&old_path)) {
+                let new_id = FileEntry::path_id(Path::new(&new_path));
+                sqlx::query("DELETE FROM file_usage WHERE id = ?")
+                    .bind(&new_id)
+                    .execute(&mut *tx)
+                    .await?;
+                sqlx::query("UPDATE file_usage SET id = ? WHERE id = ?")
+                    .bind(&new_id)
+                    .bind(&id)
+                    .execute(&mut *tx)
+                    .await?;
+                id = new_id;
+            }
+
+            sqlx::query("UPDATE file_entries SET id = ?, path = ?, name = ?, parent = ?, extension = ? WHERE rowid = ?")
+                .bind(id)
+                .bind(&new_path)
+                .bind(&name)
+                .bind(parent_of(&new_path, &name))
+                .bind(extension)
+                .bind(row.get::<i64, _>("rowid"))
+                .execute(&mut *tx)
+                .await?;
+
+            if !is_directory {
+                sqlx::query("UPDATE file_contents SET path = ? WHERE path = ?")
+                    .bind(&new_path)
+                    .bind(&old_path)
+                    .execute(&mut *tx)
+                    .await?;
+            }
         }
+        delete_orphaned_usage(&mut tx, &replaced).await?;
 
         tx.commit().await?;
+        self.changed();
+        Ok(rows.len() as u64)
+    }
+
+    /// Roots whose crawl has completed at least once
+    pub async fn indexed_roots(&self) -> Result<Vec<String>> {
+        let roots = sqlx::query_scalar("SELECT path FROM indexed_roots ORDER BY path")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(roots)
+    }
+
+    /// Count an open of the file with id `id`
+    pub async fn record_file_open(&self, id: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO file_usage (id, open_count, last_opened) VALUES (?, 1, ?)
+            ON CONFLICT(id) DO UPDATE SET
+                open_count = open_count + 1,
+                last_opened = excluded.last_opened
+            "#,
+        )
+        .bind(id)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
         Ok(())
     }
 
-    
+    /// Open history of the files among `ids` that have been opened, by file id
+    pub async fn file_usage(&self, ids: &[&str]) -> Result<HashMap<String, FileUsage>> {
+        let mut usage = HashMap::new();
+        for batch in ids.chunks(USAGE_BATCH_SIZE) {
+            let mut builder = QueryBuilder::new("SELECT id, open_count, last_opened FROM file_usage WHERE id IN (");
+            let mut separated = builder.separated(", ");
+            for id in batch {
+                separated.push_bind(*id);
+            }
+            separated.push_unseparated(")");
 
-    pub async fn get_stats(&self) -> Result<crate::types::IndexStats> {
+            for row in builder.build().fetch_all(&self.pool).await? {
+                let last_opened: String = row.try_get("last_opened")?;
+                let file_usage = FileUsage {
+                    open_count: row.try_get::<i64, _>("open_count")? as u64,
+                    last_opened: DateTime::parse_from_rfc3339(&last_opened)?.with_timezone(&Utc),
+                };
+                usage.insert(row.try_get("id")?, file_usage);
+            }
+        }
+        Ok(usage)
+    }
+
+    /// Count a run of `query`, remembering `filters` as its latest filters
+    pub async fn record_search(&self, query: &str, filters: &SearchFilters) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        sqlx::query(
+            r#"
+            INSERT INTO search_history (query, filters, use_count, first_used, last_used)
+            VALUES (?, ?, 1, ?, ?)
+            ON CONFLICT(query) DO UPDATE SET
+                filters = excluded.filters,
+                use_count = use_count + 1,
+                last_used = excluded.last_used
+            "#,
+        )
+        .bind(query)
+        .bind(serde_json::to_string(filters)?)
+        .bind(&now)
+        .bind(&now)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Every query in the search history, most recently used first
+    pub async fn search_history(&self) -> Result<Vec<HistoryEntry>> {
+        let rows = sqlx::query(
+            "SELECT query, filters, use_count, first_used, last_used FROM search_history ORDER BY last_used DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let parse_time = |column: &str| -> Result<DateTime<Utc>> {
+                    Ok(DateTime::parse_from_rfc3339(row.try_get(column)?)?.with_timezone(&Utc))
+                };
+                Ok(HistoryEntry {
+                    query: row.try_get("query")?,
+                    filters: serde_json::from_str(row.try_get("filters")?)?,
+                    use_count: row.try_get::<i64, _>("use_count")? as u64,
+                    first_used: parse_time("first_used")?,
+                    last_used: parse_time("last_used")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Remove `query` from the search history; returns whether it was there
+    pub async fn delete_search(&self, query: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM search_history WHERE query = ?")
+            .bind(query)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Drop all but the `keep` most recently used queries, or every query when `keep` is 0
+    pub async fn prune_search_history(&self, keep: usize) -> Result<u64> {
+        let result = sqlx::query(
+            "DELETE FROM search_history WHERE query NOT IN \
+             (SELECT query FROM search_history ORDER BY last_used DESC LIMIT ?)",
+        )
+        .bind(keep as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Every saved search, by name
+    pub async fn saved_searches(&self) -> Result<Vec<SavedSearch>> {
+        let rows = sqlx::query("SELECT name, query, filters, sort FROM saved_searches ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(SavedSearch {
+                    name: row.try_get("name")?,
+                    query: row.try_get("query")?,
+                    filters: serde_json::from_str(row.try_get("filters")?)?,
+                    sort: serde_json::from_str(row.try_get("sort")?)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Store `saved` under its name, replacing any search of that name when `replace` is
+    /// set; returns whether it was stored
+    pub async fn insert_saved_search(&self, saved: &SavedSearch, replace: bool) -> Result<bool> {
+        let conflict = if replace {
+            "DO UPDATE SET name = excluded.name, query = excluded.query, filters = excluded.filters, sort = excluded.sort"
+        } else {
+            "DO NOTHING"
+        };
+        let result = sqlx::query(&format!(
+            "INSERT INTO saved_searches (name, query, filters, sort) VALUES (?, ?, ?, ?) ON CONFLICT(name) {}",
+            conflict
+        ))
+        .bind(&saved.name)
+        .bind(&saved.query)
+        .bind(serde_json::to_string(&saved.filters)?)
+        .bind(serde_json::to_string(&saved.sort)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Replace the saved search called `name` with `saved`, which may rename it; returns
+    /// whether there was one
+    pub async fn update_saved_search(&self, name: &str, saved: &SavedSearch) -> Result<bool> {
+        let result = sqlx::query("UPDATE saved_searches SET name = ?, query = ?, filters = ?, sort = ? WHERE name = ?")
+            .bind(&saved.name)
+            .bind(&saved.query)
+            .bind(serde_json::to_string(&saved.filters)?)
+            .bind(serde_json::to_string(&saved.sort)?)
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns whether there was a saved search called `name`
+    pub async fn delete_saved_search(&self, name: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM saved_searches WHERE name = ?")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Names and paths of the imported file lists
+    pub async fn file_lists(&self) -> Result<Vec<(String, String)>> {
+        let lists = sqlx::query_as("SELECT name, path FROM file_lists ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(lists)
+    }
+
+    /// Remember the file list `name` as loaded from `path`, replacing one of the same name
+    pub async fn record_file_list(&self, name: &str, path: &str) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO file_lists (name, path) VALUES (?, ?) \
+             ON CONFLICT(name) DO UPDATE SET name = excluded.name, path = excluded.path",
+        )
+        .bind(name)
+        .bind(path)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns whether there was a file list called `name`
+    pub async fn delete_file_list(&self, name: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM file_lists WHERE name = ?")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Record a file operation in the journal, keeping only the latest `keep`; returns its id
+    pub async fn record_file_operation(
+        &self,
+        kind: FileOperationKind,
+        steps: &[FileOperationStep],
+        keep: usize,
+    ) -> Result<i64> {
+        let id = sqlx::query("INSERT INTO file_operations (kind, steps, performed_at) VALUES (?, ?, ?)")
+            .bind(serde_json::to_value(kind)?.as_str().map(str::to_string))
+            .bind(serde_json::to_string(steps)?)
+            .bind(Utc::now().to_rfc3339())
+            .execute(&self.pool)
+            .await?
+            .last_insert_rowid();
+
+        sqlx::query(
+            "DELETE FROM file_operations WHERE id NOT IN \
+             (SELECT id FROM file_operations ORDER BY id DESC LIMIT ?)",
+        )
+        .bind(keep as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(id)
+    }
+
+    /// Up to `limit` journalled file operations, latest first
+    pub async fn file_operations(&self, limit: usize) -> Result<Vec<FileOperationRecord>> {
+        let rows = sqlx::query("SELECT id, kind, steps, performed_at FROM file_operations ORDER BY id DESC LIMIT ?")
+            .bind(limit.min(i64::MAX as usize) as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(FileOperationRecord {
+                    id: row.try_get("id")?,
+                    kind: serde_json::from_value(serde_json::Value::String(row.try_get("kind")?))?,
+                    steps: serde_json::from_str(row.try_get("steps")?)?,
+                    performed_at: DateTime::parse_from_rfc3339(row.try_get("performed_at")?)?.with_timezone(&Utc),
+                })
+            })
+            .collect()
+    }
+
+    /// Returns whether the operation was in the journal
+    pub async fn delete_file_operation(&self, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM file_operations WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Distinct names starting with `prefix` (lowercase), with how many entries share each,
+    /// most common first. Only the first matches in name order are counted.
+    pub async fn name_completions(&self, prefix: &str, limit: usize) -> Result<Vec<(String, u64)>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT MIN(name) AS name, COUNT(*) AS uses FROM (
+                SELECT name FROM file_entries
+                WHERE LOWER(name) >= ? AND LOWER(name) < ?
+                ORDER BY LOWER(name) LIMIT ?
+            )
+            GROUP BY LOWER(name)
+            ORDER BY uses DESC, LOWER(name)
+            LIMIT ?
+            "#,
+        )
+        .bind(prefix)
+        .bind(prefix_end(prefix))
+        .bind(COMPLETION_SCAN_LIMIT)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| Ok((row.try_get("name")?, row.try_get::<i64, _>("uses")? as u64)))
+            .collect()
+    }
+
+    /// Folders whose name starts with `prefix` (lowercase), directly inside `parent` when
+    /// given, with the number of entries inside each, fullest first
+    pub async fn folder_completions(
+        &self,
+        parent: Option<&str>,
+        prefix: &str,
+        limit: usize,
+    ) -> Result<Vec<(String, u64)>> {
+        let mut builder = QueryBuilder::<Sqlite>::new(
+            "SELECT d.path, (SELECT COUNT(*) FROM file_entries c WHERE c.parent = d.path) AS uses FROM (\
+             SELECT path FROM file_entries WHERE is_directory = 1",
+        );
+        if let Some(parent) = parent {
+            builder.push(" AND parent = ").push_bind(parent.to_string());
+        }
+        builder
+            .push(" AND LOWER(name) >= ")
+            .push_bind(prefix.to_string())
+            .push(" AND LOWER(name) < ")
+            .push_bind(prefix_end(prefix))
+            .push(" ORDER BY LOWER(name) LIMIT ")
+            .push_bind(COMPLETION_SCAN_LIMIT)
+            .push(") d ORDER BY uses DESC, d.path LIMIT ")
+            .push_bind(limit as i64);
+
+        let rows = builder.build().fetch_all(&self.pool).await?;
+        rows.iter()
+            .map(|row| Ok((row.try_get("path")?, row.try_get::<i64, _>("uses")? as u64)))
+            .collect()
+    }
+
+    /// Every extension in the index with its number of files, most common first
+    pub async fn extension_counts(&self) -> Result<Vec<(String, u64)>> {
+        let rows = sqlx::query(
+            "SELECT extension, COUNT(*) AS uses FROM file_entries WHERE extension IS NOT NULL \
+             GROUP BY extension ORDER BY uses DESC, extension",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| Ok((row.try_get("extension")?, row.try_get::<i64, _>("uses")? as u64)))
+            .collect()
+    }
+
+    pub async fn get_stats(&self) -> Result<IndexStats> {
         let row = sqlx::query(
             r#"
             SELECT 
@@ -170,18 +1080,616 @@ impl Database {
         .fetch_one(&self.pool)
         .await?;
 
-        Ok(crate::types::IndexStats {
+        let roots = sqlx::query("SELECT path, last_indexed FROM indexed_roots ORDER BY path")
+            .fetch_all(&self.pool)
+            .await?;
+        let last_index_time = roots
+            .iter()
+            .filter_map(|root| DateTime::parse_from_rfc3339(root.get("last_indexed")).ok())
+            .map(|time| time.with_timezone(&Utc))
+            .max()
+            .unwrap_or_else(Utc::now);
+
+        Ok(IndexStats {
             total_files: row.get::<i64, _>("file_count") as u64,
             total_directories: row.get::<i64, _>("dir_count") as u64,
             index_size_bytes: row.get::<i64, _>("total_size") as u64,
-            last_index_time: Utc::now(), // This should be updated to reflect actual index time
-            indexed_paths: Vec::new(), // TODO: Store and retrieve indexed paths
+            last_index_time,
+            indexed_paths: roots.iter().map(|root| root.get("path")).collect(),
         })
     }
 
     pub async fn clear_index(&self) -> Result<()> {
         sqlx::query("DELETE FROM file_entries").execute(&self.pool).await?;
-        sqlx::query("DELETE FROM file_search").execute(&self.pool).await?;
+        sqlx::query("INSERT INTO file_search(file_search) VALUES ('delete-all')").execute(&self.pool).await?;
+        sqlx::query("DELETE FROM file_contents").execute(&self.pool).await?;
+        sqlx::query("DELETE FROM content_search").execute(&self.pool).await?;
+        self.changed();
         Ok(())
     }
 }
+
+/// Smallest string greater than every string starting with `prefix`, for index range scans
+fn prefix_end(prefix: &str) -> String {
+    format!("{}{}", prefix, char::MAX)
+}
+
+#[async_trait]
+impl SearchBackend for Database {
+    fn name(&self) -> &'static str {
+        "index"
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities {
+            regex: true,
+            content_search: true,
+            live_updates: true,
+            exact_total_count: true,
+        }
+    }
+
+    fn generation(&self) -> Option<u64> {
+        Some(Database::generation(self))
+    }
+
+    async fn search(&self, query: &SearchQuery) -> Result<SearchResult> {
+        Database::search(self, query).await
+    }
+
+    async fn stats(&self) -> Result<IndexStats> {
+        self.get_stats().await
+    }
+
+    async fn health(&self) -> BackendHealth {
+        match sqlx::query("SELECT COUNT(*) FROM indexed_roots").fetch_one(&self.pool).await {
+            Ok(row) if row.get::<i64, _>(0) == 0 => {
+                BackendHealth::Degraded("Initial indexing has not completed".to_string())
+            }
+            Ok(_) => BackendHealth::Healthy,
+            Err(e) => BackendHealth::Unavailable(e.to_string()),
+        }
+    }
+}
+
+/// Translate a compiled query into a SQL condition on `file_entries`
+fn push_query(builder: &mut QueryBuilder<'_, Sqlite>, compiled: &CompiledQuery) {
+    push_expr(builder, &compiled.expr, compiled.case_sensitive);
+
+    if !compiled.include_hidden {
+        builder.push(" AND name NOT LIKE '.%' AND (attributes & 2) = 0");
+    }
+}
+
+fn push_expr(builder: &mut QueryBuilder<'_, Sqlite>, expr: &Expr, case_sensitive: bool) {
+    match expr {
+        Expr::And(children) | Expr::Or(children) if children.is_empty() => {
+            builder.push(if matches!(expr, Expr::And(_)) { "1 = 1" } else { "1 = 0" });
+        }
+        Expr::And(children) | Expr::Or(children) => {
+            let separator = if matches!(expr, Expr::And(_)) { " AND " } else { " OR " };
+            builder.push("(");
+            for (i, child) in children.iter().enumerate() {
+                if i > 0 {
+                    builder.push(separator);
+                }
+                push_expr(builder, child, case_sensitive);
+            }
+            builder.push(")");
+        }
+        Expr::Not(child) => {
+            builder.push("NOT ");
+            push_expr(builder, child, case_sensitive);
+        }
+        Expr::Term(term) => push_term(builder, term, case_sensitive),
+    }
+}
+
+fn push_term(builder: &mut QueryBuilder<'_, Sqlite>, term: &Term, case_sensitive: bool) {
+    match term {
+        Term::Text(pattern) => {
+            let column = if pattern.text.contains(['/', '\\']) { "path" } else { "name" };
+            push_pattern(builder, column, pattern, case_sensitive);
+        }
+        Term::Path(pattern) => push_pattern(builder, "path", pattern, case_sensitive),
+        Term::Fuzzy(pattern) => {
+            let column = if pattern.text.contains(['/', '\\']) { "path" } else { "name" };
+            push_subsequence(builder, column, pattern, case_sensitive);
+        }
+        Term::Parent(parent) => {
+            let parent = parent.trim_end_matches(['/', '\\']).to_string();
+            if case_sensitive {
+                builder.push("parent = ");
+                builder.push_bind(parent);
+            } else if let Some(folded) = folded_column("parent", &parent) {
+                builder.push(format!("{} = ", folded));
+                builder.push_bind(parent.to_lowercase());
+            } else {
+                builder.push("parent LIKE ");
+                builder.push_bind(escape_like(&parent));
+                builder.push(" ESCAPE '\\'");
+            }
+        }
+        Term::Extension(extensions) => {
+            builder.push("extension IN (");
+            let mut separated = builder.separated(", ");
+            for extension in extensions {
+                separated.push_bind(extension.clone());
+            }
+            separated.push_unseparated(")");
+        }
+        Term::Size(range) => {
+            builder.push("(is_directory = 0");
+            if let Some(min) = range.min {
+                builder.push(" AND size >= ");
+                builder.push_bind(min);
+            }
+            if let Some(max) = range.max {
+                builder.push(" AND size <= ");
+                builder.push_bind(max);
+            }
+            builder.push(")");
+        }
+        Term::DateModified(range) => push_date_range(builder, "modified", range),
+        Term::DateCreated(range) => push_date_range(builder, "created", range),
+        Term::FolderOnly => {
+            builder.push("is_directory = 1");
+        }
+        Term::FileOnly => {
+            builder.push("is_directory = 0");
+        }
+        Term::Content(text) => {
+            // The unescaped LIKE is a superset that the trigram index can answer; `%` and `_`
+            // in the text and case-sensitive queries are then checked exactly
+            builder.push(
+                "path IN (SELECT c.path FROM file_contents c JOIN content_search s ON s.rowid = c.id \
+                 WHERE s.content LIKE ",
+            );
+            builder.push_bind(format!("%{}%", text));
+            if case_sensitive {
+                builder.push(" AND instr(s.content, ");
+                builder.push_bind(text.clone());
+                builder.push(") > 0");
+            } else if text.contains(['%', '_']) {
+                builder.push(" AND instr(lower(s.content), ");
+                builder.push_bind(text.to_lowercase());
+                builder.push(") > 0");
+            }
+            builder.push(")");
+        }
+        Term::Hash(prefix) => {
+            builder.push("(hash >= ");
+            builder.push_bind(prefix.clone());
+            builder.push(" AND hash < ");
+            builder.push_bind(prefix_end(prefix));
+            builder.push(")");
+        }
+    }
+}
+
+/// Narrows fuzzy candidates to entries containing the pattern's characters in order; the
+/// matches are then scored in Rust
+fn push_subsequence(builder: &mut QueryBuilder<'_, Sqlite>, column: &str, pattern: &TextPattern, case_sensitive: bool) {
+    if let Some(folded) = folded_column(column, &pattern.text).filter(|_| !case_sensitive) {
+        return push_subsequence(builder, &folded, &TextPattern::new(&pattern.text.to_lowercase()), true);
+    }
+    let chars = pattern.text.chars();
+    if case_sensitive {
+        let glob: String = chars
+            .map(|c| match c {
+                '*' | '?' | '[' => format!("[{}]*", c),
+                c => format!("{}*", c),
+            })
+            .collect();
+        builder.push(format!("{} GLOB ", column));
+        builder.push_bind(format!("*{}", glob));
+    } else {
+        let like: String = chars.map(|c| format!("{}%", escape_like(&c.to_string()))).collect();
+        builder.push(format!("{} LIKE ", column));
+        builder.push_bind(format!("%{}", like));
+        builder.push(" ESCAPE '\\'");
+    }
+}
+
+/// SQL function that lowercases like `str::to_lowercase`. LIKE only folds ASCII, so
+/// patterns with other letters are matched against `fold(column)` instead.
+const FOLD_FUNCTION: &str = "fold";
+
+async fn register_fold(conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    unsafe extern "C" fn fold(
+        context: *mut libsqlite3_sys::sqlite3_context,
+        _argc: c_int,
+        argv: *mut *mut libsqlite3_sys::sqlite3_value,
+    ) {
+        let value = *argv;
+        let text = libsqlite3_sys::sqlite3_value_text(value);
+        if text.is_null() {
+            libsqlite3_sys::sqlite3_result_null(context);
+            return;
+        }
+        let len = libsqlite3_sys::sqlite3_value_bytes(value) as usize;
+        let folded = String::from_utf8_lossy(std::slice::from_raw_parts(text, len)).to_lowercase();
+        libsqlite3_sys::sqlite3_result_text(
+            context,
+            folded.as_ptr().cast(),
+            folded.len() as c_int,
+            libsqlite3_sys::SQLITE_TRANSIENT(),
+        );
+    }
+
+    let name = CString::new(FOLD_FUNCTION).expect("function name has no NUL");
+    let mut handle = conn.lock_handle().await?;
+    // SAFETY: the handle is locked for the call, and `fold` reads its one argument as SQLite
+    // documents and has SQLite copy the result before `folded` is dropped
+    let status = unsafe {
+        libsqlite3_sys::sqlite3_create_function_v2(
+            handle.as_raw_handle().as_ptr(),
+            name.as_ptr(),
+            1,
+            libsqlite3_sys::SQLITE_UTF8 | libsqlite3_sys::SQLITE_DETERMINISTIC,
+            std::ptr::null_mut(),
+            Some(fold),
+            None,
+            None,
+            None,
+        )
+    };
+    match status {
+        libsqlite3_sys::SQLITE_OK => Ok(()),
+        status => Err(sqlx::Error::Protocol(format!(
+            "Could not register {}(): SQLite error {}",
+            FOLD_FUNCTION, status
+        ))),
+    }
+}
+
+/// Case-insensitive text is matched with LIKE when it is ASCII, the common case that LIKE
+/// folds like the matcher, and otherwise with `fold(column)` against the lowercased pattern
+fn folded_column(column: &str, text: &str) -> Option<String> {
+    (!text.is_ascii()).then(|| format!("{}({})", FOLD_FUNCTION, column))
+}
+
+/// Remove the usage of those `ids` that no entry has any more. Inode ids are reused, so
+/// usage must not outlive its file, but a file moved while unwatched is briefly stored at
+/// both paths and keeps its usage when the old one goes.
+async fn delete_orphaned_usage(conn: &mut SqliteConnection, ids: &[String]) -> Result<()> {
+    for batch in ids.chunks(USAGE_BATCH_SIZE) {
+        let mut builder = QueryBuilder::new(
+            "DELETE FROM file_usage WHERE id NOT IN (SELECT id FROM file_entries) AND id IN (",
+        );
+        let mut separated = builder.separated(", ");
+        for id in batch {
+            separated.push_bind(id.as_str());
+        }
+        separated.push_unseparated(")");
+        builder.build().execute(&mut *conn).await?;
+    }
+    Ok(())
+}
+
+fn push_pattern(builder: &mut QueryBuilder<'_, Sqlite>, column: &str, pattern: &TextPattern, case_sensitive: bool) {
+    if let Some(folded) = folded_column(column, &pattern.text).filter(|_| !case_sensitive) {
+        return push_pattern(builder, &folded, &TextPattern::new(&pattern.text.to_lowercase()), true);
+    }
+    match (pattern.wildcard, case_sensitive) {
+        (false, false) => {
+            builder.push(format!("{} LIKE ", column));
+            builder.push_bind(format!("%{}%", escape_like(&pattern.text)));
+            builder.push(" ESCAPE '\\'");
+        }
+        (false, true) => {
+            builder.push(format!("instr({}, ", column));
+            builder.push_bind(pattern.text.clone());
+            builder.push(") > 0");
+        }
+        (true, false) => {
+            let like = escape_like(&pattern.text).replace('*', "%").replace('?', "_");
+            builder.push(format!("{} LIKE ", column));
+            builder.push_bind(like);
+            builder.push(" ESCAPE '\\'");
+        }
+        (true, true) => {
+            builder.push(format!("{} GLOB ", column));
+            builder.push_bind(pattern.text.replace('[', "[[]"));
+        }
+    }
+}
+
+/// `ORDER BY` clause that orders rows exactly like `Sorter::compare`
+fn push_order_by(builder: &mut QueryBuilder<'_, Sqlite>, sorter: &Sorter) {
+    let sort = sorter.sort();
+    let direction = if sort.descending { "DESC" } else { "ASC" };
+    let natural = |column: &str| format!("{} COLLATE {} {}", column, NATURAL_COLLATION, direction);
+
+    builder.push(" ORDER BY ");
+    match (sort.field, sorter.needle()) {
+        (SortField::Name | SortField::Path, _) | (SortField::Relevance, None) => {}
+        (SortField::Size, _) => {
+            builder.push(format!("size {}, ", direction));
+        }
+        (SortField::Extension, _) => {
+            builder.push(format!("{}, ", natural("extension")));
+        }
+        // RFC 3339 timestamps in UTC sort chronologically as text
+        (SortField::DateModified, _) => {
+            builder.push(format!("modified {}, ", direction));
+        }
+        (SortField::DateCreated, _) => {
+            builder.push(format!("created {}, ", direction));
+        }
+        (SortField::Relevance, Some(needle)) => {
+            // Mirrors `relevance_rank`; SQLite's LOWER only folds ASCII, as does the needle
+            builder.push("CASE WHEN LOWER(name) = ");
+            builder.push_bind(needle.to_string());
+            builder.push(" THEN 0 WHEN substr(LOWER(name), 1, length(");
+            builder.push_bind(needle.to_string());
+            builder.push(") + 1) = ");
+            builder.push_bind(format!("{}.", needle));
+            builder.push(" THEN 1 WHEN substr(LOWER(name), 1, length(");
+            builder.push_bind(needle.to_string());
+            builder.push(")) = ");
+            builder.push_bind(needle.to_string());
+            builder.push(" THEN 2 WHEN instr(LOWER(name), ");
+            builder.push_bind(needle.to_string());
+            builder.push(format!(") > 0 THEN 3 ELSE 4 END {}, length(name) {}, ", direction, direction));
+        }
+    }
+
+    if sort.field != SortField::Path {
+        builder.push(format!("{}, ", natural("name")));
+    }
+    builder.push(format!("{}, path {}", natural("path"), direction));
+}
+
+// Timestamps are stored as RFC 3339 in UTC, so they compare lexicographically
+fn push_date_range(builder: &mut QueryBuilder<'_, Sqlite>, column: &str, range: &DateRange) {
+    builder.push("(1 = 1");
+    if let Some(start) = range.start {
+        builder.push(format!(" AND {} >= ", column));
+        builder.push_bind(start.to_rfc3339());
+    }
+    if let Some(end) = range.end {
+        builder.push(format!(" AND {} < ", column));
+        builder.push_bind(end.to_rfc3339());
+    }
+    builder.push(")");
+}
+
+/// Inserts an entry, or replaces the entry already stored at its path
+fn upsert_entry(entry: &FileEntry) -> Query<'_, Sqlite, SqliteArguments<'_>> {
+    sqlx::query(
+        r#"
+        INSERT INTO file_entries
+        (id, name, path, parent, size, modified, created, is_directory, extension, attributes)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT(path) DO UPDATE SET
+            id = excluded.id,
+            name = excluded.name,
+            parent = excluded.parent,
+            size = excluded.size,
+            modified = excluded.modified,
+            created = excluded.created,
+            is_directory = excluded.is_directory,
+            extension = excluded.extension,
+            attributes = excluded.attributes
+        "#,
+    )
+    .bind(&entry.id)
+    .bind(&entry.name)
+    .bind(&entry.path)
+    .bind(parent_of(&entry.path, &entry.name).to_string())
+    .bind(entry.size)
+    .bind(entry.modified.to_rfc3339())
+    .bind(entry.created.to_rfc3339())
+    .bind(entry.is_directory)
+    .bind(&entry.extension)
+    .bind(entry.attributes)
+}
+
+/// Bounds of the paths below the directory `prefix`, for a range scan on the path index
+fn descendant_range(prefix: &str) -> (String, String) {
+    let prefix = prefix.trim_end_matches(['/', '\\']);
+    let separator = if prefix.contains('\\') && !prefix.contains('/') { '\\' } else { '/' };
+    // The character after the separator sorts after every path that continues with it
+    let after_separator = char::from(separator as u8 + 1);
+    (format!("{}{}", prefix, separator), format!("{}{}", prefix, after_separator))
+}
+
+fn escape_like(term: &str) -> String {
+    term.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn row_to_entry(row: &SqliteRow) -> Result<FileEntry> {
+    let modified: String = row.try_get("modified")?;
+    let created: String = row.try_get("created")?;
+
+    Ok(FileEntry {
+        id: row.try_get("id")?,
+        name: row.try_get("name")?,
+        path: row.try_get("path")?,
+        size: row.try_get("size")?,
+        modified: DateTime::parse_from_rfc3339(&modified)?.with_timezone(&Utc),
+        created: DateTime::parse_from_rfc3339(&created)?.with_timezone(&Utc),
+        is_directory: row.try_get("is_directory")?,
+        extension: row.try_get("extension")?,
+        attributes: row.try_get("attributes")?,
+        score: None,
+        source: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{entry, folder, query};
+
+    async fn database(entries: &[FileEntry]) -> (tempfile::TempDir, Database) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::new(&dir.path().join("index.db")).await.unwrap();
+        db.batch_insert_file_entries(entries).await.unwrap();
+        (dir, db)
+    }
+
+    /// Three entries named config and one whose name holds LIKE's wildcards
+    async fn configs() -> (tempfile::TempDir, Database) {
+        database(&[
+            FileEntry { size: 120, ..entry("/src/config_loader.rs") },
+            FileEntry { size: 4096, ..entry("/etc/config.json") },
+            folder("/etc/config"),
+            FileEntry { size: 1, ..entry("/tmp/100%_done.txt") },
+        ])
+        .await
+    }
+
+    fn filtered(text: &str, filters: SearchFilters) -> SearchQuery {
+        SearchQuery { filters, ..query(text) }
+    }
+
+    #[tokio::test]
+    async fn test_search_applies_terms_and_filters() {
+        let (_dir, db) = configs().await;
+
+        let result = db.search(&query("CONFIG")).await.unwrap();
+        assert_eq!(result.total_count, 3);
+
+        let files_only = SearchFilters { files_only: true, size_min: Some(1000), ..Default::default() };
+        let result = db.search(&filtered("config", files_only)).await.unwrap();
+        assert_eq!(result.entries.len(), 1);
+        assert_eq!(result.entries[0].name, "config.json");
+
+        let by_type = SearchFilters { file_types: vec![".RS".to_string()], ..Default::default() };
+        let result = db.search(&filtered("loader config", by_type)).await.unwrap();
+        assert_eq!(result.entries[0].path, "/src/config_loader.rs");
+
+        let result = db.search(&query("0%_")).await.unwrap();
+        assert_eq!(result.total_count, 1);
+
+        let result = db.search(&query("<*.json|*.rs> !loader")).await.unwrap();
+        assert_eq!(result.entries.len(), 1);
+        assert_eq!(result.entries[0].name, "config.json");
+
+        let result = db.search(&query("parent:/ETC/ size:<1kb")).await.unwrap();
+        assert_eq!(result.total_count, 0);
+        let result = db.search(&query("parent:/ETC/ folder:")).await.unwrap();
+        assert_eq!(result.entries[0].path, "/etc/config");
+
+        let regex = SearchFilters { use_regex: true, ..Default::default() };
+        let result = db.search(&filtered(r"^config\.\w+$", regex)).await.unwrap();
+        assert_eq!(result.total_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_fuzzy_matches_are_scored_and_ranked() {
+        let (_dir, db) = configs().await;
+        let fuzzy = SearchFilters { fuzzy: true, ..Default::default() };
+
+        let mut query = filtered("cfg", fuzzy.clone());
+        query.sort.field = SortField::Relevance;
+        let result = db.search(&query).await.unwrap();
+        assert_eq!(result.total_count, 3);
+        assert_eq!(result.entries[0].name, "config");
+        assert!(result.entries.iter().all(|e| e.score.is_some()));
+
+        let result = db.search(&filtered("cfgldr", fuzzy)).await.unwrap();
+        assert_eq!(result.entries[0].name, "config_loader.rs");
+    }
+
+    #[tokio::test]
+    async fn test_case_insensitive_search_folds_unicode_like_the_matcher() {
+        let entries = [entry("/Recettes/Éclair.txt"), entry("/Straße/STRASSE.md"), folder("/Ünïcode")];
+        let (_dir, db) = database(&entries).await;
+
+        for (text, expected) in [
+            ("éclair", 1),
+            ("ÉCLAIR", 1),
+            ("recettes/é", 1),
+            ("éc*.TXT", 1),
+            ("straße/", 1),
+            ("STRAẞE", 0),
+            ("üNÏ", 1),
+            ("parent:/STRAẞE", 1),
+            ("parent:/recettes/", 1),
+        ] {
+            let query = query(text);
+            let compiled = CompiledQuery::compile(&query).unwrap();
+            let matched = entries.iter().filter(|entry| compiled.matches(entry)).count();
+            assert_eq!(matched, expected, "matcher on {}", text);
+            assert_eq!(db.search(&query).await.unwrap().total_count, expected as u64, "index on {}", text);
+        }
+
+        let fuzzy = filtered("ÉCL", SearchFilters { fuzzy: true, ..Default::default() });
+        assert_eq!(db.search(&fuzzy).await.unwrap().total_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_deletes_keep_full_text_index_consistent() {
+        let (_dir, db) = database(&[
+            folder("/data"),
+            entry("/data/a.txt"),
+            folder("/data/sub"),
+            entry("/data/sub/b.txt"),
+            entry("/data2/c.txt"),
+        ])
+        .await;
+
+        assert_eq!(db.delete_by_prefix("/data/").await.unwrap(), 3);
+        assert_eq!(db.delete_by_path("/data").await.unwrap(), 1);
+        assert_eq!(db.delete_by_path("/data").await.unwrap(), 0);
+
+        let result = db.search(&query("txt")).await.unwrap();
+        assert_eq!(result.entries.len(), 1);
+        assert_eq!(result.entries[0].path, "/data2/c.txt");
+
+        // Compares the FTS index with file_entries and fails on any stale row
+        sqlx::query("INSERT INTO file_search(file_search, rank) VALUES ('integrity-check', 1)")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_removed_entries_take_their_usage_along() {
+        let entries = [
+            entry("/data/a.txt"),
+            entry("/data/sub/b.txt"),
+            entry("/data/c.txt"),
+            entry("/data/d.txt"),
+        ];
+        let (_dir, db) = database(&entries).await;
+        let ids: Vec<&str> = entries.iter().map(|entry| entry.id.as_str()).collect();
+        for id in &ids {
+            db.record_file_open(id).await.unwrap();
+        }
+
+        db.delete_by_path("/data/a.txt").await.unwrap();
+        db.delete_by_prefix("/data/sub").await.unwrap();
+        // d.txt replaces c.txt, so c.txt's usage goes and d.txt's follows it to the new path id
+        db.record_file_open(ids[3]).await.unwrap();
+        db.rename_path("/data/d.txt", "/data/c.txt").await.unwrap();
+
+        let usage = db.file_usage(&ids).await.unwrap();
+        let remaining: Vec<_> = usage.iter().map(|(id, usage)| (id.as_str(), usage.open_count)).collect();
+        assert_eq!(remaining, vec![(ids[2], 2)]);
+    }
+
+    #[tokio::test]
+    async fn test_usage_stays_while_another_path_has_the_id() {
+        // A file moved while unwatched, already indexed at its new path under its inode id
+        let old = FileEntry { id: "inode".to_string(), ..entry("/data/old.txt") };
+        let new = FileEntry { id: "inode".to_string(), ..entry("/data/new.txt") };
+        let (_dir, db) = database(&[old, new]).await;
+        db.record_file_open("inode").await.unwrap();
+
+        db.rename_path("/data/old.txt", "/data/new.txt").await.unwrap();
+        assert_eq!(db.file_usage(&["inode"]).await.unwrap().len(), 1);
+        db.batch_insert_file_entries(&[FileEntry { id: "inode".to_string(), ..entry("/data/copy.txt") }])
+            .await
+            .unwrap();
+        db.delete_by_path("/data/new.txt").await.unwrap();
+        assert_eq!(db.file_usage(&["inode"]).await.unwrap().len(), 1);
+        db.delete_by_prefix("/data").await.unwrap();
+        assert!(db.file_usage(&["inode"]).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,256 @@
+//! Finding files with identical contents. Files are grouped by size, then by a hash of
+//! their first bytes, and only files that still match are hashed in full.
+
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, Metadata};
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use crate::stream::CancelHandle;
+use crate::types::{DuplicateGroup, DuplicatePhase, DuplicateProgress, DuplicateReport, FileEntry, SearchError};
+
+/// Bytes hashed from the start of each file before deciding whether to hash all of it
+const PARTIAL_HASH_LEN: u64 = 64 * 1024;
+/// Least time between two progress reports within a phase
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Device and inode of a file, on Unix
+type Inode = Option<(u64, u64)>;
+
+/// A file's contents as hashed, with what identifies the file on disk
+struct Hashed {
+    entry: FileEntry,
+    hash: String,
+    /// Device and inode on Unix, so hard links are not counted as wasted space
+    inode: Inode,
+}
+
+/// Group the files among `entries` of at least `min_size` bytes by their contents.
+/// Directories, files that cannot be read and files whose size no longer matches their
+/// entry are left out. Fails with `SearchError::Cancelled` once `cancel` is cancelled.
+pub async fn find_duplicates(
+    entries: Vec<FileEntry>,
+    min_size: u64,
+    mut progress: impl FnMut(DuplicateProgress),
+    cancel: &CancelHandle,
+) -> Result<DuplicateReport> {
+    let start_time = Instant::now();
+    let files_scanned = entries.len() as u64;
+
+    let mut paths = HashSet::new();
+    let mut by_size: HashMap<u64, Vec<FileEntry>> = HashMap::new();
+    for entry in entries {
+        if !entry.is_directory && entry.size as u64 >= min_size && paths.insert(entry.path.clone()) {
+            by_size.entry(entry.size as u64).or_default().push(entry);
+        }
+    }
+    let candidates: Vec<FileEntry> = by_size.into_values().filter(|files| files.len() > 1).flatten().collect();
+    debug!("{} files share their size with another file", candidates.len());
+
+    let mut skipped = 0;
+    let partial = hash_all(candidates, DuplicatePhase::PartialHash, &mut progress, cancel, &mut skipped).await?;
+
+    // Files no longer than the partial hash were hashed whole already
+    let (whole, rest): (Vec<Hashed>, Vec<Hashed>) = matching(partial)
+        .into_iter()
+        .partition(|hashed| hashed.entry.size as u64 <= PARTIAL_HASH_LEN);
+    let rest = rest.into_iter().map(|hashed| hashed.entry).collect();
+    let full = hash_all(rest, DuplicatePhase::FullHash, &mut progress, cancel, &mut skipped).await?;
+
+    let mut groups: HashMap<(u64, String), Vec<Hashed>> = HashMap::new();
+    for hashed in whole.into_iter().chain(full) {
+        groups.entry((hashed.entry.size as u64, hashed.hash.clone())).or_default().push(hashed);
+    }
+
+    let mut groups: Vec<DuplicateGroup> = groups
+        .into_iter()
+        .filter_map(|((size, hash), files)| {
+            let copies = files
+                .iter()
+                .map(|hashed| hashed.inode.ok_or(hashed.entry.path.as_str()))
+                .collect::<HashSet<_>>()
+                .len() as u64;
+            let mut files: Vec<FileEntry> = files.into_iter().map(|hashed| hashed.entry).collect();
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+            (copies > 1).then(|| DuplicateGroup { size, hash, files, wasted_bytes: size * (copies - 1) })
+        })
+        .collect();
+    groups.sort_by(|a, b| {
+        b.wasted_bytes
+            .cmp(&a.wasted_bytes)
+            .then_with(|| a.files[0].path.cmp(&b.files[0].path))
+    });
+
+    Ok(DuplicateReport {
+        wasted_bytes: groups.iter().map(|group| group.wasted_bytes).sum(),
+        groups,
+        files_scanned,
+        files_skipped: skipped,
+        query_time_ms: start_time.elapsed().as_millis() as u64,
+    })
+}
+
+/// Hash each of `files` for `phase`, counting the files that could not be hashed
+async fn hash_all(
+    files: Vec<FileEntry>,
+    phase: DuplicatePhase,
+    progress: &mut impl FnMut(DuplicateProgress),
+    cancel: &CancelHandle,
+    skipped: &mut u64,
+) -> Result<Vec<Hashed>> {
+    let len = match phase {
+        DuplicatePhase::PartialHash => Some(PARTIAL_HASH_LEN),
+        DuplicatePhase::FullHash => None,
+    };
+    let mut report = DuplicateProgress {
+        phase,
+        files_done: 0,
+        files_total: files.len() as u64,
+        bytes_done: 0,
+        bytes_total: files.iter().map(|entry| hashed_len(entry, len)).sum(),
+    };
+    progress(report);
+    let mut last_report = Instant::now();
+
+    let mut hashed = Vec::with_capacity(files.len());
+    for entry in files {
+        if cancel.is_cancelled() {
+            return Err(SearchError::Cancelled.into());
+        }
+
+        report.files_done += 1;
+        report.bytes_done += hashed_len(&entry, len);
+        let path = entry.path.clone();
+        let size = entry.size as u64;
+        match tokio::task::spawn_blocking(move || hash_file(Path::new(&path), size, len)).await? {
+            Ok(Some((hash, inode))) => hashed.push(Hashed { entry, hash, inode }),
+            Ok(None) => *skipped += 1,
+            Err(e) => {
+                debug!("Failed to hash '{}': {}", entry.path, e);
+                *skipped += 1;
+            }
+        }
+
+        if report.files_done == report.files_total || last_report.elapsed() >= PROGRESS_INTERVAL {
+            progress(report);
+            last_report = Instant::now();
+        }
+    }
+
+    Ok(hashed)
+}
+
+/// Only the files whose size and hash are shared with another file
+fn matching(hashed: Vec<Hashed>) -> Vec<Hashed> {
+    let mut groups: HashMap<(i64, String), Vec<Hashed>> = HashMap::new();
+    for file in hashed {
+        groups.entry((file.entry.size, file.hash.clone())).or_default().push(file);
+    }
+    groups.into_values().filter(|files| files.len() > 1).flatten().collect()
+}
+
+fn hashed_len(entry: &FileEntry, len: Option<u64>) -> u64 {
+    let size = entry.size as u64;
+    len.map_or(size, |len| size.min(len))
+}
+
+/// Hash of the first `len` bytes of the file at `path`, or all of it, with its inode.
+/// `None` if the file is no longer `size` bytes long.
+fn hash_file(path: &Path, size: u64, len: Option<u64>) -> io::Result<Option<(String, Inode)>> {
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+    if metadata.len() != size {
+        return Ok(None);
+    }
+
+    let mut hasher = blake3::Hasher::new();
+    match len {
+        Some(len) => io::copy(&mut (&mut file).take(len), &mut hasher)?,
+        None => io::copy(&mut file, &mut hasher)?,
+    };
+    Ok(Some((hasher.finalize().to_hex().to_string(), inode(&metadata))))
+}
+
+#[cfg(unix)]
+fn inode(metadata: &Metadata) -> Inode {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &Metadata) -> Inode {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::on_disk;
+
+    const LARGE_LEN: usize = PARTIAL_HASH_LEN as usize + 10;
+
+    /// Three identical small files, two identical large ones and one that differs from them
+    /// only at the end, with a unique file, a near miss and two empty files
+    fn files() -> (tempfile::TempDir, Vec<FileEntry>) {
+        let dir = tempfile::tempdir().unwrap();
+        let large = vec![7u8; LARGE_LEN];
+        let mut large_changed_at_end = large.clone();
+        *large_changed_at_end.last_mut().unwrap() = 8;
+        let files: &[(&str, &[u8])] = &[
+            ("a.txt", b"same contents"),
+            ("b.txt", b"same contents"),
+            ("c.txt", b"same contents"),
+            ("d.txt", b"diff contents"),
+            ("unique.txt", b"no other file is this long"),
+            ("large1.bin", &large),
+            ("large2.bin", &large),
+            ("large3.bin", &large_changed_at_end),
+            ("empty1", b""),
+            ("empty2", b""),
+        ];
+        for (name, contents) in files {
+            std::fs::write(dir.path().join(name), contents).unwrap();
+        }
+        let entries = files.iter().map(|(name, _)| on_disk(&dir.path().join(name))).collect();
+        (dir, entries)
+    }
+
+    #[tokio::test]
+    async fn test_groups_identical_files_by_wasted_space() {
+        let (_dir, entries) = files();
+        let report = find_duplicates(entries, 1, |_| {}, &CancelHandle::default()).await.unwrap();
+
+        let names: Vec<Vec<&str>> = report
+            .groups
+            .iter()
+            .map(|group| group.files.iter().map(|file| file.name.as_str()).collect())
+            .collect();
+        assert_eq!(names, vec![vec!["large1.bin", "large2.bin"], vec!["a.txt", "b.txt", "c.txt"]]);
+        assert_eq!(report.wasted_bytes, LARGE_LEN as u64 + 2 * 13);
+        assert_eq!(report.files_scanned, 10);
+    }
+
+    #[tokio::test]
+    async fn test_only_files_sharing_their_first_bytes_are_hashed_in_full() {
+        let (_dir, entries) = files();
+        let mut phases = Vec::new();
+        find_duplicates(entries, 1, |progress| phases.push(progress), &CancelHandle::default())
+            .await
+            .unwrap();
+
+        let last = phases.last().unwrap();
+        assert_eq!((last.phase, last.files_done, last.files_total), (DuplicatePhase::FullHash, 3, 3));
+    }
+
+    #[tokio::test]
+    async fn test_a_cancelled_scan_fails() {
+        let (_dir, entries) = files();
+        let cancel = CancelHandle::default();
+        cancel.cancel();
+        let error = find_duplicates(entries, 1, |_| {}, &cancel).await.unwrap_err();
+        assert!(matches!(error.downcast_ref(), Some(SearchError::Cancelled)));
+    }
+}
@@ -1,11 +1,19 @@
 use anyhow::Result;
-use std::path::PathBuf;
+use async_trait::async_trait;
 use tracing::info;
-use crate::types::{FileEntry, SearchQuery, SearchResult, IndexStats};
+use crate::backend::{BackendCapabilities, BackendHealth, SearchBackend};
+use crate::types::{SearchQuery, SearchResult, IndexStats};
+#[cfg(windows)]
+use crate::{
+    search::query::CompiledQuery,
+    search::sort::{relevance_needle, Sorter},
+    types::{FileEntry, Sort, SortField},
+};
 
 #[cfg(windows)]
 use {
     std::ffi::{CString, OsString},
+    std::path::PathBuf,
     std::os::windows::ffi::OsStringExt,
     winapi::{
         shared::minwindef::*,
@@ -31,12 +39,24 @@ const EVERYTHING_REQUEST_DATE_MODIFIED: u32 = 0x00000040;
 #[cfg(windows)]
 const EVERYTHING_REQUEST_ATTRIBUTES: u32 = 0x00000080;
 
+// Each descending sort is the ascending value plus one
 #[cfg(windows)]
 const EVERYTHING_SORT_NAME_ASCENDING: u32 = 1;
 #[cfg(windows)]
+const EVERYTHING_SORT_PATH_ASCENDING: u32 = 3;
+#[cfg(windows)]
 const EVERYTHING_SORT_SIZE_ASCENDING: u32 = 5;
 #[cfg(windows)]
-const EVERYTHING_SORT_DATE_MODIFIED_ASCENDING: u32 = 9;
+const EVERYTHING_SORT_EXTENSION_ASCENDING: u32 = 7;
+#[cfg(windows)]
+const EVERYTHING_SORT_DATE_CREATED_ASCENDING: u32 = 11;
+#[cfg(windows)]
+const EVERYTHING_SORT_DATE_MODIFIED_ASCENDING: u32 = 13;
+
+/// Results requested from Everything for a fuzzy or relevance-sorted query, which are then
+/// scored and ranked here
+#[cfg(windows)]
+const EVERYTHING_RANK_CANDIDATES: u32 = 10_000;
 
 // Everything SDK function types
 #[cfg(windows)]
@@ -48,6 +68,8 @@ type EverythingQueryW = unsafe extern "stdcall" fn(BOOL) -> BOOL;
 #[cfg(windows)]
 type EverythingGetNumResults = unsafe extern "stdcall" fn() -> u32;
 #[cfg(windows)]
+type EverythingGetTotResults = unsafe extern "stdcall" fn() -> u32;
+#[cfg(windows)]
 type EverythingGetResultFullPathNameW = unsafe extern "stdcall" fn(u32, *mut u16, u32) -> u32;
 #[cfg(windows)]
 type EverythingGetResultSize = unsafe extern "stdcall" fn(u32, *mut i64) -> BOOL;
@@ -66,6 +88,8 @@ type EverythingSetSort = unsafe extern "stdcall" fn(u32);
 #[cfg(windows)]
 type EverythingSetMax = unsafe extern "stdcall" fn(u32);
 #[cfg(windows)]
+type EverythingSetOffset = unsafe extern "stdcall" fn(u32);
+#[cfg(windows)]
 type EverythingReset = unsafe extern "stdcall" fn();
 #[cfg(windows)]
 type EverythingGetLastError = unsafe extern "stdcall" fn() -> u32;
@@ -75,7 +99,7 @@ pub struct EverythingSDK {
     #[cfg(windows)]
     dll_handle: Option<HMODULE>,
     initialized: bool,
-    fallback_mode: bool, // Use when Everything DLL is not available
+    available: bool, // False when the Everything DLL could not be loaded
 }
 
 // Implement Send and Sync for EverythingSDK
@@ -88,7 +112,7 @@ impl EverythingSDK {
             #[cfg(windows)]
             dll_handle: None,
             initialized: false,
-            fallback_mode: false,
+            available: false,
         })
     }
 
@@ -100,19 +124,19 @@ impl EverythingSDK {
             match self.load_everything_dll() {
                 Ok(()) => {
                     info!("Everything SDK initialized successfully with DLL");
-                    self.fallback_mode = false;
+                    self.available = true;
                 }
                 Err(e) => {
-                    info!("Failed to load Everything DLL, using fallback mode: {}", e);
-                    self.fallback_mode = true;
+                    info!("Failed to load Everything DLL: {}", e);
+                    self.available = false;
                 }
             }
         }
         
         #[cfg(not(windows))]
         {
-            info!("Running on non-Windows platform, using fallback mode");
-            self.fallback_mode = true;
+            info!("Running on non-Windows platform, Everything is unavailable");
+            self.available = false;
         }
         
         self.initialized = true;
@@ -120,6 +144,11 @@ impl EverythingSDK {
         Ok(())
     }
 
+    /// Whether the Everything DLL was loaded and can answer queries
+    pub fn is_available(&self) -> bool {
+        self.available
+    }
+
     #[cfg(windows)]
     fn load_everything_dll(&mut self) -> Result<()> {
         unsafe {
@@ -198,20 +227,20 @@ impl EverythingSDK {
             return Err(anyhow::anyhow!("Everything SDK not initialized"));
         }
 
+        if !self.available {
+            return Err(anyhow::anyhow!("Everything DLL is not loaded"));
+        }
+
         info!("Searching with query: '{:?}'", query);
 
-        if self.fallback_mode {
-            self.fallback_search(query)
-        } else {
-            #[cfg(windows)]
-            {
-                self.perform_search(query)
-            }
-            
-            #[cfg(not(windows))]
-            {
-                self.fallback_search(query)
-            }
+        #[cfg(windows)]
+        {
+            self.perform_search(query)
+        }
+
+        #[cfg(not(windows))]
+        {
+            Err(anyhow::anyhow!("Everything is only available on Windows"))
         }
     }
 
@@ -225,9 +254,11 @@ impl EverythingSDK {
             let set_request_flags: EverythingSetRequestFlags = self.get_function("Everything_SetRequestFlags")?;
             let set_sort: EverythingSetSort = self.get_function("Everything_SetSort")?;
             let set_max: EverythingSetMax = self.get_function("Everything_SetMax")?;
+            let set_offset: EverythingSetOffset = self.get_function("Everything_SetOffset")?;
             let set_search: EverythingSetSearchW = self.get_function("Everything_SetSearchW")?;
             let query_fn: EverythingQueryW = self.get_function("Everything_QueryW")?;
             let get_num_results: EverythingGetNumResults = self.get_function("Everything_GetNumResults")?;
+            let get_tot_results: EverythingGetTotResults = self.get_function("Everything_GetTotResults")?;
             let get_last_error: EverythingGetLastError = self.get_function("Everything_GetLastError")?;
             
             // Reset Everything state
@@ -243,13 +274,26 @@ impl EverythingSDK {
             );
             
             // Set sort order
-            set_sort(EVERYTHING_SORT_NAME_ASCENDING);
+            set_sort(everything_sort(query.sort));
             
-            // Set maximum number of results
-            set_max(query.limit.unwrap_or(1000));
+            // Only the requested page is returned. Everything has no relevance sort, no fuzzy
+            // scores and no hashes, so for those the same candidates from the start are ranked or
+            // checked and paged here, which keeps pages within them stable.
+            let compiled = CompiledQuery::compile(query)?;
+            let offset = query.offset.unwrap_or(0);
+            let limit = query.limit.unwrap_or(1000);
+            let ranked = compiled.fuzzy || query.sort.field == SortField::Relevance;
+            let filtered = compiled.expr.without_hash() != compiled.expr;
+            if ranked || filtered {
+                set_offset(0);
+                set_max(offset.saturating_add(limit).max(EVERYTHING_RANK_CANDIDATES));
+            } else {
+                set_offset(offset);
+                set_max(limit);
+            }
 
             // Construct the search query string
-            let search_string = self.build_search_string(query);
+            let search_string = self.build_search_string(query, &compiled);
             
             // Convert search term to wide string
             let search_wide: Vec<u16> = search_string.encode_utf16().chain(std::iter::once(0)).collect();
@@ -264,75 +308,68 @@ impl EverythingSDK {
                 return Err(anyhow::anyhow!("Everything query failed with error code: {}", error_code));
             }
             
-            // Get number of results
+            // Rows in the page, and matches of the whole query
             let num_results = get_num_results();
+            let total_results = get_tot_results();
             
-            info!("Everything returned {} results for query: {}", num_results, search_string);
+            info!("Everything returned {} of {} results for query: {}", num_results, total_results, search_string);
             
             // Process results
             let mut entries = Vec::new();
             for i in 0..num_results {
-                if let Ok(entry) = self.get_result_entry(i) {
+                if let Ok(mut entry) = self.get_result_entry(i) {
+                    entry.score = compiled.score(&entry);
                     entries.push(entry);
                 }
             }
+
+            // `hash:` terms were left out of the search string, so candidates are hashed here
+            if filtered {
+                entries.retain(|entry| compiled.matches(entry));
+            }
+            let total_count = if filtered { entries.len() as u64 } else { total_results as u64 };
+
+            // Other sorts keep Everything's order, which pages consistently
+            if ranked {
+                Sorter::new(query.sort, relevance_needle(&compiled)).sort_entries(&mut entries);
+            }
+            if ranked || filtered {
+                entries = entries.into_iter().skip(offset as usize).take(limit as usize).collect();
+            }
             
             let query_time = start_time.elapsed().as_millis() as u64;
             
             Ok(SearchResult {
                 entries,
-                total_count: num_results as u64,
+                total_count,
                 query_time_ms: query_time,
             })
         }
     }
 
+    /// Everything executes the same query language natively, so the compiled
+    /// expression is written back out with the flags as Everything modifiers
     #[cfg(windows)]
-    fn build_search_string(&self, query: &SearchQuery) -> String {
+    fn build_search_string(&self, query: &SearchQuery, compiled: &CompiledQuery) -> String {
         let mut search_parts = Vec::new();
 
-        // Main query
-        if !query.query.trim().is_empty() {
-            search_parts.push(query.query.clone());
+        if compiled.case_sensitive {
+            search_parts.push("case:".to_string());
         }
 
-        // File type filters
-        if !query.filters.file_types.is_empty() {
-            let types_str = query.filters.file_types.iter()
-                .map(|t| format!("ext:{}", t))
-                .collect::<Vec<_>>()
-                .join("|");
-            search_parts.push(format!("({})", types_str));
+        if compiled.regex.is_some() {
+            search_parts.push(format!("regex:\"{}\"", query.query.replace('"', "\\x22")));
         }
 
-        // Size filters
-        if let Some(min) = query.filters.size_min {
-            search_parts.push(format!("size:>={}", min));
-        }
-        if let Some(max) = query.filters.size_max {
-            search_parts.push(format!("size:<={}", max));
+        if !compiled.include_hidden {
+            search_parts.push("!attrib:H".to_string());
         }
 
-        // Date filters (Everything uses 'dm:' for date modified)
-        if let Some(from) = query.filters.date_from {
-            search_parts.push(format!("dm:>={}", from.format("%Y-%m-%d")));
-        }
-        if let Some(to) = query.filters.date_to {
-            search_parts.push(format!("dm:<={}", to.format("%Y-%m-%d")));
+        let expr = compiled.expr.without_hash().to_string();
+        if !expr.is_empty() {
+            search_parts.push(expr);
         }
 
-        // Boolean flags
-        if query.filters.directories_only {
-            search_parts.push("folder:".to_string());
-        } else if query.filters.files_only {
-            search_parts.push("file:".to_string());
-        }
-
-        // Note: include_hidden is usually a default behavior in Everything
-        // case_sensitive and use_regex are handled by Everything's own syntax if needed,
-        // but here we rely on its default smart behavior. For more complex cases,
-        // the main query string could be constructed to include `case:` or `regex:`.
-
         search_parts.join(" ")
     }
 
@@ -386,18 +423,18 @@ impl EverythingSDK {
         let attributes = get_result_attributes(index);
         
         // Convert Windows FILETIME to chrono DateTime
-        let created = self.filetime_to_datetime(created_time);
-        let modified = self.filetime_to_datetime(modified_time);
+        let created = filetime_to_datetime(created_time);
+        let modified = filetime_to_datetime(modified_time);
         
         // Get file extension
         let extension = if !is_directory {
-            path_buf.extension().map(|e| e.to_string_lossy().to_string())
+            path_buf.extension().map(|e| e.to_string_lossy().to_lowercase())
         } else {
             None
         };
         
         Ok(FileEntry {
-            id: format!("everything_{}", index),
+            id: FileEntry::path_id(&path_buf),
             name,
             path: path_str,
             size: if is_directory { 0 } else { file_size },
@@ -406,150 +443,117 @@ impl EverythingSDK {
             is_directory,
             extension,
             attributes: attributes as i32,
+            score: None,
+            source: None,
         })
     }
 
-    #[cfg(windows)]
-    fn filetime_to_datetime(&self, filetime: u64) -> chrono::DateTime<chrono::Utc> {
-        // Windows FILETIME is 100-nanosecond intervals since January 1, 1601 UTC
-        // Unix timestamp is seconds since January 1, 1970 UTC
-        // The difference is 11644473600 seconds
-        
-        if filetime == 0 {
-            return chrono::Utc::now(); // fallback to current time
-        }
-        
-        let windows_epoch_diff = 11644473600u64;
-        let seconds = filetime / 10_000_000;
-        
-        if seconds > windows_epoch_diff {
-            let unix_seconds = seconds - windows_epoch_diff;
-            if let Some(dt) = chrono::DateTime::from_timestamp(unix_seconds as i64, 0) {
-                return dt;
-            }
-        }
-        
-        chrono::Utc::now() // fallback
-    }
-
     pub async fn get_stats(&self) -> Result<IndexStats> {
         if !self.initialized {
             return Err(anyhow::anyhow!("Everything SDK not initialized"));
         }
 
-        if self.fallback_mode {
-            // Return stats for fallback mode
-            Ok(IndexStats {
-                total_files: 100000, // Estimated
-                total_directories: 10000, // Estimated
-                indexed_paths: vec![
-                    "C:\\Windows\\System32".to_string(),
-                    "C:\\Program Files".to_string(),
-                    "C:\\Program Files (x86)".to_string(),
-                    "C:\\Users".to_string(),
-                ],
-                last_index_time: chrono::Utc::now(),
-                index_size_bytes: 10 * 1024 * 1024, // 10MB estimated
-            })
-        } else {
-            // Return stats for Everything SDK mode
-            Ok(IndexStats {
-                total_files: 0, // Could be queried with an empty search
-                total_directories: 0,
-                indexed_paths: vec![
-                    "C:\\".to_string(), // Everything typically indexes all NTFS drives
-                ],
-                last_index_time: chrono::Utc::now(),
-                index_size_bytes: 0, // Not available via SDK
-            })
+        if !self.available {
+            return Err(anyhow::anyhow!("Everything DLL is not loaded"));
         }
+
+        Ok(IndexStats {
+            total_files: 0, // Could be queried with an empty search
+            total_directories: 0,
+            indexed_paths: vec![
+                "C:\\".to_string(), // Everything typically indexes all NTFS drives
+            ],
+            last_index_time: chrono::Utc::now(),
+            index_size_bytes: 0, // Not available via SDK
+        })
     }
+}
 
-    fn fallback_search(&self, query: &SearchQuery) -> Result<SearchResult> {
-        info!("Using fallback file system search for: '{:?}'", query);
-        let start_time = std::time::Instant::now();
-        
-        if query.query.is_empty() {
-            return Ok(SearchResult {
-                entries: Vec::new(),
-                total_count: 0,
-                query_time_ms: 0,
-            });
+/// Everything's sort for `sort`; relevance candidates are taken in name order and ranked here
+#[cfg(windows)]
+fn everything_sort(sort: Sort) -> u32 {
+    let ascending = match sort.field {
+        SortField::Name | SortField::Relevance => EVERYTHING_SORT_NAME_ASCENDING,
+        SortField::Path => EVERYTHING_SORT_PATH_ASCENDING,
+        SortField::Size => EVERYTHING_SORT_SIZE_ASCENDING,
+        SortField::Extension => EVERYTHING_SORT_EXTENSION_ASCENDING,
+        SortField::DateCreated => EVERYTHING_SORT_DATE_CREATED_ASCENDING,
+        SortField::DateModified => EVERYTHING_SORT_DATE_MODIFIED_ASCENDING,
+    };
+    if sort.descending && sort.field != SortField::Relevance {
+        ascending + 1
+    } else {
+        ascending
+    }
+}
+
+/// Attribute Everything sets on folders, also in its file lists
+pub(crate) const FILE_ATTRIBUTE_DIRECTORY: i32 = 0x10;
+
+/// The time of a Windows FILETIME, as reported by Everything and stored in its file lists
+pub(crate) fn filetime_to_datetime(filetime: u64) -> chrono::DateTime<chrono::Utc> {
+    // Windows FILETIME is 100-nanosecond intervals since January 1, 1601 UTC
+    // Unix timestamp is seconds since January 1, 1970 UTC
+    // The difference is 11644473600 seconds
+    
+    if filetime == 0 {
+        return chrono::Utc::now(); // fallback to current time
+    }
+    
+    let windows_epoch_diff = 11644473600u64;
+    let seconds = filetime / 10_000_000;
+    
+    if seconds > windows_epoch_diff {
+        let unix_seconds = seconds - windows_epoch_diff;
+        if let Some(dt) = chrono::DateTime::from_timestamp(unix_seconds as i64, 0) {
+            return dt;
         }
-        
-        let mut entries = Vec::new();
-        let search_term_lower = query.query.to_lowercase();
-        let max_results = query.limit.unwrap_or(1000);
-        
-        // Search in common Windows directories
-        let search_paths = vec![
-            "C:\\Windows\\System32",
-            "C:\\Program Files",
-            "C:\\Program Files (x86)",
-            "C:\\Users",
-        ];
-        
-        for search_path in search_paths {
-            if entries.len() >= max_results as usize {
-                break;
-            }
-            
-            if let Ok(read_dir) = std::fs::read_dir(search_path) {
-                for entry in read_dir.flatten() {
-                    if entries.len() >= max_results as usize {
-                        break;
-                    }
-                    
-                    let path = entry.path();
-                    let file_name = path.file_name()
-                        .unwrap_or_default()
-                        .to_string_lossy()
-                        .to_string();
-                    
-                    if file_name.to_lowercase().contains(&search_term_lower) {
-                        if let Ok(metadata) = entry.metadata() {
-                            let is_directory = metadata.is_dir();
-                            let size = if is_directory { 0 } else { metadata.len() as i64 };
-                            
-                            // Get timestamps
-                            let created = metadata.created()
-                                .map(|t| chrono::DateTime::<chrono::Utc>::from(t))
-                                .unwrap_or_else(|_| chrono::Utc::now());
-                            let modified = metadata.modified()
-                                .map(|t| chrono::DateTime::<chrono::Utc>::from(t))
-                                .unwrap_or_else(|_| chrono::Utc::now());
-                            
-                            let extension = if !is_directory {
-                                path.extension().map(|e| e.to_string_lossy().to_string())
-                            } else {
-                                None
-                            };
-                            
-                            entries.push(FileEntry {
-                                id: format!("fallback_{}", entries.len()),
-                                name: file_name,
-                                path: path.to_string_lossy().to_string(),
-                                size,
-                                modified,
-                                created,
-                                is_directory,
-                                extension,
-                                attributes: 0,
-                            });
-                        }
-                    }
-                }
-            }
+    }
+    
+    chrono::Utc::now() // fallback
+}
+
+/// Windows FILETIME of `time`; the inverse of `filetime_to_datetime`, for writing
+/// Everything's file lists
+pub(crate) fn datetime_to_filetime(time: chrono::DateTime<chrono::Utc>) -> u64 {
+    let windows_epoch_diff = 11644473600i64;
+    match u64::try_from(time.timestamp() + windows_epoch_diff) {
+        Ok(seconds) => seconds * 10_000_000 + u64::from(time.timestamp_subsec_nanos() / 100),
+        Err(_) => 0,
+    }
+}
+
+#[async_trait]
+impl SearchBackend for EverythingSDK {
+    fn name(&self) -> &'static str {
+        "everything"
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities {
+            regex: true,
+            content_search: true,
+            live_updates: true,
+            exact_total_count: true,
+        }
+    }
+
+    async fn search(&self, query: &SearchQuery) -> Result<SearchResult> {
+        EverythingSDK::search(self, query).await
+    }
+
+    async fn stats(&self) -> Result<IndexStats> {
+        self.get_stats().await
+    }
+
+    async fn health(&self) -> BackendHealth {
+        if !self.initialized {
+            BackendHealth::Unavailable("Everything SDK not initialized".to_string())
+        } else if !self.available {
+            BackendHealth::Unavailable("Everything DLL is not loaded".to_string())
+        } else {
+            BackendHealth::Healthy
         }
-        
-        let query_time = start_time.elapsed().as_millis() as u64;
-        let total_count = entries.len() as u64;
-        
-        Ok(SearchResult {
-            entries,
-            total_count,
-            query_time_ms: query_time,
-        })
     }
 }
 
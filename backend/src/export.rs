@@ -0,0 +1,342 @@
+//! Writing results to CSV, TSV, JSON Lines or Everything's `.efu` file lists. Result sets
+//! are written in batches as they are fetched, and a query's matches are read uncached and
+//! moved to the writer, so a large export is never held twice.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::everything_sdk::{datetime_to_filetime, FILE_ATTRIBUTE_DIRECTORY};
+use crate::resultset::ResultSetStore;
+use crate::types::FileEntry;
+
+/// Rows fetched from a result set per write
+const EXPORT_BATCH_SIZE: u64 = 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Tsv,
+    /// One JSON object per line
+    #[serde(alias = "jsonl")]
+    JsonLines,
+    /// Everything's file list: full path, size, FILETIME dates and attributes
+    Efu,
+}
+
+impl ExportFormat {
+    /// The format a file name's extension asks for
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        match extension.as_str() {
+            "csv" => Some(ExportFormat::Csv),
+            "tsv" | "tab" => Some(ExportFormat::Tsv),
+            "jsonl" | "ndjson" => Some(ExportFormat::JsonLines),
+            "efu" => Some(ExportFormat::Efu),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportColumn {
+    Name,
+    Path,
+    /// The folder holding the entry
+    Folder,
+    Size,
+    Modified,
+    Created,
+    Extension,
+    /// `file` or `folder`
+    Type,
+    Attributes,
+}
+
+impl ExportColumn {
+    fn header(self) -> &'static str {
+        match self {
+            ExportColumn::Name => "Name",
+            ExportColumn::Path => "Path",
+            ExportColumn::Folder => "Folder",
+            ExportColumn::Size => "Size",
+            ExportColumn::Modified => "Date Modified",
+            ExportColumn::Created => "Date Created",
+            ExportColumn::Extension => "Extension",
+            ExportColumn::Type => "Type",
+            ExportColumn::Attributes => "Attributes",
+        }
+    }
+
+    fn value(self, entry: &FileEntry) -> serde_json::Value {
+        match self {
+            ExportColumn::Name => entry.name.clone().into(),
+            ExportColumn::Path => entry.path.clone().into(),
+            ExportColumn::Folder => Path::new(&entry.path)
+                .parent()
+                .map(|parent| parent.to_string_lossy().to_string())
+                .unwrap_or_default()
+                .into(),
+            ExportColumn::Size => entry.size.into(),
+            ExportColumn::Modified => entry.modified.to_rfc3339().into(),
+            ExportColumn::Created => entry.created.to_rfc3339().into(),
+            ExportColumn::Extension => entry.extension.clone().unwrap_or_default().into(),
+            ExportColumn::Type => if entry.is_directory { "folder" } else { "file" }.into(),
+            ExportColumn::Attributes => entry.attributes.into(),
+        }
+    }
+}
+
+/// How results are written. `.efu` lists always have Everything's own columns.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportOptions {
+    #[serde(default)]
+    pub format: ExportFormat,
+    #[serde(default = "default_columns")]
+    pub columns: Vec<ExportColumn>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            format: ExportFormat::default(),
+            columns: default_columns(),
+        }
+    }
+}
+
+fn default_columns() -> Vec<ExportColumn> {
+    vec![ExportColumn::Name, ExportColumn::Path, ExportColumn::Size, ExportColumn::Modified]
+}
+
+/// Writes entries in one of the export formats, a batch at a time
+pub struct ExportWriter<W: Write> {
+    out: BufWriter<W>,
+    options: ExportOptions,
+    rows: u64,
+}
+
+impl<W: Write> ExportWriter<W> {
+    /// Start an export to `out`, writing the header row of formats that have one
+    pub fn new(out: W, options: ExportOptions) -> io::Result<Self> {
+        let mut writer = Self {
+            out: BufWriter::new(out),
+            options,
+            rows: 0,
+        };
+        match writer.options.format {
+            ExportFormat::Csv | ExportFormat::Tsv => {
+                let headers: Vec<String> = writer
+                    .options
+                    .columns
+                    .iter()
+                    .map(|column| column.header().to_string())
+                    .collect();
+                let separator = if writer.options.format == ExportFormat::Csv { "," } else { "\t" };
+                writer.write_line(&headers, separator)?;
+            }
+            ExportFormat::JsonLines => {}
+            ExportFormat::Efu => writer.out.write_all(b"Filename,Size,Date Modified,Date Created,Attributes\r\n")?,
+        }
+        Ok(writer)
+    }
+
+    pub fn write_entries(&mut self, entries: &[FileEntry]) -> io::Result<()> {
+        for entry in entries {
+            self.write_entry(entry)?;
+        }
+        Ok(())
+    }
+
+    /// Flush what is left; returns the number of entries written
+    pub fn finish(mut self) -> io::Result<u64> {
+        self.out.flush()?;
+        Ok(self.rows)
+    }
+
+    fn write_entry(&mut self, entry: &FileEntry) -> io::Result<()> {
+        match self.options.format {
+            ExportFormat::Csv => {
+                let fields: Vec<String> = self
+                    .options
+                    .columns
+                    .iter()
+                    .map(|column| csv_field(&text(column.value(entry))))
+                    .collect();
+                self.write_line(&fields, ",")?;
+            }
+            ExportFormat::Tsv => {
+                // TSV has no quoting, so separators inside a value become spaces
+                let fields: Vec<String> = self
+                    .options
+                    .columns
+                    .iter()
+                    .map(|column| text(column.value(entry)).replace(['\t', '\n', '\r'], " "))
+                    .collect();
+                self.write_line(&fields, "\t")?;
+            }
+            ExportFormat::JsonLines => {
+                let object: serde_json::Map<String, serde_json::Value> = self
+                    .options
+                    .columns
+                    .iter()
+                    .map(|column| (column_key(*column), column.value(entry)))
+                    .collect();
+                serde_json::to_writer(&mut self.out, &object)?;
+                self.out.write_all(b"\n")?;
+            }
+            ExportFormat::Efu => {
+                let (size, attributes) = match entry.is_directory {
+                    true => (String::new(), entry.attributes | FILE_ATTRIBUTE_DIRECTORY),
+                    false => (entry.size.to_string(), entry.attributes),
+                };
+                write!(
+                    self.out,
+                    "\"{}\",{},{},{},{}\r\n",
+                    entry.path.replace('"', "\"\""),
+                    size,
+                    datetime_to_filetime(entry.modified),
+                    datetime_to_filetime(entry.created),
+                    attributes
+                )?;
+            }
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn write_line(&mut self, fields: &[String], separator: &str) -> io::Result<()> {
+        self.out.write_all(fields.join(separator).as_bytes())?;
+        self.out.write_all(b"\n")
+    }
+}
+
+fn column_key(column: ExportColumn) -> String {
+    match serde_json::to_value(column) {
+        Ok(serde_json::Value::String(key)) => key,
+        _ => column.header().to_string(),
+    }
+}
+
+fn text(value: serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(text) => text,
+        other => other.to_string(),
+    }
+}
+
+/// `value` quoted when it holds a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Write `entries` to the file at `path`; returns the number written
+pub async fn export_entries(entries: Vec<FileEntry>, path: &Path, options: ExportOptions) -> Result<u64> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut writer = ExportWriter::new(File::create(&path)?, options)?;
+        writer.write_entries(&entries)?;
+        Ok(writer.finish()?)
+    })
+    .await?
+}
+
+/// Write every row of the result set `handle`, in its current view, to the file at `path`,
+/// a batch at a time; returns the number written
+pub async fn export_result_set(
+    store: Arc<ResultSetStore>,
+    handle: u64,
+    path: &Path,
+    options: ExportOptions,
+) -> Result<u64> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut writer = ExportWriter::new(File::create(&path)?, options)?;
+        let mut offset = 0;
+        loop {
+            let batch = store.fetch(handle, offset, EXPORT_BATCH_SIZE)?;
+            if batch.is_empty() {
+                break;
+            }
+            writer.write_entries(&batch)?;
+            offset += batch.len() as u64;
+        }
+        Ok(writer.finish()?)
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{entry, folder};
+    use chrono::TimeZone;
+
+    fn export(format: ExportFormat, columns: Vec<ExportColumn>) -> String {
+        let time = chrono::Utc.with_ymd_and_hms(2024, 1, 15, 12, 0, 0).unwrap();
+        let entries = [
+            FileEntry { size: 1234, modified: time, created: time, ..entry("/data/a, \"quoted\".csv") },
+            FileEntry { modified: time, created: time, ..folder("/data/folder") },
+        ];
+        let mut out = Vec::new();
+        let mut writer = ExportWriter::new(&mut out, ExportOptions { format, columns }).unwrap();
+        writer.write_entries(&entries[..1]).unwrap();
+        writer.write_entries(&entries[1..]).unwrap();
+        assert_eq!(writer.finish().unwrap(), 2);
+        String::from_utf8(out).unwrap()
+    }
+
+    fn name_size_type() -> Vec<ExportColumn> {
+        vec![ExportColumn::Name, ExportColumn::Size, ExportColumn::Type]
+    }
+
+    #[test]
+    fn test_csv_quotes_fields_with_separators_and_quotes() {
+        assert_eq!(
+            export(ExportFormat::Csv, name_size_type()),
+            "Name,Size,Type\n\"a, \"\"quoted\"\".csv\",1234,file\nfolder,0,folder\n"
+        );
+    }
+
+    #[test]
+    fn test_tsv_writes_fields_as_they_are() {
+        assert_eq!(
+            export(ExportFormat::Tsv, name_size_type()),
+            "Name\tSize\tType\na, \"quoted\".csv\t1234\tfile\nfolder\t0\tfolder\n"
+        );
+    }
+
+    #[test]
+    fn test_json_lines_hold_the_selected_columns() {
+        let lines = export(ExportFormat::JsonLines, vec![ExportColumn::Path, ExportColumn::Modified]);
+        let first: serde_json::Value = serde_json::from_str(lines.lines().next().unwrap()).unwrap();
+        assert_eq!(first["path"], "/data/a, \"quoted\".csv");
+        assert_eq!(first["modified"], "2024-01-15T12:00:00+00:00");
+    }
+
+    #[test]
+    fn test_efu_has_fixed_columns_filetimes_and_attributes() {
+        // 2024-01-15 12:00 UTC as a FILETIME; folders have no size and the directory attribute
+        assert_eq!(
+            export(ExportFormat::Efu, name_size_type()),
+            "Filename,Size,Date Modified,Date Created,Attributes\r\n\
+             \"/data/a, \"\"quoted\"\".csv\",1234,133497936000000000,133497936000000000,0\r\n\
+             \"/data/folder\",,133497936000000000,133497936000000000,16\r\n"
+        );
+    }
+
+    #[test]
+    fn test_format_from_extension_ignores_case() {
+        assert_eq!(ExportFormat::from_path(Path::new("out.NDJSON")), Some(ExportFormat::JsonLines));
+    }
+}
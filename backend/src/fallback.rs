@@ -0,0 +1,298 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+use crate::backend::{BackendCapabilities, BackendHealth, SearchBackend};
+use crate::config::{AppConfig, ContentConfig, WalkConfig};
+use crate::indexer::{is_excluded, windows_integration};
+use crate::search::query::{is_hidden, CompiledQuery};
+use crate::search::sort::{relevance_needle, Sorter};
+use crate::stream::ResultSink;
+use crate::types::{FileEntry, IndexStats, SearchError, SearchQuery, SearchResult};
+
+/// Streamed matches are sent once this many are pending, or after `STREAM_INTERVAL`
+const STREAM_BATCH_SIZE: usize = 256;
+const STREAM_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Index-free backend that walks the file system on every query
+#[derive(Clone)]
+pub struct FallbackSearch {
+    config: WalkConfig,
+    exclude_patterns: Vec<String>,
+    content: ContentConfig,
+}
+
+impl FallbackSearch {
+    pub fn new(config: WalkConfig, exclude_patterns: Vec<String>) -> Self {
+        Self { config, exclude_patterns, content: ContentConfig::default() }
+    }
+
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self::new(config.walk.clone(), config.exclude_patterns.clone()).with_content(config.content.clone())
+    }
+
+    /// Read files for `content:` terms as configured by `content`
+    pub fn with_content(mut self, content: ContentConfig) -> Self {
+        self.content = content;
+        self
+    }
+
+    /// Breadth-first walk of every root, so shallow matches are found first. Stops once
+    /// `offset + limit` matches are found or the time budget runs out; only the matches
+    /// found are sorted. With a `sink`, matches are also streamed in the order they are
+    /// found, and the walk stops when the sink is cancelled.
+    fn walk(&self, query: &SearchQuery, sink: Option<&ResultSink>) -> Result<SearchResult> {
+        info!("Using fallback file system search for: '{:?}'", query);
+        let start_time = Instant::now();
+
+        if query.query.is_empty() {
+            return Ok(SearchResult {
+                entries: Vec::new(),
+                total_count: 0,
+                query_time_ms: 0,
+            });
+        }
+
+        let compiled = CompiledQuery::compile(query)?.with_content(self.content.clone());
+        let sorter = Sorter::new(query.sort, relevance_needle(&compiled));
+        let offset = query.offset.unwrap_or(0) as usize;
+        let wanted = offset + query.limit.unwrap_or(1000) as usize;
+        let deadline = start_time + Duration::from_millis(self.config.time_budget_ms);
+
+        let mut matched = 0usize;
+        let mut entries = Vec::new();
+        let mut streamed = 0usize;
+        let mut last_batch = start_time;
+        let mut queue: VecDeque<(PathBuf, usize)> = self
+            .config
+            .roots
+            .iter()
+            .map(|root| (root.clone(), 0))
+            .collect();
+
+        'walk: while let Some((dir, depth)) = queue.pop_front() {
+            let Ok(read_dir) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+
+            for entry in read_dir.flatten() {
+                if matched >= wanted {
+                    break 'walk;
+                }
+                if Instant::now() >= deadline {
+                    debug!("Walk time budget exhausted after {} matches", matched);
+                    break 'walk;
+                }
+                if let Some(sink) = sink {
+                    let pending = entries.len() - streamed;
+                    if pending >= STREAM_BATCH_SIZE || (pending > 0 && last_batch.elapsed() >= STREAM_INTERVAL) {
+                        sink.send(entries[streamed..].to_vec());
+                        streamed = entries.len();
+                        last_batch = Instant::now();
+                    }
+                    if sink.is_cancelled() {
+                        return Err(SearchError::Cancelled.into());
+                    }
+                }
+
+                let path = entry.path();
+                if is_excluded(&path, &self.exclude_patterns) {
+                    continue;
+                }
+
+                // DirEntry metadata does not traverse symlinks, so linked directories are not followed
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+
+                let file_entry = entry_from_metadata(&path, &metadata);
+                let descend = compiled.include_hidden || !is_hidden(&file_entry);
+                if metadata.is_dir() && depth < self.config.max_depth && descend {
+                    queue.push_back((path, depth + 1));
+                }
+
+                if compiled.matches(&file_entry) {
+                    let score = compiled.score(&file_entry);
+                    entries.push(FileEntry { score, ..file_entry });
+                    matched += 1;
+                }
+            }
+        }
+
+        if let Some(sink) = sink {
+            sink.send(entries[streamed..].to_vec());
+        }
+
+        sorter.sort_entries(&mut entries);
+        entries.drain(..offset.min(entries.len()));
+
+        Ok(SearchResult {
+            entries,
+            total_count: matched as u64,
+            query_time_ms: start_time.elapsed().as_millis() as u64,
+        })
+    }
+}
+
+fn entry_from_metadata(path: &Path, metadata: &Metadata) -> FileEntry {
+    let is_directory = metadata.is_dir();
+    let size = if is_directory { 0 } else { metadata.len() as i64 };
+
+    // Get timestamps
+    let created = metadata.created()
+        .map(chrono::DateTime::<chrono::Utc>::from)
+        .unwrap_or_else(|_| chrono::Utc::now());
+    let modified = metadata.modified()
+        .map(chrono::DateTime::<chrono::Utc>::from)
+        .unwrap_or_else(|_| chrono::Utc::now());
+
+    let extension = if !is_directory {
+        path.extension().map(|e| e.to_string_lossy().to_lowercase())
+    } else {
+        None
+    };
+
+    FileEntry {
+        id: FileEntry::file_id(path, metadata),
+        name: path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+        path: path.to_string_lossy().to_string(),
+        size,
+        modified,
+        created,
+        is_directory,
+        extension,
+        attributes: windows_integration::get_windows_attributes(path),
+        score: None,
+        source: None,
+    }
+}
+
+#[async_trait]
+impl SearchBackend for FallbackSearch {
+    fn name(&self) -> &'static str {
+        "walk"
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities {
+            regex: true,
+            content_search: true,
+            live_updates: true,
+            exact_total_count: false,
+        }
+    }
+
+    async fn search(&self, query: &SearchQuery) -> Result<SearchResult> {
+        let walker = self.clone();
+        let query = query.clone();
+        tokio::task::spawn_blocking(move || walker.walk(&query, None)).await?
+    }
+
+    async fn search_streaming(&self, query: &SearchQuery, sink: &ResultSink) -> Result<SearchResult> {
+        let walker = self.clone();
+        let query = query.clone();
+        let sink = sink.clone();
+        tokio::task::spawn_blocking(move || walker.walk(&query, Some(&sink))).await?
+    }
+
+    async fn stats(&self) -> Result<IndexStats> {
+        Ok(IndexStats {
+            total_files: 0, // Nothing is indexed
+            total_directories: 0,
+            indexed_paths: self
+                .config
+                .roots
+                .iter()
+                .map(|path| path.to_string_lossy().to_string())
+                .collect(),
+            last_index_time: chrono::Utc::now(),
+            index_size_bytes: 0,
+        })
+    }
+
+    async fn health(&self) -> BackendHealth {
+        if self.config.roots.iter().any(|path| path.exists()) {
+            BackendHealth::Healthy
+        } else {
+            BackendHealth::Unavailable("None of the walk roots exist".to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use crate::types::SearchFilters;
+
+    fn query(text: &str, filters: SearchFilters, limit: u32) -> SearchQuery {
+        SearchQuery { filters, limit: Some(limit), ..test_support::query(text) }
+    }
+
+    #[test]
+    fn test_walk_recurses_and_applies_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("a/b/node_modules")).unwrap();
+        std::fs::create_dir_all(root.join(".hidden")).unwrap();
+        std::fs::write(root.join("a/b/notes.txt"), "hello").unwrap();
+        std::fs::write(root.join("a/b/node_modules/notes.txt"), "").unwrap();
+        std::fs::write(root.join(".hidden/notes.txt"), "").unwrap();
+        std::fs::write(root.join("a/notes.md"), "").unwrap();
+
+        let config = WalkConfig { roots: vec![root.to_path_buf()], max_depth: 8, time_budget_ms: 10_000 };
+        let walker = FallbackSearch::new(config, vec!["node_modules".to_string()]);
+
+        let result = walker.walk(&query("notes", SearchFilters::default(), 10), None).unwrap();
+        assert_eq!(result.total_count, 2);
+        // Sorted by name, wherever each was found
+        assert_eq!(result.entries[0].name, "notes.md");
+
+        let filters = SearchFilters { file_types: vec!["txt".to_string()], size_min: Some(1), ..Default::default() };
+        let result = walker.walk(&query("notes", filters, 10), None).unwrap();
+        assert_eq!(result.entries.len(), 1);
+        assert!(result.entries[0].path.ends_with("notes.txt"));
+
+        let filters = SearchFilters { include_hidden: true, ..Default::default() };
+        let result = walker.walk(&query("notes", filters, 10), None).unwrap();
+        assert_eq!(result.total_count, 3);
+
+        let result = walker.walk(&query("notes", SearchFilters::default(), 1), None).unwrap();
+        assert_eq!(result.entries.len(), 1);
+    }
+
+    #[test]
+    fn test_walk_stops_at_the_shallowest_matches() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("a/b")).unwrap();
+        std::fs::write(root.join("a/b/a_notes.txt"), "").unwrap();
+        std::fs::write(root.join("z_notes.txt"), "").unwrap();
+
+        let config = WalkConfig { roots: vec![root.to_path_buf()], max_depth: 8, time_budget_ms: 10_000 };
+        let walker = FallbackSearch::new(config, Vec::new());
+
+        // Breadth-first, so the deeper match is never reached although it sorts first
+        let result = walker.walk(&query("notes", SearchFilters::default(), 1), None).unwrap();
+        assert_eq!(result.entries.len(), 1);
+        assert_eq!(result.entries[0].name, "z_notes.txt");
+    }
+
+    #[test]
+    fn test_walk_reads_contents_as_configured() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("build.gn"), "executable(\"server\")").unwrap();
+
+        let config = WalkConfig { roots: vec![dir.path().to_path_buf()], max_depth: 8, time_budget_ms: 10_000 };
+        let walker = FallbackSearch::new(config, Vec::new());
+        assert_eq!(walker.walk(&query("content:server", SearchFilters::default(), 10), None).unwrap().total_count, 0);
+
+        let content = ContentConfig { extensions: vec!["gn".to_string()], ..Default::default() };
+        let walker = walker.with_content(content);
+        assert_eq!(walker.walk(&query("content:server", SearchFilters::default(), 10), None).unwrap().total_count, 1);
+    }
+}
@@ -0,0 +1,403 @@
+//! Everything's `.efu` file lists, of archive disks and other machines that are not always
+//! reachable. Lists are held in memory under a name and searched next to the live sources;
+//! their entries are tagged with the list they came from.
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use tracing::{info, warn};
+
+use crate::backend::{BackendCapabilities, BackendHealth, SearchBackend};
+use crate::config::ContentConfig;
+use crate::database::Database;
+use crate::everything_sdk::{filetime_to_datetime, FILE_ATTRIBUTE_DIRECTORY};
+use crate::search::query::CompiledQuery;
+use crate::search::sort::{relevance_needle, Sorter};
+use crate::types::{FileEntry, FileListInfo, IndexStats, SearchQuery, SearchResult};
+
+struct FileList {
+    name: String,
+    path: String,
+    entries: Vec<FileEntry>,
+    loaded_at: DateTime<Utc>,
+}
+
+impl FileList {
+    fn info(&self) -> FileListInfo {
+        FileListInfo {
+            name: self.name.clone(),
+            path: self.path.clone(),
+            entry_count: self.entries.len() as u64,
+            loaded_at: self.loaded_at,
+        }
+    }
+}
+
+/// Read-only backend over the imported file lists
+pub struct FileListBackend {
+    db: Arc<Database>,
+    lists: RwLock<Vec<Arc<FileList>>>,
+    /// Bumped whenever a list is loaded or removed
+    generation: AtomicU64,
+    content: ContentConfig,
+}
+
+impl FileListBackend {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self {
+            db,
+            lists: RwLock::new(Vec::new()),
+            generation: AtomicU64::new(0),
+            content: ContentConfig::default(),
+        }
+    }
+
+    /// Read files for `content:` terms as configured by `content`
+    pub fn with_content(mut self, content: ContentConfig) -> Self {
+        self.content = content;
+        self
+    }
+
+    /// Load every list imported by an earlier run. Lists whose file cannot be read are
+    /// skipped but stay registered, in case their drive comes back.
+    pub async fn load_saved(&self) -> Result<()> {
+        for (name, path) in self.db.file_lists().await? {
+            match self.load(&name, Path::new(&path)).await {
+                Ok(info) => info!("Loaded file list '{}' with {} entries", info.name, info.entry_count),
+                Err(e) => warn!("Failed to load file list '{}' from {}: {}", name, path, e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Load the `.efu` file at `path` as the list `name`, replacing any list of that name,
+    /// and load it again on later runs
+    pub async fn import(&self, name: &str, path: &Path) -> Result<FileListInfo> {
+        let name = name.trim();
+        if name.is_empty() {
+            bail!("A file list needs a name");
+        }
+        let info = self.load(name, path).await?;
+        self.db.record_file_list(&info.name, &info.path).await?;
+        Ok(info)
+    }
+
+    /// Returns whether there was a list called `name`
+    pub async fn remove(&self, name: &str) -> Result<bool> {
+        let removed = {
+            let mut lists = self.lists.write().unwrap();
+            let count = lists.len();
+            lists.retain(|list| !list.name.eq_ignore_ascii_case(name));
+            lists.len() < count
+        };
+        if removed {
+            self.generation.fetch_add(1, Ordering::AcqRel);
+        }
+        Ok(self.db.delete_file_list(name).await? || removed)
+    }
+
+    /// The loaded lists, by name
+    pub fn lists(&self) -> Vec<FileListInfo> {
+        let mut lists: Vec<FileListInfo> = self.lists.read().unwrap().iter().map(|list| list.info()).collect();
+        lists.sort_by_key(|list| list.name.to_lowercase());
+        lists
+    }
+
+    async fn load(&self, name: &str, path: &Path) -> Result<FileListInfo> {
+        let source = name.to_string();
+        let file = path.to_path_buf();
+        let entries = tokio::task::spawn_blocking(move || -> Result<Vec<FileEntry>> {
+            let contents = std::fs::read(&file)?;
+            parse_efu(&String::from_utf8_lossy(&contents), &source)
+        })
+        .await??;
+
+        let list = Arc::new(FileList {
+            name: name.to_string(),
+            path: path.to_string_lossy().to_string(),
+            entries,
+            loaded_at: Utc::now(),
+        });
+        let info = list.info();
+        {
+            let mut lists = self.lists.write().unwrap();
+            lists.retain(|other| !other.name.eq_ignore_ascii_case(name));
+            lists.push(list);
+        }
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        Ok(info)
+    }
+}
+
+/// The entries of an Everything file list, tagged with `source`. Columns are found by
+/// their header, so lists with only some of Everything's columns are read too.
+pub fn parse_efu(contents: &str, source: &str) -> Result<Vec<FileEntry>> {
+    let mut lines = contents.trim_start_matches('\u{feff}').lines();
+    let header = csv_fields(lines.next().unwrap_or_default());
+    let column = |name: &str| header.iter().position(|field| field.eq_ignore_ascii_case(name));
+    let filename = column("Filename").ok_or_else(|| anyhow!("Not an Everything file list: no Filename column"))?;
+    let (size, modified, created, attributes) =
+        (column("Size"), column("Date Modified"), column("Date Created"), column("Attributes"));
+
+    let mut entries = Vec::new();
+    for line in lines.filter(|line| !line.is_empty()) {
+        let fields = csv_fields(line);
+        let field = |index: Option<usize>| index.and_then(|index| fields.get(index)).map(|field| field.trim());
+        let number = |index: Option<usize>| field(index).and_then(|field| field.parse::<u64>().ok()).unwrap_or(0);
+        // An empty date is unknown, not now, so it never passes for a recent change
+        let date = |index: Option<usize>| match number(index) {
+            0 => DateTime::<Utc>::UNIX_EPOCH,
+            filetime => filetime_to_datetime(filetime),
+        };
+
+        let Some(path) = field(Some(filename)).filter(|path| !path.is_empty()) else {
+            continue;
+        };
+        // Roots such as `D:\` keep their separator
+        let path = match path.trim_end_matches(['/', '\\']) {
+            trimmed if trimmed.ends_with(':') || trimmed.is_empty() => path,
+            trimmed => trimmed,
+        };
+        let attributes = number(attributes) as i32;
+        let is_directory = attributes & FILE_ATTRIBUTE_DIRECTORY != 0;
+        // The list may come from another platform, so both separators are honoured
+        let name = match path.rsplit(['/', '\\']).next() {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => path.to_string(),
+        };
+        let extension = match is_directory {
+            true => None,
+            false => name
+                .rfind('.')
+                .filter(|&dot| dot > 0 && dot + 1 < name.len())
+                .map(|dot| name[dot + 1..].to_lowercase()),
+        };
+
+        entries.push(FileEntry {
+            id: FileEntry::path_id(Path::new(path)),
+            name,
+            path: path.to_string(),
+            size: if is_directory { 0 } else { number(size) as i64 },
+            modified: date(modified),
+            created: date(created),
+            is_directory,
+            extension,
+            attributes,
+            score: None,
+            source: Some(source.to_string()),
+        });
+    }
+    Ok(entries)
+}
+
+/// Fields of one CSV line, with quotes removed and doubled quotes unescaped
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+#[async_trait]
+impl SearchBackend for FileListBackend {
+    fn name(&self) -> &'static str {
+        "file_list"
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities {
+            regex: true,
+            content_search: false,
+            live_updates: false,
+            exact_total_count: true,
+        }
+    }
+
+    async fn search(&self, query: &SearchQuery) -> Result<SearchResult> {
+        // Like the other backends, an empty query matches nothing
+        if query.query.is_empty() {
+            return Ok(SearchResult {
+                entries: Vec::new(),
+                total_count: 0,
+                query_time_ms: 0,
+            });
+        }
+
+        let lists = self.lists.read().unwrap().clone();
+        let query = query.clone();
+        let content = self.content.clone();
+        tokio::task::spawn_blocking(move || {
+            let start_time = Instant::now();
+            let compiled = CompiledQuery::compile(&query)?.with_content(content);
+            let sorter = Sorter::new(query.sort, relevance_needle(&compiled));
+
+            let mut entries: Vec<FileEntry> = lists
+                .iter()
+                .flat_map(|list| list.entries.iter())
+                .filter(|entry| compiled.matches(entry))
+                .map(|entry| FileEntry {
+                    score: compiled.score(entry),
+                    ..entry.clone()
+                })
+                .collect();
+            let total_count = entries.len() as u64;
+
+            sorter.sort_entries(&mut entries);
+            let offset = query.offset.unwrap_or(0) as usize;
+            let limit = query.limit.unwrap_or(1000) as usize;
+            let entries = entries.into_iter().skip(offset).take(limit).collect();
+
+            Ok(SearchResult {
+                entries,
+                total_count,
+                query_time_ms: start_time.elapsed().as_millis() as u64,
+            })
+        })
+        .await?
+    }
+
+    fn generation(&self) -> Option<u64> {
+        Some(self.generation.load(Ordering::Acquire))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.lists.read().unwrap().is_empty()
+    }
+
+    async fn stats(&self) -> Result<IndexStats> {
+        let lists = self.lists.read().unwrap();
+        let entries = || lists.iter().flat_map(|list| list.entries.iter());
+        Ok(IndexStats {
+            total_files: entries().filter(|entry| !entry.is_directory).count() as u64,
+            total_directories: entries().filter(|entry| entry.is_directory).count() as u64,
+            indexed_paths: lists.iter().map(|list| list.path.clone()).collect(),
+            last_index_time: lists.iter().map(|list| list.loaded_at).max().unwrap_or_else(Utc::now),
+            index_size_bytes: 0,
+        })
+    }
+
+    async fn health(&self) -> BackendHealth {
+        BackendHealth::Healthy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::query;
+    use crate::types::SearchFilters;
+
+    struct Fixture {
+        _dir: tempfile::TempDir,
+        db: Arc<Database>,
+        backend: FileListBackend,
+        info: FileListInfo,
+    }
+
+    /// A backend with a list of a drive root, a folder, a photo and a document imported
+    async fn fixture() -> Fixture {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(Database::new(&dir.path().join("index.db")).await.unwrap());
+        let list = dir.path().join("archive.efu");
+        // 133497936000000000 is 2024-01-15 12:00 UTC
+        std::fs::write(
+            &list,
+            "\u{feff}Filename,Size,Date Modified,Date Created,Attributes\r\n\
+             \"E:\\\",,,,16\r\n\
+             \"E:\\Photos\",,133497936000000000,133497936000000000,16\r\n\
+             \"E:\\Photos\\beach, \"\"2023\"\".JPG\",2048,133497936000000000,133497936000000000,32\r\n\
+             \"E:\\Docs\\report.pdf\",100,133497936000000000,133497936000000000,0\r\n",
+        )
+        .unwrap();
+
+        let backend = FileListBackend::new(db.clone());
+        assert!(backend.is_empty());
+        let info = backend.import(" Archive ", &list).await.unwrap();
+        Fixture { _dir: dir, db, backend, info }
+    }
+
+    #[tokio::test]
+    async fn test_import_trims_the_name_and_counts_the_entries() {
+        let Fixture { _dir, info, .. } = fixture().await;
+        assert_eq!((info.name.as_str(), info.entry_count), ("Archive", 4));
+    }
+
+    #[tokio::test]
+    async fn test_entries_keep_their_fields_and_are_tagged_with_the_list() {
+        let Fixture { _dir, backend, .. } = fixture().await;
+        let result = backend.search(&query("ext:jpg")).await.unwrap();
+        let photo = &result.entries[0];
+        assert_eq!((photo.name.as_str(), photo.size), ("beach, \"2023\".JPG", 2048));
+        assert_eq!(photo.source.as_deref(), Some("Archive"));
+        assert_eq!(photo.modified, DateTime::parse_from_rfc3339("2024-01-15T12:00:00Z").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_folders_are_told_apart_by_their_attributes() {
+        let Fixture { _dir, backend, .. } = fixture().await;
+        let folders = SearchQuery {
+            filters: SearchFilters { directories_only: true, ..Default::default() },
+            ..query("p")
+        };
+        let folders = backend.search(&folders).await.unwrap();
+        let folders: Vec<_> = folders.entries.iter().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(folders, vec!["E:\\Photos"]);
+    }
+
+    #[tokio::test]
+    async fn test_an_empty_query_lists_nothing_like_the_live_index() {
+        let Fixture { _dir, backend, .. } = fixture().await;
+        assert_eq!(backend.search(&query("")).await.unwrap().total_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_imported_lists_are_loaded_again_until_removed() {
+        let Fixture { _dir, db, .. } = fixture().await;
+        let reloaded = FileListBackend::new(db.clone());
+        reloaded.load_saved().await.unwrap();
+        assert_eq!(reloaded.lists().len(), 1);
+        assert_eq!(reloaded.search(&query("report")).await.unwrap().total_count, 1);
+        assert!(reloaded.remove("archive").await.unwrap());
+        assert!(reloaded.is_empty());
+
+        let after_removal = FileListBackend::new(db);
+        after_removal.load_saved().await.unwrap();
+        assert!(after_removal.is_empty());
+    }
+
+    #[test]
+    fn test_lists_without_a_filename_column_are_rejected() {
+        assert!(parse_efu("Name,Size\r\n", "x").is_err());
+    }
+
+    #[test]
+    fn test_roots_keep_their_separator_and_empty_dates_are_unknown() {
+        let entries = parse_efu(
+            "Filename,Size,Date Modified,Date Created,Attributes\r\n\
+             \"E:\\\",,,,16\r\n\
+             \"E:\\undated.txt\",5,,,0\r\n",
+            "Archive",
+        )
+        .unwrap();
+        assert_eq!((entries[0].path.as_str(), entries[0].is_directory), ("E:\\", true));
+        assert_eq!(entries[1].modified, DateTime::<Utc>::UNIX_EPOCH);
+        assert_eq!(entries[1].created, DateTime::<Utc>::UNIX_EPOCH);
+    }
+}
@@ -0,0 +1,475 @@
+//! Renaming, moving, copying and trashing files from the results. Every operation is
+//! journalled so the latest ones can be undone, and the index is updated as each file is
+//! handled instead of when the watcher reports it.
+
+use anyhow::{anyhow, bail, Result};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+use crate::database::Database;
+use crate::indexer::FileIndexer;
+use crate::types::{
+    FileOperationFailure, FileOperationKind, FileOperationRecord, FileOperationReport, FileOperationStep,
+};
+
+/// Operations kept in the journal
+const JOURNAL_LENGTH: usize = 100;
+
+pub struct FileOperations {
+    db: Arc<Database>,
+    /// Native indexer to update, when the index backend is selected
+    indexer: Option<Arc<FileIndexer>>,
+    /// Held for each operation, so an undo never starts halfway through another operation
+    running: Mutex<()>,
+}
+
+impl FileOperations {
+    pub fn new(db: Arc<Database>, indexer: Option<Arc<FileIndexer>>) -> Self {
+        Self {
+            db,
+            indexer,
+            running: Mutex::new(()),
+        }
+    }
+
+    /// Give the file or folder at `path` the name `new_name`, in the same folder
+    pub async fn rename(&self, path: &str, new_name: &str) -> Result<FileOperationReport> {
+        if new_name.is_empty() || new_name.contains(['/', '\\']) || new_name == "." || new_name == ".." {
+            bail!("'{}' is not a valid name", new_name);
+        }
+        let source = PathBuf::from(path);
+        let parent = source.parent().ok_or_else(|| anyhow!("'{}' cannot be renamed", path))?;
+        let destination = parent.join(new_name);
+        // Keeping the name does nothing, and is not journalled
+        if destination == source {
+            return Ok(FileOperationReport {
+                kind: FileOperationKind::Rename,
+                operation_id: None,
+                completed: Vec::new(),
+                failed: Vec::new(),
+            });
+        }
+        self.perform(FileOperationKind::Rename, vec![(source, Some(destination))]).await
+    }
+
+    /// Move each of `paths` into the folder `destination`
+    pub async fn move_to(&self, paths: &[String], destination: &str) -> Result<FileOperationReport> {
+        let targets = into_folder(paths, destination)?;
+        self.perform(FileOperationKind::Move, targets).await
+    }
+
+    /// Copy each of `paths`, folders with their contents, into the folder `destination`
+    pub async fn copy_to(&self, paths: &[String], destination: &str) -> Result<FileOperationReport> {
+        let targets = into_folder(paths, destination)?;
+        self.perform(FileOperationKind::Copy, targets).await
+    }
+
+    /// Move each of `paths` to the system trash
+    pub async fn trash(&self, paths: &[String]) -> Result<FileOperationReport> {
+        let targets = paths.iter().map(|path| (PathBuf::from(path), None)).collect();
+        self.perform(FileOperationKind::Trash, targets).await
+    }
+
+    /// Undo the latest operation in the journal, last file first. Copies are moved to the
+    /// trash rather than deleted. The operation leaves the journal even if some files could
+    /// not be put back, since those have changed since. `None` if the journal is empty.
+    pub async fn undo_last(&self) -> Result<Option<FileOperationReport>> {
+        let _running = self.running.lock().await;
+        let Some(operation) = self.db.file_operations(1).await?.pop() else {
+            return Ok(None);
+        };
+
+        let kind = operation.kind;
+        let mut report = FileOperationReport {
+            kind,
+            operation_id: Some(operation.id),
+            completed: Vec::new(),
+            failed: Vec::new(),
+        };
+        for step in operation.steps.into_iter().rev() {
+            let undone = {
+                let step = step.clone();
+                tokio::task::spawn_blocking(move || undo(kind, &step)).await?
+            };
+            match undone {
+                Ok(()) => {
+                    let source = Path::new(&step.source);
+                    match (kind, step.destination.as_deref().map(Path::new)) {
+                        (FileOperationKind::Rename | FileOperationKind::Move, Some(destination)) => {
+                            self.index_moved(destination, source).await
+                        }
+                        (FileOperationKind::Copy, Some(destination)) => self.index_changed(destination).await,
+                        _ => self.index_changed(source).await,
+                    }
+                    report.completed.push(step);
+                }
+                Err(e) => report.failed.push(FileOperationFailure {
+                    path: step.destination.unwrap_or(step.source),
+                    error: e.to_string(),
+                }),
+            }
+        }
+
+        self.db.delete_file_operation(operation.id).await?;
+        Ok(Some(report))
+    }
+
+    /// Up to `limit` journalled operations, latest first
+    pub async fn journal(&self, limit: usize) -> Result<Vec<FileOperationRecord>> {
+        self.db.file_operations(limit).await
+    }
+
+    /// Apply `kind` to each source, with its destination, and journal the files it handled
+    async fn perform(
+        &self,
+        kind: FileOperationKind,
+        targets: Vec<(PathBuf, Option<PathBuf>)>,
+    ) -> Result<FileOperationReport> {
+        let _running = self.running.lock().await;
+        let mut report = FileOperationReport {
+            kind,
+            operation_id: None,
+            completed: Vec::new(),
+            failed: Vec::new(),
+        };
+
+        for (source, destination) in targets {
+            let applied = {
+                let (source, destination) = (source.clone(), destination.clone());
+                tokio::task::spawn_blocking(move || apply(kind, &source, destination.as_deref())).await?
+            };
+            match applied {
+                Ok(()) => {
+                    debug!("{:?} {} -> {:?}", kind, source.display(), destination);
+                    match (kind, &destination) {
+                        (FileOperationKind::Rename | FileOperationKind::Move, Some(destination)) => {
+                            self.index_moved(&source, destination).await
+                        }
+                        (FileOperationKind::Copy, Some(destination)) => self.index_changed(destination).await,
+                        _ => self.index_changed(&source).await,
+                    }
+                    report.completed.push(FileOperationStep {
+                        source: source.to_string_lossy().to_string(),
+                        destination: destination.map(|path| path.to_string_lossy().to_string()),
+                    });
+                }
+                Err(e) => report.failed.push(FileOperationFailure {
+                    path: source.to_string_lossy().to_string(),
+                    error: e.to_string(),
+                }),
+            }
+        }
+
+        if !report.completed.is_empty() {
+            report.operation_id = Some(self.db.record_file_operation(kind, &report.completed, JOURNAL_LENGTH).await?);
+        }
+        Ok(report)
+    }
+
+    async fn index_moved(&self, from: &Path, to: &Path) {
+        if let Some(indexer) = &self.indexer {
+            if let Err(e) = indexer.record_move(from, to).await {
+                warn!("Failed to update the index for {}: {}", to.display(), e);
+            }
+        }
+    }
+
+    async fn index_changed(&self, path: &Path) {
+        if let Some(indexer) = &self.indexer {
+            if let Err(e) = indexer.record_change(path).await {
+                warn!("Failed to update the index for {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Each of `paths` paired with the path of the same name in the folder `destination`
+fn into_folder(paths: &[String], destination: &str) -> Result<Vec<(PathBuf, Option<PathBuf>)>> {
+    let folder = Path::new(destination);
+    if !folder.is_dir() {
+        bail!("'{}' is not a folder", destination);
+    }
+    paths
+        .iter()
+        .map(|path| {
+            let source = PathBuf::from(path);
+            let name = source.file_name().ok_or_else(|| anyhow!("'{}' cannot be moved or copied", path))?;
+            let target = folder.join(name);
+            Ok((source, Some(target)))
+        })
+        .collect()
+}
+
+fn apply(kind: FileOperationKind, source: &Path, destination: Option<&Path>) -> Result<()> {
+    match (kind, destination) {
+        (FileOperationKind::Trash, _) => Ok(trash::delete(source)?),
+        (FileOperationKind::Copy, Some(destination)) => copy_path(source, destination),
+        (FileOperationKind::Rename | FileOperationKind::Move, Some(destination)) => move_path(source, destination),
+        (_, None) => bail!("No destination for '{}'", source.display()),
+    }
+}
+
+fn undo(kind: FileOperationKind, step: &FileOperationStep) -> Result<()> {
+    let source = Path::new(&step.source);
+    match (kind, step.destination.as_deref().map(Path::new)) {
+        (FileOperationKind::Trash, _) => restore(source),
+        (FileOperationKind::Copy, Some(destination)) => Ok(trash::delete(destination)?),
+        (FileOperationKind::Rename | FileOperationKind::Move, Some(destination)) => move_path(destination, source),
+        (_, None) => bail!("No destination recorded for '{}'", step.source),
+    }
+}
+
+/// Move `source` to `destination`, copying it across volumes; never replaces a file
+fn move_path(source: &Path, destination: &Path) -> Result<()> {
+    let renamed = match check_destination(source, destination)? {
+        true => fs::rename(source, destination),
+        false => rename_no_replace(source, destination),
+    };
+    match renamed {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            copy_path(source, destination)?;
+            match fs::symlink_metadata(source)?.is_dir() {
+                true => fs::remove_dir_all(source)?,
+                false => fs::remove_file(source)?,
+            }
+            Ok(())
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Copy `source`, and everything below it, to `destination`; never replaces a file. A
+/// copy that fails halfway is removed again.
+fn copy_path(source: &Path, destination: &Path) -> Result<()> {
+    check_destination(source, destination)?;
+    if let Err(e) = copy_recursive(source, destination) {
+        let _ = match fs::symlink_metadata(destination).map(|metadata| metadata.is_dir()) {
+            Ok(true) => fs::remove_dir_all(destination),
+            Ok(false) => fs::remove_file(destination),
+            Err(_) => Ok(()),
+        };
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Returns whether `destination` is `source` itself under another case
+fn check_destination(source: &Path, destination: &Path) -> Result<bool> {
+    fs::symlink_metadata(source)?;
+    if destination == source {
+        bail!("'{}' is already there", source.display());
+    }
+    if destination.starts_with(source) {
+        bail!("'{}' cannot be put inside itself", source.display());
+    }
+    // On case-insensitive volumes a change of case finds the source itself
+    let exists = fs::symlink_metadata(destination).is_ok();
+    let same_file = exists && fs::canonicalize(source).ok() == fs::canonicalize(destination).ok();
+    if exists && !same_file {
+        bail!("'{}' already exists", destination.display());
+    }
+    Ok(same_file)
+}
+
+/// Rename that fails rather than replaces a file created after `check_destination`
+#[cfg(target_os = "linux")]
+fn rename_no_replace(source: &Path, destination: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let from = CString::new(source.as_os_str().as_bytes())?;
+    let to = CString::new(destination.as_os_str().as_bytes())?;
+    // SAFETY: both paths are NUL-terminated and outlive the call
+    let result = unsafe {
+        libc::renameat2(libc::AT_FDCWD, from.as_ptr(), libc::AT_FDCWD, to.as_ptr(), libc::RENAME_NOREPLACE)
+    };
+    if result == 0 {
+        return Ok(());
+    }
+    match io::Error::last_os_error() {
+        // File systems without the flag fall back to checking first
+        e if matches!(e.raw_os_error(), Some(libc::EINVAL | libc::ENOSYS)) => fs::rename(source, destination),
+        e => Err(e),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn rename_no_replace(source: &Path, destination: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let from = CString::new(source.as_os_str().as_bytes())?;
+    let to = CString::new(destination.as_os_str().as_bytes())?;
+    // SAFETY: both paths are NUL-terminated and outlive the call
+    let result = unsafe { libc::renamex_np(from.as_ptr(), to.as_ptr(), libc::RENAME_EXCL) };
+    if result == 0 {
+        return Ok(());
+    }
+    match io::Error::last_os_error() {
+        e if e.raw_os_error() == Some(libc::ENOTSUP) => fs::rename(source, destination),
+        e => Err(e),
+    }
+}
+
+#[cfg(windows)]
+fn rename_no_replace(source: &Path, destination: &Path) -> io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+
+    let wide = |path: &Path| path.as_os_str().encode_wide().chain(Some(0)).collect::<Vec<u16>>();
+    let (from, to) = (wide(source), wide(destination));
+    // Without MOVEFILE_REPLACE_EXISTING an existing destination is an error
+    // SAFETY: both paths are NUL-terminated and outlive the call
+    match unsafe { winapi::um::winbase::MoveFileExW(from.as_ptr(), to.as_ptr(), 0) } {
+        0 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn rename_no_replace(source: &Path, destination: &Path) -> io::Result<()> {
+    fs::rename(source, destination)
+}
+
+fn copy_recursive(source: &Path, destination: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(source)?;
+    if metadata.is_dir() {
+        fs::create_dir(destination)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &destination.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else if metadata.file_type().is_symlink() {
+        copy_link(source, destination)
+    } else {
+        fs::copy(source, destination).map(|_| ())
+    }
+}
+
+#[cfg(unix)]
+fn copy_link(source: &Path, destination: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(source)?, destination)
+}
+
+#[cfg(not(unix))]
+fn copy_link(source: &Path, destination: &Path) -> io::Result<()> {
+    fs::copy(source, destination).map(|_| ())
+}
+
+/// Put the most recently trashed file that was at `path` back
+#[cfg(any(
+    target_os = "windows",
+    all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+))]
+fn restore(path: &Path) -> Result<()> {
+    if fs::symlink_metadata(path).is_ok() {
+        bail!("'{}' already exists", path.display());
+    }
+    let item = trash::os_limited::list()?
+        .into_iter()
+        .filter(|item| item.original_path() == path)
+        .max_by_key(|item| item.time_deleted)
+        .ok_or_else(|| anyhow!("'{}' is no longer in the trash", path.display()))?;
+    Ok(trash::os_limited::restore_all([item])?)
+}
+
+#[cfg(not(any(
+    target_os = "windows",
+    all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+)))]
+fn restore(path: &Path) -> Result<()> {
+    bail!("'{}' cannot be restored from the trash on this platform", path.display())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_operations_update_the_index_and_can_be_undone() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        std::fs::create_dir_all(root.join("docs/inner")).unwrap();
+        std::fs::create_dir(root.join("archive")).unwrap();
+        std::fs::write(root.join("notes.txt"), "notes").unwrap();
+        std::fs::write(root.join("docs/inner/plan.md"), "plan").unwrap();
+
+        let db = Arc::new(Database::new(&dir.path().join("index.db")).await.unwrap());
+        let mut indexer = FileIndexer::new(db.clone());
+        indexer.add_indexed_path(root.clone());
+        indexer.start_initial_indexing().await.unwrap();
+        let operations = FileOperations::new(db.clone(), Some(Arc::new(indexer)));
+        let indexed = |path: PathBuf| {
+            let db = db.clone();
+            async move { db.get_entry(&path.to_string_lossy()).await.unwrap().is_some() }
+        };
+        let path = |relative: &str| root.join(relative).to_string_lossy().to_string();
+
+        let copied = operations.copy_to(&[path("docs")], &path("archive")).await.unwrap();
+        assert_eq!(copied.completed.len(), 1);
+        assert!(indexed(root.join("archive/docs/inner/plan.md")).await);
+
+        let renamed = operations.rename(&path("notes.txt"), "todo.txt").await.unwrap();
+        assert!(renamed.failed.is_empty());
+        assert!(!indexed(root.join("notes.txt")).await && indexed(root.join("todo.txt")).await);
+        assert!(operations.rename(&path("todo.txt"), "../todo.txt").await.is_err());
+
+        // Each file is handled on its own; one that is in the way does not stop the rest
+        std::fs::write(root.join("archive/todo.txt"), "in the way").unwrap();
+        let moved = operations
+            .move_to(&[path("todo.txt"), path("docs/inner"), path("docs")], &path("archive"))
+            .await
+            .unwrap();
+        let failed: Vec<_> = moved.failed.iter().map(|failure| failure.path.clone()).collect();
+        assert_eq!(failed, vec![path("todo.txt"), path("docs")], "existing files are never replaced");
+        assert_eq!(moved.completed[0].destination, Some(path("archive/inner")));
+        assert!(indexed(root.join("archive/inner/plan.md")).await && !indexed(root.join("docs/inner")).await);
+
+        let undone = operations.undo_last().await.unwrap().unwrap();
+        assert_eq!((undone.kind, undone.completed.len()), (FileOperationKind::Move, 1));
+        assert!(root.join("docs/inner/plan.md").exists() && !root.join("archive/inner").exists());
+        assert!(indexed(root.join("docs/inner/plan.md")).await && !indexed(root.join("archive/inner")).await);
+
+        operations.undo_last().await.unwrap().unwrap();
+        assert!(indexed(root.join("notes.txt")).await && !indexed(root.join("todo.txt")).await);
+
+        // Undone operations leave the journal
+        let journal = operations.journal(10).await.unwrap();
+        assert_eq!(journal.len(), 1);
+        assert_eq!(journal[0].kind, FileOperationKind::Copy);
+    }
+
+    #[tokio::test]
+    async fn test_keeping_the_name_does_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(Database::new(&dir.path().join("index.db")).await.unwrap());
+        let operations = FileOperations::new(db, None);
+        let notes = dir.path().join("notes.txt");
+        std::fs::write(&notes, "notes").unwrap();
+
+        let renamed = operations.rename(&notes.to_string_lossy(), "notes.txt").await.unwrap();
+        assert_eq!((renamed.operation_id, renamed.completed.len(), renamed.failed.len()), (None, 0, 0));
+        let folder = dir.path().to_string_lossy().to_string();
+        let moved = operations.move_to(&[notes.to_string_lossy().to_string()], &folder).await.unwrap();
+        assert!(moved.failed[0].error.contains("already there"));
+        assert!(operations.journal(10).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rename_never_replaces_a_file_that_appeared_after_the_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, destination) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+        std::fs::write(&source, "a").unwrap();
+        std::fs::write(&destination, "b").unwrap();
+
+        assert!(rename_no_replace(&source, &destination).is_err());
+        assert_eq!(std::fs::read_to_string(&destination).unwrap(), "b");
+        std::fs::remove_file(&destination).unwrap();
+        rename_no_replace(&source, &destination).unwrap();
+        assert_eq!(std::fs::read_to_string(&destination).unwrap(), "a");
+    }
+}
@@ -0,0 +1,222 @@
+//! Hashes of file contents, stored in the index so `hash:` can find every copy of a file
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::{debug, info, warn};
+
+use crate::config::HashConfig;
+use crate::database::Database;
+
+/// Entries fetched per round of `FileHasher::hash_pending`
+const PENDING_BATCH_SIZE: i64 = 200;
+/// Longest wait between two looks for changed files, in case a change was not signalled
+const RECHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Wait after a signal, so a burst of changes is hashed in one round
+const SETTLE_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    #[default]
+    Blake3,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// Name stored with each hash
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Sha256 => "sha256",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HashReport {
+    pub hashed: u64,
+    /// Files that could not be read
+    pub skipped: u64,
+}
+
+/// Fills in the hash of every indexed file, and of every file that changes afterwards
+pub struct FileHasher {
+    db: Arc<Database>,
+    config: HashConfig,
+    wake: Notify,
+}
+
+impl FileHasher {
+    pub fn new(db: Arc<Database>, config: HashConfig) -> Self {
+        Self {
+            db,
+            config,
+            wake: Notify::new(),
+        }
+    }
+
+    /// Hash every file that has no hash of its current size and modification time
+    pub async fn hash_pending(&self) -> Result<HashReport> {
+        let algorithm = self.config.algorithm;
+        let mut report = HashReport::default();
+
+        loop {
+            let pending = self.db.pending_hash_entries(algorithm, PENDING_BATCH_SIZE).await?;
+            if pending.is_empty() {
+                break;
+            }
+
+            let mut hashes = Vec::with_capacity(pending.len());
+            for entry in pending {
+                let path = entry.path.clone();
+                match tokio::task::spawn_blocking(move || hash_file(Path::new(&path), algorithm)).await? {
+                    Ok(hash) => {
+                        report.hashed += 1;
+                        hashes.push((entry, Some(hash)));
+                    }
+                    Err(e) => {
+                        debug!("Failed to hash {}: {}", entry.path, e);
+                        report.skipped += 1;
+                        hashes.push((entry, None));
+                    }
+                }
+            }
+            self.db.set_hashes(algorithm, &hashes).await?;
+        }
+
+        if report.hashed + report.skipped > 0 {
+            info!("Hashing completed: {} hashed, {} skipped", report.hashed, report.skipped);
+        }
+        Ok(report)
+    }
+
+    /// Signal that indexed files changed and need hashing
+    pub fn wake(&self) {
+        self.wake.notify_one();
+    }
+
+    /// Keep hashing new and changed files until the task is dropped
+    pub async fn run(&self) {
+        loop {
+            if let Err(e) = self.hash_pending().await {
+                warn!("Hashing failed: {}", e);
+            }
+            if tokio::time::timeout(RECHECK_INTERVAL, self.wake.notified()).await.is_ok() {
+                tokio::time::sleep(SETTLE_DELAY).await;
+            }
+        }
+    }
+}
+
+/// Hex hash of the contents of the file at `path`
+pub fn hash_file(path: &Path, algorithm: HashAlgorithm) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    match algorithm {
+        HashAlgorithm::Blake3 => {
+            let mut hasher = blake3::Hasher::new();
+            io::copy(&mut file, &mut hasher)?;
+            Ok(hasher.finalize().to_hex().to_string())
+        }
+        HashAlgorithm::Sha256 => {
+            let mut hasher = Sha256::new();
+            io::copy(&mut file, &mut hasher)?;
+            Ok(hex(&hasher.finalize()))
+        }
+    }
+}
+
+/// Whether the BLAKE3 or SHA-256 hash of the file at `path` starts with the lowercase
+/// `prefix`, for backends without stored hashes. The file is read once for both.
+pub fn file_has_hash(path: &Path, prefix: &str) -> bool {
+    let hashes = File::open(path).and_then(|mut file| {
+        let mut blake3 = blake3::Hasher::new();
+        let mut sha256 = Sha256::new();
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            blake3.write_all(&buffer[..read])?;
+            sha256.update(&buffer[..read]);
+        }
+        Ok([blake3.finalize().to_hex().to_string(), hex(&sha256.finalize())])
+    });
+    hashes.is_ok_and(|hashes| hashes.iter().any(|hash| hash.starts_with(prefix)))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{FileEntry, SearchQuery};
+
+    #[tokio::test]
+    async fn test_hashes_changed_files_and_finds_them_by_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(Database::new(&dir.path().join("index.db")).await.unwrap());
+        let mut entries = Vec::new();
+        for (name, contents) in [("a.bin", "artifact"), ("copy.bin", "artifact"), ("other.bin", "other")] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, contents).unwrap();
+            let metadata = std::fs::metadata(&path).unwrap();
+            entries.push(FileEntry {
+                id: FileEntry::file_id(&path, &metadata),
+                name: name.to_string(),
+                path: path.to_string_lossy().to_string(),
+                size: metadata.len() as i64,
+                modified: chrono::Utc::now(),
+                created: chrono::Utc::now(),
+                is_directory: false,
+                extension: Some("bin".to_string()),
+                attributes: 0,
+                score: None,
+                source: None,
+            });
+        }
+        db.batch_insert_file_entries(&entries).await.unwrap();
+
+        let config = HashConfig { enabled: true, algorithm: HashAlgorithm::Sha256 };
+        let hasher = FileHasher::new(db.clone(), config);
+        assert_eq!(hasher.hash_pending().await.unwrap().hashed, 3);
+        assert_eq!(hasher.hash_pending().await.unwrap().hashed, 0, "unchanged files are not hashed again");
+
+        // sha256("artifact")
+        let hash = "c7c5c1d70c5dec4416ab6158afd0b223ef40c29b1dc1f97ed9428b94d4cadb1c";
+        assert_eq!(hash_file(Path::new(&entries[0].path), HashAlgorithm::Sha256).unwrap(), hash);
+        let search = |text: String| {
+            let db = db.clone();
+            async move {
+                let query = SearchQuery {
+                    query: text,
+                    filters: Default::default(),
+                    limit: None,
+                    offset: None,
+                    sort: Default::default(),
+                };
+                let result = db.search(&query).await.unwrap();
+                result.entries.into_iter().map(|entry| entry.name).collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(search(format!("hash:{}", &hash[..12].to_uppercase())).await, vec!["a.bin", "copy.bin"]);
+        assert!(file_has_hash(Path::new(&entries[1].path), &hash[..12]));
+
+        // A changed file is hashed again
+        std::fs::write(&entries[1].path, "changed").unwrap();
+        let mut changed = entries[1].clone();
+        changed.size = 7;
+        db.insert_file_entry(&changed).await.unwrap();
+        assert_eq!(hasher.hash_pending().await.unwrap().hashed, 1);
+        assert_eq!(search(format!("hash:{}", hash)).await, vec!["a.bin"]);
+    }
+}
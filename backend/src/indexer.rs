@@ -1,21 +1,60 @@
 use anyhow::Result;
 use chrono::Utc;
+use notify::event::{ModifyKind, RenameMode};
 use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
 use tracing::{debug, error, info, warn};
-use uuid::Uuid;
 
+use crate::config::AppConfig;
+use crate::content::ContentIndexer;
+use crate::hashing::FileHasher;
 use crate::database::Database;
-use crate::types::{FileEntry, SearchError};
+use crate::locate;
+use crate::types::FileEntry;
+
+/// Watcher events buffered between the watcher thread and `process_events`
+const EVENT_CHANNEL_SIZE: usize = 4096;
+/// Quiet period that ends a batch of watcher events
+const EVENT_DEBOUNCE: Duration = Duration::from_millis(200);
+/// Upper bound on a batch, so a continuous stream of events is still applied
+const MAX_EVENT_BATCH: usize = 10_000;
+
+/// Changes applied to the index by a crawl or reconciliation pass
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReconcileReport {
+    pub added: u64,
+    pub updated: u64,
+    pub removed: u64,
+}
+
+impl std::ops::AddAssign for ReconcileReport {
+    fn add_assign(&mut self, other: Self) {
+        self.added += other.added;
+        self.updated += other.updated;
+        self.removed += other.removed;
+    }
+}
 
 pub struct FileIndexer {
     db: Arc<Database>,
     indexed_paths: HashSet<PathBuf>,
     exclude_patterns: Vec<String>,
-    watcher: Option<RecommendedWatcher>,
+    watcher: Mutex<Option<RecommendedWatcher>>,
+    /// Set when content indexing is enabled
+    content: Option<ContentIndexer>,
+    /// Set when hashing is enabled
+    hasher: Option<Arc<FileHasher>>,
+    /// Source of a rename whose destination event has not arrived yet, with its tracker
+    pending_rename: Mutex<Option<(PathBuf, Option<usize>)>>,
+    /// Roots that lost watcher events and must be reconciled
+    rescan_roots: Arc<Mutex<HashSet<PathBuf>>>,
+    /// Locate databases that new roots are seeded from, tried in order
+    locate_databases: Vec<PathBuf>,
 }
 
 impl FileIndexer {
@@ -23,21 +62,45 @@ impl FileIndexer {
         Self {
             db,
             indexed_paths: HashSet::new(),
-            exclude_patterns: vec![
-                "System Volume Information".to_string(),
-                "$Recycle.Bin".to_string(),
-                "Windows/WinSxS".to_string(),
-                "ProgramData/Microsoft".to_string(),
-                ".git".to_string(),
-                "node_modules".to_string(),
-                ".vs".to_string(),
-                ".vscode".to_string(),
-                "target".to_string(), // Rust build directory
-                "dist".to_string(),
-                "build".to_string(),
-            ],
-            watcher: None,
+            exclude_patterns: AppConfig::default().exclude_patterns,
+            watcher: Mutex::new(None),
+            content: None,
+            hasher: None,
+            pending_rename: Mutex::new(None),
+            rescan_roots: Arc::new(Mutex::new(HashSet::new())),
+            locate_databases: Vec::new(),
+        }
+    }
+
+    /// Create an indexer for the roots and exclude patterns in `config`
+    pub fn from_config(db: Arc<Database>, config: &AppConfig) -> Self {
+        let mut indexer = Self::new(db);
+        for root in &config.index_roots {
+            indexer.add_indexed_path(root.clone());
+        }
+
+        // Never index our own database, its writes would feed back through the watcher
+        let mut exclude_patterns = config.exclude_patterns.clone();
+        if let Some(database_dir) = config.database_path.parent() {
+            exclude_patterns.push(database_dir.to_string_lossy().to_string());
+        }
+        indexer.set_exclude_patterns(exclude_patterns);
+
+        if config.content.enabled {
+            indexer.content = Some(ContentIndexer::new(indexer.db.clone(), config.content.clone()));
+        }
+        if config.hashing.enabled {
+            indexer.hasher = Some(Arc::new(FileHasher::new(indexer.db.clone(), config.hashing.clone())));
+        }
+        if config.locate.enabled {
+            indexer.locate_databases = config.locate.databases.clone();
         }
+        indexer
+    }
+
+    /// The background hasher, when hashing is enabled
+    pub fn hasher(&self) -> Option<Arc<FileHasher>> {
+        self.hasher.clone()
     }
 
     pub fn add_indexed_path(&mut self, path: PathBuf) {
@@ -48,32 +111,263 @@ impl FileIndexer {
         self.exclude_patterns = patterns;
     }
 
-    pub async fn start_initial_indexing(&self) -> Result<()> {
+    /// Store the entries of roots that were never indexed from a locate database, so they
+    /// can be searched before they are crawled. Seeded roots count as indexed, and the next
+    /// `start_initial_indexing` reconciles them, which fills in sizes and dates.
+    pub async fn seed_new_roots(&self) -> Result<u64> {
+        if self.locate_databases.is_empty() {
+            return Ok(0);
+        }
+        let indexed_roots: HashSet<String> = self.db.indexed_roots().await?.into_iter().collect();
+        let mut seeded = 0;
+        for path in &self.indexed_paths {
+            let root = path.to_string_lossy().to_string();
+            if indexed_roots.contains(&root) || !path.exists() {
+                continue;
+            }
+            if let Some(count) = self.seed_from_locate(path).await? {
+                self.db.record_indexed_root(&root).await?;
+                seeded += count;
+            }
+        }
+        Ok(seeded)
+    }
+
+    /// Bring the index up to date with every root. Roots indexed by an earlier run are
+    /// reconciled against the stored entries, new roots are crawled in full.
+    pub async fn start_initial_indexing(&self) -> Result<ReconcileReport> {
         info!("Starting initial file indexing...");
 
+        let indexed_roots: HashSet<String> = self.db.indexed_roots().await?.into_iter().collect();
+        let mut report = ReconcileReport::default();
+
         for path in &self.indexed_paths {
             if path.exists() {
-                info!("Indexing path: {}", path.display());
-                self.index_directory_recursive(path).await?;
+                let root = path.to_string_lossy().to_string();
+                if indexed_roots.contains(&root) {
+                    info!("Reconciling path: {}", path.display());
+                    report += self.reconcile_directory(path).await?;
+                } else {
+                    info!("Indexing path: {}", path.display());
+                    report.added += self.index_directory_recursive(path).await?;
+                }
+                self.db.record_indexed_root(&root).await?;
             } else {
                 warn!("Path does not exist: {}", path.display());
             }
         }
 
-        info!("Initial indexing completed");
+        info!(
+            "Initial indexing completed: {} added, {} updated, {} removed",
+            report.added, report.updated, report.removed
+        );
+
+        if let Some(content) = &self.content {
+            content.index_pending().await?;
+        }
+        Ok(report)
+    }
+
+    /// Update the stored entries below `root` to match the disk. A directory's modification
+    /// time changes whenever an entry is added, removed or renamed in it, so only directories
+    /// whose time differs from the stored one are listed; elsewhere the stored files are
+    /// checked individually for changes.
+    pub async fn reconcile_directory(&self, root: &Path) -> Result<ReconcileReport> {
+        let mut report = ReconcileReport::default();
+        let mut updates = Vec::new();
+        let mut stack = vec![root.to_path_buf()];
+
+        while let Some(dir) = stack.pop() {
+            let dir_str = dir.to_string_lossy().to_string();
+            let stored = self.db.get_entry(&dir_str).await?;
+
+            let current = match self.create_file_entry(&dir).await {
+                Ok(current) if current.is_directory => current,
+                _ => {
+                    if let Some(stored) = &stored {
+                        report.removed += self.remove_stored(stored).await?;
+                    }
+                    continue;
+                }
+            };
+
+            let mut stored_children: HashMap<String, FileEntry> = self
+                .db
+                .children_of(&dir_str)
+                .await?
+                .into_iter()
+                .map(|entry| (entry.path.clone(), entry))
+                .collect();
+
+            let listing_changed = stored.as_ref().is_none_or(|stored| stored.modified != current.modified);
+            if listing_changed {
+                match tokio::fs::read_dir(&dir).await {
+                    Ok(mut entries) => {
+                        while let Ok(Some(dir_entry)) = entries.next_entry().await {
+                            let path = dir_entry.path();
+                            if !self.should_index_path(&path) {
+                                continue;
+                            }
+
+                            match stored_children.remove(path.to_string_lossy().as_ref()) {
+                                Some(stored_child) => {
+                                    self.reconcile_child(stored_child, &mut stack, &mut updates, &mut report)
+                                        .await?;
+                                }
+                                None => report.added += self.index_new_path(&path).await?,
+                            }
+                        }
+                    }
+                    Err(e) => warn!("Failed to read directory {}: {}", dir.display(), e),
+                }
+
+                // Whatever was not listed is gone
+                for stored_child in stored_children.into_values() {
+                    report.removed += self.remove_stored(&stored_child).await?;
+                }
+            } else {
+                for stored_child in stored_children.into_values() {
+                    if self.should_index_path(Path::new(&stored_child.path)) {
+                        self.reconcile_child(stored_child, &mut stack, &mut updates, &mut report).await?;
+                    } else {
+                        report.removed += self.remove_stored(&stored_child).await?;
+                    }
+                }
+            }
+
+            match stored {
+                None => {
+                    report.added += 1;
+                    updates.push(current);
+                }
+                Some(stored) if listing_changed => {
+                    report.updated += 1;
+                    updates.push(with_stored_id(&stored, current));
+                }
+                Some(_) => {}
+            }
+
+            if updates.len() >= 1000 {
+                self.db.batch_insert_file_entries(&updates).await?;
+                updates.clear();
+            }
+        }
+
+        if !updates.is_empty() {
+            self.db.batch_insert_file_entries(&updates).await?;
+        }
+
+        Ok(report)
+    }
+
+    /// Compare one stored entry with the disk. Directories are queued to be reconciled in turn.
+    async fn reconcile_child(
+        &self,
+        stored: FileEntry,
+        stack: &mut Vec<PathBuf>,
+        updates: &mut Vec<FileEntry>,
+        report: &mut ReconcileReport,
+    ) -> Result<()> {
+        let path = PathBuf::from(&stored.path);
+        let Ok(current) = self.create_file_entry(&path).await else {
+            report.removed += self.remove_stored(&stored).await?;
+            return Ok(());
+        };
+
+        if current.is_directory != stored.is_directory {
+            report.removed += self.remove_stored(&stored).await?;
+            report.added += self.index_new_path(&path).await?;
+        } else if current.is_directory {
+            stack.push(path);
+        } else if current.size != stored.size || current.modified != stored.modified {
+            report.updated += 1;
+            updates.push(with_stored_id(&stored, current));
+        }
+
         Ok(())
     }
 
-    pub async fn start_file_watching(&mut self) -> Result<mpsc::Receiver<Event>> {
-        let (tx, rx) = mpsc::channel(1000);
+    /// Store the entries below `root` from the first readable locate database, without
+    /// sizes or dates. Returns `None` when no database lists anything below it.
+    async fn seed_from_locate(&self, root: &Path) -> Result<Option<u64>> {
+        for database in &self.locate_databases {
+            let (file, prefix) = (database.clone(), root.to_path_buf());
+            let read = tokio::task::spawn_blocking(move || {
+                locate::read_locate_database(&file, |path| Path::new(path).starts_with(&prefix))
+            })
+            .await?;
+            let mut entries = match read {
+                Ok(entries) => entries,
+                // Usually only readable by the locate group, so this is expected
+                Err(e) => {
+                    debug!("Cannot seed from {}: {}", database.display(), e);
+                    continue;
+                }
+            };
+
+            // Everything between the root and the entry must be indexable too
+            entries.retain(|entry| {
+                Path::new(&entry.path)
+                    .ancestors()
+                    .take_while(|path| path.starts_with(root))
+                    .all(|path| self.should_index_path(path))
+            });
+            if entries.is_empty() {
+                return Ok(None);
+            }
+            for chunk in entries.chunks(1000) {
+                self.db.batch_insert_file_entries(chunk).await?;
+            }
+            info!("Seeded {} entries below {} from {}", entries.len(), root.display(), database.display());
+            return Ok(Some(entries.len() as u64));
+        }
+        Ok(None)
+    }
+
+    /// Index a path that has no stored entry, with everything below it
+    async fn index_new_path(&self, path: &Path) -> Result<u64> {
+        let is_dir = tokio::fs::symlink_metadata(path).await.map(|m| m.is_dir()).unwrap_or(false);
+        if is_dir {
+            return self.index_directory_recursive(path).await;
+        }
+
+        match self.create_file_entry(path).await {
+            Ok(entry) => {
+                self.db.insert_file_entry(&entry).await?;
+                Ok(1)
+            }
+            Err(_) => Ok(0),
+        }
+    }
+
+    /// Remove a stored entry and, for a directory, everything below it
+    async fn remove_stored(&self, entry: &FileEntry) -> Result<u64> {
+        let mut removed = self.db.delete_by_path(&entry.path).await?;
+        if entry.is_directory {
+            removed += self.db.delete_by_prefix(&entry.path).await?;
+        }
+        debug!("Removed from index: {}", entry.path);
+        Ok(removed)
+    }
+
+    /// Watch every root. The callback never blocks: when the channel is full the event is
+    /// dropped and its root queued for a rescan, which `process_events` picks up.
+    pub fn start_file_watching(&self) -> Result<mpsc::Receiver<Event>> {
+        let (tx, rx) = mpsc::channel(EVENT_CHANNEL_SIZE);
+        let roots: Vec<PathBuf> = self.indexed_paths.iter().cloned().collect();
+        let rescan_roots = self.rescan_roots.clone();
 
         let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
             match res {
-                Ok(event) => {
-                    if let Err(e) = tx.blocking_send(event) {
-                        error!("Failed to send file system event: {}", e);
+                Ok(event) => match tx.try_send(event) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(event)) => {
+                        for root in queue_rescan(&rescan_roots, &roots, &event.paths) {
+                            warn!("File system events are arriving faster than they are indexed, rescanning {}", root.display());
+                        }
                     }
-                }
+                    Err(TrySendError::Closed(_)) => {}
+                },
                 Err(e) => error!("File system watcher error: {}", e),
             }
         })?;
@@ -85,44 +379,225 @@ impl FileIndexer {
             }
         }
 
-        self.watcher = Some(watcher);
+        *self.watcher.lock().unwrap() = Some(watcher);
         Ok(rx)
     }
 
+    /// Apply watcher events until the channel closes. Events are gathered until the channel
+    /// has been quiet for `EVENT_DEBOUNCE`, so a burst touching one path many times costs a
+    /// single update. Roots that lost events are reconciled after each batch.
+    pub async fn process_events(&self, mut events: mpsc::Receiver<Event>) {
+        while let Some(first) = events.recv().await {
+            let mut batch = vec![first];
+            while batch.len() < MAX_EVENT_BATCH {
+                match tokio::time::timeout(EVENT_DEBOUNCE, events.recv()).await {
+                    Ok(Some(event)) => batch.push(event),
+                    Ok(None) | Err(_) => break,
+                }
+            }
+
+            if let Err(e) = self.apply_events(batch).await {
+                warn!("Failed to apply file system events: {}", e);
+            }
+            self.rescan_queued_roots().await;
+        }
+    }
+
+    async fn apply_events(&self, batch: Vec<Event>) -> Result<()> {
+        debug!("Applying {} file system events", batch.len());
+        let mut dirty = Vec::new();
+        let mut seen = HashSet::new();
+
+        for event in batch {
+            if event.need_rescan() {
+                // The platform dropped events, e.g. an inotify queue overflow
+                let roots: Vec<PathBuf> = self.indexed_paths.iter().cloned().collect();
+                for root in queue_rescan(&self.rescan_roots, &roots, &event.paths) {
+                    warn!("File system watcher lost events, rescanning {}", root.display());
+                }
+                continue;
+            }
+
+            match event.kind {
+                // Renames depend on what came before them, so apply everything up to here first
+                EventKind::Modify(ModifyKind::Name(_)) => {
+                    self.sync_paths(&mut dirty).await?;
+                    seen.clear();
+                    self.handle_file_system_event(event).await?;
+                }
+                EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => {
+                    for path in event.paths {
+                        if seen.insert(path.clone()) {
+                            dirty.push(path);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.sync_paths(&mut dirty).await?;
+
+        // Both sides of a rename arrive in the same batch
+        self.flush_pending_rename().await
+    }
+
     pub async fn handle_file_system_event(&self, event: Event) -> Result<()> {
         debug!("File system event: {:?}", event);
 
         match event.kind {
-            EventKind::Create(_) => {
-                for path in &event.paths {
-                    if self.should_index_path(path) {
-                        self.index_single_path(path).await?;
-                    }
+            EventKind::Modify(ModifyKind::Name(mode)) => self.handle_rename(mode, &event).await,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => {
+                self.flush_pending_rename().await?;
+                let mut paths = event.paths;
+                self.sync_paths(&mut paths).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// A rename source whose destination did not follow was moved out of the watched roots
+    async fn flush_pending_rename(&self) -> Result<()> {
+        let unmatched = self.pending_rename.lock().unwrap().take();
+        match unmatched {
+            Some((from, _)) => self.remove_from_index(&from).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Bring the stored entries for `paths` in line with the disk, whatever happened to them
+    async fn sync_paths(&self, paths: &mut Vec<PathBuf>) -> Result<()> {
+        if paths.is_empty() {
+            return Ok(());
+        }
+
+        self.flush_pending_rename().await?;
+
+        for path in paths.drain(..) {
+            self.sync_path(&path).await?;
+        }
+        Ok(())
+    }
+
+    async fn sync_path(&self, path: &Path) -> Result<()> {
+        if !path.exists() {
+            self.remove_from_index(path).await
+        } else if !self.should_index_path(path) {
+            Ok(())
+        } else if path.is_dir() && self.db.get_entry(&path.to_string_lossy()).await?.is_none() {
+            // A new directory may already have contents the watcher never reported
+            self.index_new_path(path).await.map(|_| ())
+        } else {
+            self.index_single_path(path).await
+        }
+    }
+
+    /// Update the index after the app itself created, changed or removed `path`, without
+    /// waiting for the watcher
+    pub async fn record_change(&self, path: &Path) -> Result<()> {
+        match self.is_under_root(path) {
+            true => self.sync_path(path).await,
+            false => Ok(()),
+        }
+    }
+
+    /// Update the index after the app itself moved `from` to `to`
+    pub async fn record_move(&self, from: &Path, to: &Path) -> Result<()> {
+        if self.is_under_root(to) {
+            self.apply_rename(from, to).await
+        } else if self.is_under_root(from) {
+            self.remove_from_index(from).await
+        } else {
+            Ok(())
+        }
+    }
+
+    fn is_under_root(&self, path: &Path) -> bool {
+        self.indexed_paths.iter().any(|root| path.starts_with(root))
+    }
+
+    async fn rescan_queued_roots(&self) {
+        let roots: Vec<PathBuf> = self.rescan_roots.lock().unwrap().drain().collect();
+        for root in roots {
+            match self.reconcile_directory(&root).await {
+                Ok(report) => info!(
+                    "Rescanned {}: {} added, {} updated, {} removed",
+                    root.display(),
+                    report.added,
+                    report.updated,
+                    report.removed
+                ),
+                Err(e) => error!("Failed to rescan {}: {}", root.display(), e),
+            }
+        }
+    }
+
+    /// Pair rename sources with their destinations. inotify tags both sides with a tracker and
+    /// also reports the pair as one event; Windows reports the two sides back to back.
+    async fn handle_rename(&self, mode: RenameMode, event: &Event) -> Result<()> {
+        match (mode, event.paths.as_slice()) {
+            (RenameMode::Both, [from, to]) => self.apply_rename(from, to).await,
+            (RenameMode::From, [from]) => {
+                let previous = self.pending_rename.lock().unwrap().replace((from.clone(), event.tracker()));
+                if let Some((previous, _)) = previous {
+                    self.remove_from_index(&previous).await?;
                 }
+                Ok(())
             }
-            EventKind::Modify(_) => {
-                for path in &event.paths {
-                    if self.should_index_path(path) {
-                        self.index_single_path(path).await?;
+            (RenameMode::To, [to]) => {
+                let pending = self.pending_rename.lock().unwrap().take();
+                match pending {
+                    Some((from, tracker)) if tracker == event.tracker() => self.apply_rename(&from, to).await,
+                    unmatched => {
+                        if let Some((from, _)) = unmatched {
+                            self.remove_from_index(&from).await?;
+                        }
+                        // Moved in from outside the watched roots
+                        if self.should_index_path(to) {
+                            self.index_new_path(to).await?;
+                        }
+                        Ok(())
                     }
                 }
             }
-            EventKind::Remove(_) => {
+            // Without the two sides paired up (FSEvents), each path is checked on its own
+            _ => {
                 for path in &event.paths {
-                    self.remove_from_index(path).await?;
+                    if !path.exists() {
+                        self.remove_from_index(path).await?;
+                    } else if self.should_index_path(path) {
+                        self.index_new_path(path).await?;
+                    }
                 }
+                Ok(())
             }
-            _ => {}
+        }
+    }
+
+    /// Rewrite the stored paths of a renamed or moved entry and everything below it
+    async fn apply_rename(&self, from: &Path, to: &Path) -> Result<()> {
+        if !self.should_index_path(to) {
+            return self.remove_from_index(from).await;
         }
 
+        let to_str = to.to_string_lossy();
+        let moved = self.db.rename_path(&from.to_string_lossy(), &to_str).await?;
+        if moved > 0 {
+            debug!("Moved {} entries: {} -> {}", moved, from.display(), to.display());
+        } else if self.db.get_entry(&to_str).await?.is_none() {
+            // The source was never indexed, e.g. it was excluded
+            self.index_new_path(to).await?;
+        }
         Ok(())
     }
 
-    async fn index_directory_recursive(&self, dir_path: &Path) -> Result<()> {
+    /// Crawl `dir_path` and insert everything below it. Returns the number of entries inserted.
+    async fn index_directory_recursive(&self, dir_path: &Path) -> Result<u64> {
         if !self.should_index_path(dir_path) {
-            return Ok(());
+            return Ok(0);
         }
 
+        let mut inserted = 0;
         let mut entries = Vec::new();
         let mut stack = vec![dir_path.to_path_buf()];
 
@@ -142,15 +617,16 @@ impl FileIndexer {
                     Ok(mut dir) => {
                         while let Ok(Some(dir_entry)) = dir.next_entry().await {
                             let path = dir_entry.path();
-                            
-                            if self.should_index_path(&path) {
-                                if path.is_dir() {
-                                    stack.push(path);
-                                } else {
-                                    if let Ok(entry) = self.create_file_entry(&path).await {
-                                        entries.push(entry);
-                                    }
-                                }
+                            if !self.should_index_path(&path) {
+                                continue;
+                            }
+
+                            // Don't follow symlinked directories to avoid cycles
+                            let is_dir = dir_entry.file_type().await.map(|t| t.is_dir()).unwrap_or(false);
+                            if is_dir {
+                                stack.push(path);
+                            } else if let Ok(entry) = self.create_file_entry(&path).await {
+                                entries.push(entry);
                             }
                         }
                     }
@@ -162,8 +638,9 @@ impl FileIndexer {
 
             // Batch insert entries every 1000 files for performance
             if entries.len() >= 1000 {
-                if let Err(e) = self.db.batch_insert_file_entries(&entries).await {
-                    error!("Failed to batch insert entries: {}", e);
+                match self.db.batch_insert_file_entries(&entries).await {
+                    Ok(()) => inserted += entries.len() as u64,
+                    Err(e) => error!("Failed to batch insert entries: {}", e),
                 }
                 entries.clear();
             }
@@ -171,30 +648,50 @@ impl FileIndexer {
 
         // Insert remaining entries
         if !entries.is_empty() {
-            if let Err(e) = self.db.batch_insert_file_entries(&entries).await {
-                error!("Failed to insert remaining entries: {}", e);
+            match self.db.batch_insert_file_entries(&entries).await {
+                Ok(()) => inserted += entries.len() as u64,
+                Err(e) => error!("Failed to insert remaining entries: {}", e),
             }
         }
 
-        Ok(())
+        Ok(inserted)
     }
 
     async fn index_single_path(&self, path: &Path) -> Result<()> {
         if let Ok(entry) = self.create_file_entry(path).await {
             self.db.insert_file_entry(&entry).await?;
             debug!("Indexed file: {}", path.display());
+
+            if let Some(content) = &self.content {
+                if let Err(e) = content.index_entry(&entry).await {
+                    warn!("Failed to index content of {}: {}", path.display(), e);
+                }
+            }
+            if let Some(hasher) = &self.hasher {
+                hasher.wake();
+            }
         }
         Ok(())
     }
 
     async fn remove_from_index(&self, path: &Path) -> Result<()> {
-        // TODO: Implement removal from database
-        debug!("Removed from index: {}", path.display());
+        // Some platforms report a path replaced in place as removed
+        if path.exists() {
+            if self.should_index_path(path) {
+                self.index_single_path(path).await?;
+            }
+            return Ok(());
+        }
+
+        // The path is gone, so whether it was a directory is unknown; clear anything below it too
+        let path_str = path.to_string_lossy();
+        let removed = self.db.delete_by_path(&path_str).await? + self.db.delete_by_prefix(&path_str).await?;
+        debug!("Removed {} entries from index: {}", removed, path.display());
         Ok(())
     }
 
     async fn create_file_entry(&self, path: &Path) -> Result<FileEntry> {
-        let metadata = tokio::fs::metadata(path).await?;
+        let metadata = tokio::fs::symlink_metadata(path).await?;
         let name = path
             .file_name()
             .and_then(|n| n.to_str())
@@ -209,43 +706,28 @@ impl FileIndexer {
             None
         };
 
-        let modified = metadata
-            .modified()?
-            .duration_since(std::time::UNIX_EPOCH)?
-            .as_secs();
-
-        let created = metadata
-            .created()
-            .unwrap_or(metadata.modified()?)
-            .duration_since(std::time::UNIX_EPOCH)?
-            .as_secs();
+        // Full precision, so a directory changed in the second it was crawled still differs
+        let modified: chrono::DateTime<Utc> = metadata.modified()?.into();
+        let created: chrono::DateTime<Utc> = metadata.created().unwrap_or(metadata.modified()?).into();
 
         Ok(FileEntry {
-            id: Uuid::new_v4(),
+            id: FileEntry::file_id(path, &metadata),
             name,
             path: path.to_string_lossy().to_string(),
             size: metadata.len() as i64,
-            modified: chrono::DateTime::from_timestamp(modified as i64, 0)
-                .unwrap_or_default()
-                .with_timezone(&Utc),
-            created: chrono::DateTime::from_timestamp(created as i64, 0)
-                .unwrap_or_default()
-                .with_timezone(&Utc),
+            modified,
+            created,
             is_directory: metadata.is_dir(),
             extension,
-            attributes: 0, // TODO: Extract Windows file attributes
-            indexed_at: Utc::now(),
+            attributes: windows_integration::get_windows_attributes(path),
+            score: None,
+            source: None,
         })
     }
 
     fn should_index_path(&self, path: &Path) -> bool {
-        let path_str = path.to_string_lossy().to_lowercase();
-
-        // Check exclude patterns
-        for pattern in &self.exclude_patterns {
-            if path_str.contains(&pattern.to_lowercase()) {
-                return false;
-            }
+        if is_excluded(path, &self.exclude_patterns) {
+            return false;
         }
 
         // Skip system files and hidden files by default
@@ -256,12 +738,48 @@ impl FileIndexer {
             }
         }
 
+        if windows_integration::is_hidden_or_system(windows_integration::get_windows_attributes(path)) {
+            return false;
+        }
+
         true
     }
 }
 
+/// `current` under the id of the stored entry it updates, so usage stays attached. Seeded
+/// entries were stored under a path id for want of metadata, and take the real one.
+fn with_stored_id(stored: &FileEntry, current: FileEntry) -> FileEntry {
+    if stored.id == FileEntry::path_id(Path::new(&stored.path)) {
+        return current;
+    }
+    FileEntry { id: stored.id.clone(), ..current }
+}
+
+/// Queue the roots containing `paths`, or every root if the paths are unknown. Returns the
+/// roots that were not already queued.
+fn queue_rescan(queue: &Mutex<HashSet<PathBuf>>, roots: &[PathBuf], paths: &[PathBuf]) -> Vec<PathBuf> {
+    let affected: Vec<&PathBuf> = roots
+        .iter()
+        .filter(|root| paths.iter().any(|path| path.starts_with(root)))
+        .collect();
+    let affected = if affected.is_empty() { roots.iter().collect() } else { affected };
+
+    let mut queue = queue.lock().unwrap();
+    affected
+        .into_iter()
+        .filter(|root| queue.insert((*root).clone()))
+        .cloned()
+        .collect()
+}
+
+/// Whether `path` contains any of the exclude patterns, ignoring case
+pub(crate) fn is_excluded(path: &Path, patterns: &[String]) -> bool {
+    let path_str = path.to_string_lossy().to_lowercase();
+    patterns.iter().any(|pattern| path_str.contains(&pattern.to_lowercase()))
+}
+
 #[cfg(windows)]
-mod windows_integration {
+pub(crate) mod windows_integration {
     use super::*;
     use winapi::um::fileapi::GetFileAttributesW;
     use winapi::um::winnt::{FILE_ATTRIBUTE_HIDDEN, FILE_ATTRIBUTE_SYSTEM};
@@ -291,7 +809,7 @@ mod windows_integration {
 }
 
 #[cfg(not(windows))]
-mod windows_integration {
+pub(crate) mod windows_integration {
     use super::*;
 
     pub fn get_windows_attributes(_path: &Path) -> i32 {
@@ -302,3 +820,253 @@ mod windows_integration {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    /// Backdate `path` so that changes made by the test land in a later second
+    fn backdate(path: &Path) {
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        std::fs::File::open(path).unwrap().set_modified(an_hour_ago).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_only_applies_changes() {
+        let dir = tempfile::Builder::new().prefix("index").tempdir().unwrap();
+        let root = dir.path().join("root");
+        std::fs::create_dir_all(root.join("docs/old")).unwrap();
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        for file in ["docs/a.md", "docs/old/b.md", "src/main.rs"] {
+            std::fs::write(root.join(file), "v1").unwrap();
+        }
+        for path in ["docs/a.md", "docs/old/b.md", "src/main.rs", "docs/old", "docs", "src", ""] {
+            backdate(&root.join(path));
+        }
+
+        let db = Arc::new(Database::new(&dir.path().join("index.db")).await.unwrap());
+        let mut indexer = FileIndexer::new(db.clone());
+        indexer.set_exclude_patterns(Vec::new());
+        indexer.add_indexed_path(root.clone());
+
+        let report = indexer.start_initial_indexing().await.unwrap();
+        assert_eq!(report, ReconcileReport { added: 7, updated: 0, removed: 0 });
+        let report = indexer.start_initial_indexing().await.unwrap();
+        assert_eq!(report, ReconcileReport::default());
+
+        let main_rs = root.join("src/main.rs").to_string_lossy().to_string();
+        let main_id = db.get_entry(&main_rs).await.unwrap().unwrap().id;
+        std::fs::write(root.join("src/main.rs"), "version 2").unwrap();
+        std::fs::remove_dir_all(root.join("docs/old")).unwrap();
+        std::fs::write(root.join("docs/c.md"), "new").unwrap();
+
+        let report = indexer.start_initial_indexing().await.unwrap();
+        // docs changed its listing and main.rs its contents; docs/old and b.md are gone
+        assert_eq!(report, ReconcileReport { added: 1, updated: 2, removed: 2 });
+        let main = db.get_entry(&main_rs).await.unwrap().unwrap();
+        assert_eq!((main.id, main.size), (main_id, 9));
+        assert!(db.get_entry(&root.join("docs/old/b.md").to_string_lossy()).await.unwrap().is_none());
+        assert_eq!(db.children_of(&root.join("docs").to_string_lossy()).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_sees_changes_within_the_second_of_the_crawl() {
+        let dir = tempfile::Builder::new().prefix("index").tempdir().unwrap();
+        let root = dir.path().join("root");
+        std::fs::create_dir(&root).unwrap();
+        // A tenth into a whole second an hour ago, so both times fall in the same second
+        let an_hour_ago = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs() - 3600;
+        let second = SystemTime::UNIX_EPOCH + Duration::from_secs(an_hour_ago) + Duration::from_millis(100);
+        std::fs::File::open(&root).unwrap().set_modified(second).unwrap();
+
+        let db = Arc::new(Database::new(&dir.path().join("index.db")).await.unwrap());
+        let mut indexer = FileIndexer::new(db.clone());
+        indexer.add_indexed_path(root.clone());
+        indexer.start_initial_indexing().await.unwrap();
+
+        std::fs::write(root.join("late.txt"), "").unwrap();
+        let later = second + Duration::from_millis(500);
+        std::fs::File::open(&root).unwrap().set_modified(later).unwrap();
+        let report = indexer.start_initial_indexing().await.unwrap();
+        assert_eq!(report.added, 1);
+    }
+
+    #[tokio::test]
+    async fn test_a_file_moved_while_unwatched_keeps_its_usage() {
+        let dir = tempfile::Builder::new().prefix("index").tempdir().unwrap();
+        let root = dir.path().join("root");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("draft.txt"), "text").unwrap();
+        backdate(&root);
+
+        let db = Arc::new(Database::new(&dir.path().join("index.db")).await.unwrap());
+        let mut indexer = FileIndexer::new(db.clone());
+        indexer.add_indexed_path(root.clone());
+        indexer.start_initial_indexing().await.unwrap();
+        let id = db.get_entry(&root.join("draft.txt").to_string_lossy()).await.unwrap().unwrap().id;
+        db.record_file_open(&id).await.unwrap();
+
+        // The new path is indexed before the old one is dropped, both with the inode id
+        std::fs::rename(root.join("draft.txt"), root.join("final.txt")).unwrap();
+        let report = indexer.start_initial_indexing().await.unwrap();
+        assert_eq!((report.added, report.removed), (1, 1));
+        let moved = db.get_entry(&root.join("final.txt").to_string_lossy()).await.unwrap().unwrap();
+        assert_eq!(moved.id, id);
+        assert_eq!(db.file_usage(&[id.as_str()]).await.unwrap()[&id].open_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_renames_keep_ids() {
+        let dir = tempfile::Builder::new().prefix("index").tempdir().unwrap();
+        let root = dir.path().join("root");
+        std::fs::create_dir_all(root.join("old/sub")).unwrap();
+        std::fs::write(root.join("old/sub/a.txt"), "a").unwrap();
+        std::fs::write(root.join("b.txt"), "b").unwrap();
+
+        let db = Arc::new(Database::new(&dir.path().join("index.db")).await.unwrap());
+        let mut indexer = FileIndexer::new(db.clone());
+        indexer.set_exclude_patterns(Vec::new());
+        indexer.add_indexed_path(root.clone());
+        indexer.start_initial_indexing().await.unwrap();
+
+        let entry = |path: PathBuf| {
+            let db = db.clone();
+            async move { db.get_entry(&path.to_string_lossy()).await.unwrap() }
+        };
+        let rename = |mode: RenameMode, paths: Vec<PathBuf>, tracker: Option<usize>| {
+            let event = Event::new(EventKind::Modify(ModifyKind::Name(mode)));
+            let event = paths.into_iter().fold(event, Event::add_path);
+            match tracker {
+                Some(tracker) => event.set_tracker(tracker),
+                None => event,
+            }
+        };
+
+        // inotify: both sides, then the pair
+        let id = entry(root.join("old/sub/a.txt")).await.unwrap().id;
+        std::fs::rename(root.join("old"), root.join("new")).unwrap();
+        for event in [
+            rename(RenameMode::From, vec![root.join("old")], Some(7)),
+            rename(RenameMode::To, vec![root.join("new")], Some(7)),
+            rename(RenameMode::Both, vec![root.join("old"), root.join("new")], Some(7)),
+        ] {
+            indexer.handle_file_system_event(event).await.unwrap();
+        }
+        assert!(entry(root.join("old")).await.is_none());
+        let moved = entry(root.join("new/sub/a.txt")).await.unwrap();
+        assert_eq!((&moved.id, moved.name), (&id, "a.txt".to_string()));
+        // Any backend derives the same id from the file
+        let metadata = std::fs::symlink_metadata(root.join("new/sub/a.txt")).unwrap();
+        assert_eq!(FileEntry::file_id(&root.join("new/sub/a.txt"), &metadata), id);
+        assert_eq!(db.children_of(&root.join("new").to_string_lossy()).await.unwrap().len(), 1);
+
+        // Windows: untracked sides back to back, renaming a file changes its extension
+        let id = entry(root.join("b.txt")).await.unwrap().id;
+        std::fs::rename(root.join("b.txt"), root.join("b.md")).unwrap();
+        indexer.handle_file_system_event(rename(RenameMode::From, vec![root.join("b.txt")], None)).await.unwrap();
+        indexer.handle_file_system_event(rename(RenameMode::To, vec![root.join("b.md")], None)).await.unwrap();
+        let renamed = entry(root.join("b.md")).await.unwrap();
+        assert_eq!((renamed.id, renamed.extension), (id, Some("md".to_string())));
+
+        // A source without a destination left the watched roots
+        std::fs::rename(root.join("b.md"), dir.path().join("b.md")).unwrap();
+        indexer.handle_file_system_event(rename(RenameMode::From, vec![root.join("b.md")], None)).await.unwrap();
+        indexer.handle_file_system_event(Event::new(EventKind::Create(notify::event::CreateKind::File))).await.unwrap();
+        assert!(entry(root.join("b.md")).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_event_bursts_and_overflow() {
+        let dir = tempfile::Builder::new().prefix("index").tempdir().unwrap();
+        let root = dir.path().join("root");
+        std::fs::create_dir_all(&root).unwrap();
+        backdate(&root);
+
+        let db = Arc::new(Database::new(&dir.path().join("index.db")).await.unwrap());
+        let mut indexer = FileIndexer::new(db.clone());
+        indexer.set_exclude_patterns(Vec::new());
+        indexer.add_indexed_path(root.clone());
+        indexer.start_initial_indexing().await.unwrap();
+
+        let (tx, rx) = mpsc::channel(EVENT_CHANNEL_SIZE);
+        let modify = |path: PathBuf| Event::new(EventKind::Modify(ModifyKind::Any)).add_path(path);
+
+        // A burst of writes, a file created and deleted within it, and a new directory whose
+        // contents were never reported
+        std::fs::write(root.join("log.txt"), "done").unwrap();
+        std::fs::create_dir_all(root.join("pkg/lib")).unwrap();
+        std::fs::write(root.join("pkg/lib/index.js"), "").unwrap();
+        for _ in 0..100 {
+            tx.send(modify(root.join("log.txt"))).await.unwrap();
+        }
+        tx.send(modify(root.join("tmp.lock"))).await.unwrap();
+        tx.send(Event::new(EventKind::Create(notify::event::CreateKind::Folder)).add_path(root.join("pkg")))
+            .await
+            .unwrap();
+
+        // Written while the watcher was overflowing, so only the rescan finds it
+        std::fs::write(root.join("missed.txt"), "").unwrap();
+        tx.send(Event::new(EventKind::Other).set_flag(notify::event::Flag::Rescan)).await.unwrap();
+        drop(tx);
+
+        indexer.process_events(rx).await;
+
+        let stored = |name: &str| {
+            let db = db.clone();
+            let path = root.join(name).to_string_lossy().to_string();
+            async move { db.get_entry(&path).await.unwrap() }
+        };
+        assert_eq!(stored("log.txt").await.unwrap().size, 4);
+        assert!(stored("tmp.lock").await.is_none());
+        assert!(stored("pkg/lib/index.js").await.is_some());
+        assert!(stored("missed.txt").await.is_some());
+        assert!(indexer.rescan_roots.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_new_roots_are_seeded_from_locate() {
+        let dir = tempfile::Builder::new().prefix("index").tempdir().unwrap();
+        let root = dir.path().join("root");
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        std::fs::create_dir_all(root.join(".cache")).unwrap();
+        std::fs::write(root.join("docs/a.md"), "hello").unwrap();
+        std::fs::write(root.join("new.txt"), "").unwrap();
+
+        // Taken before new.txt was written and after gone.txt was deleted
+        let root_str = root.to_string_lossy().to_string();
+        let docs_str = root.join("docs").to_string_lossy().to_string();
+        let cache_str = root.join(".cache").to_string_lossy().to_string();
+        let database = dir.path().join("mlocate.db");
+        let listing: [(&str, &[(&str, bool)]); 3] = [
+            (&root_str, &[("docs", true), ("gone.txt", false), (".cache", true)]),
+            (&docs_str, &[("a.md", false)]),
+            (&cache_str, &[("blob", false)]),
+        ];
+        std::fs::write(&database, locate::mlocate_database("/", &listing)).unwrap();
+
+        let db = Arc::new(Database::new(&dir.path().join("index.db")).await.unwrap());
+        let mut indexer = FileIndexer::new(db.clone());
+        indexer.add_indexed_path(root.clone());
+        indexer.locate_databases = vec![dir.path().join("missing.db"), database];
+        let stored = |name: &str| {
+            let db = db.clone();
+            let path = root.join(name).to_string_lossy().to_string();
+            async move { db.get_entry(&path).await.unwrap() }
+        };
+
+        // docs, a.md and gone.txt; hidden entries are left out like in a crawl
+        assert_eq!(indexer.seed_new_roots().await.unwrap(), 3);
+        assert_eq!(stored("docs/a.md").await.unwrap().size, 0);
+        let report = indexer.start_initial_indexing().await.unwrap();
+        assert_eq!(report.removed, 1);
+        let a_md = root.join("docs/a.md");
+        let entry = stored("docs/a.md").await.unwrap();
+        let metadata = std::fs::symlink_metadata(&a_md).unwrap();
+        assert_eq!((entry.size, entry.id), (5, FileEntry::file_id(&a_md, &metadata)));
+        assert!(entry.modified > chrono::DateTime::<chrono::Utc>::UNIX_EPOCH);
+        assert!(stored("new.txt").await.is_some());
+        assert!(stored("gone.txt").await.is_none());
+        assert!(stored(".cache/blob").await.is_none());
+    }
+}
@@ -1,34 +1,129 @@
 pub mod types;
+pub mod config;
+pub mod backend;
+pub mod content;
 pub mod everything_sdk;
+pub mod export;
+pub mod fallback;
+pub mod filelist;
+pub mod fileops;
 pub mod search;
 pub mod database;
+pub mod duplicates;
+pub mod hashing;
+pub mod indexer;
+pub mod locate;
+pub mod resultset;
+pub mod stream;
+#[cfg(test)]
+mod test_support;
 
 use anyhow::Result;
+use std::path::Path;
 use std::sync::Arc;
-use tracing::info;
+use tracing::{error, info, warn};
 
 pub use types::*;
+pub use config::AppConfig;
+pub use backend::{BackendKind, BackendStatus, SearchBackend};
 pub use everything_sdk::EverythingSDK;
+use crate::database::Database;
+use crate::export::ExportOptions;
+use crate::fallback::FallbackSearch;
+use crate::filelist::FileListBackend;
+use crate::fileops::FileOperations;
+use crate::indexer::FileIndexer;
+use crate::resultset::ResultSetStore;
+use crate::search::cache::QueryCache;
+use crate::search::completion::Completer;
+use crate::search::history::SearchHistory;
+use crate::search::ranking::Ranker;
+use crate::search::saved::SavedSearches;
 use crate::search::SearchEngine;
+use crate::stream::CancelHandle;
 
 /// Main application structure
 pub struct EverythingClone {
     pub search_engine: Arc<SearchEngine>,
     pub everything_sdk: Arc<EverythingSDK>,
+    pub database: Arc<Database>,
+    /// Native indexer, present when the index backend is selected
+    pub indexer: Option<Arc<FileIndexer>>,
+    /// Result sets opened by clients, paged through by handle
+    pub result_sets: Arc<ResultSetStore>,
+    /// Rename, move, copy and trash files, with undo
+    pub file_operations: Arc<FileOperations>,
+    /// Imported Everything file lists, searched next to the selected backends
+    pub file_lists: Arc<FileListBackend>,
 }
 
 impl EverythingClone {
-    /// Initialize the application
+    /// Initialize the application from the configuration file
     pub async fn new() -> Result<Self> {
+        Self::with_config(AppConfig::load()?).await
+    }
+
+    /// Initialize the application with an explicit configuration
+    pub async fn with_config(config: AppConfig) -> Result<Self> {
         let mut sdk = EverythingSDK::new()?;
         sdk.initialize().await?;
         let sdk_arc = Arc::new(sdk);
-        
-        let search_engine = Arc::new(SearchEngine::new(sdk_arc.clone()));
-        
+
+        let database = Arc::new(Database::new(&config.database_path).await?);
+
+        let kinds = if config.backends.is_empty() {
+            if sdk_arc.is_available() {
+                vec![BackendKind::Everything]
+            } else {
+                vec![BackendKind::Index]
+            }
+        } else {
+            config.backends.clone()
+        };
+
+        let mut backends: Vec<Arc<dyn SearchBackend>> = Vec::new();
+        let mut indexer = None;
+        for kind in kinds {
+            match kind {
+                BackendKind::Everything if !sdk_arc.is_available() => {
+                    warn!("Everything backend selected but the DLL is not available, skipping");
+                }
+                BackendKind::Everything => backends.push(sdk_arc.clone()),
+                BackendKind::Index => {
+                    indexer.get_or_insert_with(|| {
+                        Arc::new(FileIndexer::from_config(database.clone(), &config))
+                    });
+                    backends.push(database.clone());
+                }
+                BackendKind::Walk => backends.push(Arc::new(FallbackSearch::from_config(&config))),
+            }
+            info!("Using search backend: {:?}", kind);
+        }
+
+        // Skipped by searches until a list is imported
+        let file_lists = Arc::new(FileListBackend::new(database.clone()).with_content(config.content.clone()));
+        if let Err(e) = file_lists.load_saved().await {
+            warn!("Failed to load file lists: {}", e);
+        }
+        backends.push(file_lists.clone());
+
+        let mut search_engine = SearchEngine::new(backends)
+            .with_history(SearchHistory::new(database.clone(), config.history.clone()))
+            .with_completer(Completer::new(database.clone()))
+            .with_cache(QueryCache::new(config.cache.capacity, config.cache.max_rows))
+            .with_saved_searches(SavedSearches::new(database.clone()));
+        if config.ranking.enabled {
+            search_engine = search_engine.with_ranker(Ranker::new(database.clone(), config.ranking.clone()));
+        }
+        let search_engine = Arc::new(search_engine);
         Ok(Self {
+            result_sets: Arc::new(ResultSetStore::new(search_engine.clone()).with_content(config.content.clone())),
+            file_operations: Arc::new(FileOperations::new(database.clone(), indexer.clone())),
+            file_lists,
             search_engine,
             everything_sdk: sdk_arc,
+            database,
+            indexer,
         })
     }
 
@@ -37,14 +132,99 @@ impl EverythingClone {
         self.search_engine.search(query).await
     }
 
+    /// Remember that the user opened `path`, so ranking can favour it
+    pub async fn record_open(&self, path: &str) -> Result<()> {
+        let path = Path::new(path);
+        let metadata = std::fs::symlink_metadata(path)?;
+        self.database.record_file_open(&FileEntry::file_id(path, &metadata)).await
+    }
+
+    /// Files of at least `min_size` bytes with identical contents, among the matches of
+    /// `query` or, without one, among all indexed files
+    pub async fn find_duplicates(
+        &self,
+        query: Option<&SearchQuery>,
+        min_size: u64,
+        progress: impl FnMut(DuplicateProgress),
+        cancel: &CancelHandle,
+    ) -> Result<DuplicateReport> {
+        let mut entries = match query {
+            Some(query) => {
+                let mut query = query.clone();
+                query.limit = Some(u32::MAX);
+                query.offset = None;
+                // Any order will do, and relevance ranking is wasted on every match
+                query.sort = Sort { field: SortField::Size, descending: false };
+                self.search_engine.search_uncached(&query).await?.entries
+            }
+            None => self.database.duplicate_candidates(min_size).await?,
+        };
+        // Entries of imported file lists are not on this machine to be read
+        entries.retain(|entry| entry.source.is_none());
+        duplicates::find_duplicates(entries, min_size, progress, cancel).await
+    }
+
+    /// Write every match of `query` to the file at `path`; returns the number written. The
+    /// matches are read once, neither ranked nor cached, and handed to the writer.
+    pub async fn export_search(&self, query: &SearchQuery, path: &Path, options: ExportOptions) -> Result<u64> {
+        let mut query = query.clone();
+        query.limit = Some(u32::MAX);
+        query.offset = None;
+        let result = self.search_engine.search_uncached(&query).await?;
+        export::export_entries(result.entries, path, options).await
+    }
+
+    /// Write the rows of result set `handle`, as currently sorted and filtered, to the file
+    /// at `path`; returns the number written
+    pub async fn export_result_set(&self, handle: u64, path: &Path, options: ExportOptions) -> Result<u64> {
+        export::export_result_set(self.result_sets.clone(), handle, path, options).await
+    }
+
     /// Get indexing statistics
     pub async fn get_stats(&self) -> Result<IndexStats> {
-        self.everything_sdk.get_stats().await
+        self.search_engine.stats().await
     }
 
-    /// Everything handles indexing automatically
+    /// Health and capabilities of the selected backends
+    pub async fn backend_status(&self) -> Vec<BackendStatus> {
+        self.search_engine.backend_status().await
+    }
+
+    /// Start the native indexer in the background; Everything handles indexing itself
     pub async fn start_indexing(&mut self) -> Result<()> {
-        info!("Everything SDK handles indexing automatically.");
+        let Some(indexer) = self.indexer.clone() else {
+            info!("Everything SDK handles indexing automatically.");
+            return Ok(());
+        };
+
+        tokio::spawn(async move {
+            // Registering watches can take a while, so make new roots searchable first
+            if let Err(e) = indexer.seed_new_roots().await {
+                warn!("Failed to seed from the locate database: {}", e);
+            }
+
+            // Watch before crawling so changes made during the crawl are not missed.
+            // Registering recursive watches walks the tree, so keep it off the async workers.
+            let watch_indexer = indexer.clone();
+            match tokio::task::spawn_blocking(move || watch_indexer.start_file_watching()).await {
+                Ok(Ok(events)) => {
+                    let watch_indexer = indexer.clone();
+                    tokio::spawn(async move { watch_indexer.process_events(events).await });
+                }
+                Ok(Err(e)) => error!("Failed to start file watching: {}", e),
+                Err(e) => error!("File watching task failed: {}", e),
+            }
+
+            if let Err(e) = indexer.start_initial_indexing().await {
+                error!("Initial indexing failed: {}", e);
+            }
+
+            // Hash the crawled files, then whatever changes later
+            if let Some(hasher) = indexer.hasher() {
+                hasher.run().await;
+            }
+        });
+
         Ok(())
     }
 }
@@ -52,13 +232,13 @@ impl EverythingClone {
 /// Initialize tracing for logging
 pub fn init_logging() -> Result<()> {
     use tracing_subscriber::filter::EnvFilter;
-    
+
     tracing_subscriber::fmt()
         .with_env_filter(
             EnvFilter::from_default_env()
                 .add_directive("everything_clone_backend=debug".parse()?)
         )
         .init();
-    
+
     Ok(())
 }
@@ -0,0 +1,277 @@
+//! The databases of `mlocate` and `plocate`, which most Linux systems refresh daily. They
+//! list paths and little else, so entries read from them have no size or dates; the indexer
+//! stores them as placeholders that the next reconciliation fills in.
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::Path;
+use zstd::dict::DecoderDictionary;
+
+use crate::types::FileEntry;
+
+const MLOCATE_MAGIC: &[u8] = b"\0mlocate";
+const PLOCATE_MAGIC: &[u8] = b"\0plocate";
+
+/// Entries of the mlocate or plocate database at `path` for which `keep` returns true
+pub fn read_locate_database(path: &Path, keep: impl FnMut(&str) -> bool) -> Result<Vec<FileEntry>> {
+    parse_locate_database(&std::fs::read(path)?, keep)
+}
+
+/// Entries of an mlocate or plocate database, told apart by their magic
+pub fn parse_locate_database(data: &[u8], mut keep: impl FnMut(&str) -> bool) -> Result<Vec<FileEntry>> {
+    match data.get(..8) {
+        Some(MLOCATE_MAGIC) => parse_mlocate(data, &mut keep),
+        Some(PLOCATE_MAGIC) => parse_plocate(data, &mut keep),
+        _ => bail!("Not an mlocate or plocate database"),
+    }
+}
+
+/// mlocate is big-endian: a header with the indexed root and the `updatedb.conf` it was
+/// built with, then one record per directory listing its files and subdirectories
+fn parse_mlocate(data: &[u8], keep: &mut dyn FnMut(&str) -> bool) -> Result<Vec<FileEntry>> {
+    let mut reader = Reader { data, pos: MLOCATE_MAGIC.len() };
+    let conf_size = u32::from_be_bytes(reader.array()?) as usize;
+    let version = reader.take(1)?[0];
+    if version != 0 {
+        bail!("Unsupported mlocate database version {}", version);
+    }
+    // Visibility flag and padding
+    reader.take(3)?;
+    let root = reader.c_str()?;
+    reader.take(conf_size)?;
+
+    let mut entries = Vec::new();
+    if keep(&root) {
+        entries.push(locate_entry(&root, true));
+    }
+    while reader.pos < data.len() {
+        // The directory's time, which the crawl replaces with its own
+        reader.take(16)?;
+        let dir = reader.c_str()?;
+        loop {
+            let is_directory = match reader.take(1)?[0] {
+                0 => false,
+                1 => true,
+                2 => break,
+                other => bail!("Corrupt mlocate database: unknown entry type {}", other),
+            };
+            let name = reader.c_str()?;
+            let path = match dir.ends_with('/') {
+                true => format!("{}{}", dir, name),
+                false => format!("{}/{}", dir, name),
+            };
+            if keep(&path) {
+                entries.push(locate_entry(&path, is_directory));
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// plocate is in the byte order of the machine that built it, in practice little-endian.
+/// Full paths are stored in zstd-compressed blocks, found through the filename index;
+/// the posting lists used for searching are skipped.
+fn parse_plocate(data: &[u8], keep: &mut dyn FnMut(&str) -> bool) -> Result<Vec<FileEntry>> {
+    let header = Reader { data, pos: 0 };
+    let version = u32::from_le_bytes(header.array_at(8)?);
+    if !(1..=2).contains(&version) {
+        bail!("Unsupported plocate database version {}", version);
+    }
+    let block_count = u32::from_le_bytes(header.array_at(20)?) as usize;
+    let index_offset = u64::from_le_bytes(header.array_at(32)?) as usize;
+    let dictionary_length = u32::from_le_bytes(header.array_at(44)?) as usize;
+    let dictionary_offset = u64::from_le_bytes(header.array_at(48)?) as usize;
+    let dictionary = header.slice(dictionary_offset, dictionary_length)?;
+    let dictionary = DecoderDictionary::copy(dictionary);
+
+    // Paths carry no type, so a path is taken for a directory when another is inside it.
+    // Empty directories come out as files until the crawl sees them. Only the parents of
+    // kept paths are collected, so reading a small root stays small.
+    let mut paths = Vec::new();
+    let mut directories: HashSet<String> = HashSet::new();
+    let mut block = Vec::new();
+    for index in 0..block_count {
+        let start = u64::from_le_bytes(header.array_at(index_offset + index * 8)?) as usize;
+        let end = u64::from_le_bytes(header.array_at(index_offset + (index + 1) * 8)?) as usize;
+        let compressed = header.slice(start, end.checked_sub(start).ok_or_else(corrupt)?)?;
+        block.clear();
+        zstd::stream::read::Decoder::with_prepared_dictionary(compressed, &dictionary)?.read_to_end(&mut block)?;
+
+        for path in block.split(|&byte| byte == 0).filter(|path| !path.is_empty()) {
+            let path = String::from_utf8_lossy(path);
+            if !keep(&path) {
+                continue;
+            }
+            if let Some(parent) = parent_of(&path) {
+                if !directories.contains(parent) {
+                    directories.insert(parent.to_string());
+                }
+            }
+            paths.push(path.into_owned());
+        }
+    }
+
+    Ok(paths
+        .iter()
+        .map(|path| locate_entry(path, directories.contains(path)))
+        .collect())
+}
+
+fn parent_of(path: &str) -> Option<&str> {
+    match path.rfind('/')? {
+        0 if path.len() > 1 => Some("/"),
+        0 => None,
+        slash => Some(&path[..slash]),
+    }
+}
+
+/// An entry whose size and dates are not known yet. Its id is path based, as metadata is
+/// needed for the real one.
+fn locate_entry(path: &str, is_directory: bool) -> FileEntry {
+    let path_ref = Path::new(path);
+    let name = match path_ref.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => path.to_string(),
+    };
+    let extension = match is_directory {
+        true => None,
+        false => path_ref.extension().map(|ext| ext.to_string_lossy().to_lowercase()),
+    };
+    FileEntry {
+        id: FileEntry::path_id(path_ref),
+        name,
+        path: path.to_string(),
+        size: 0,
+        modified: DateTime::<Utc>::UNIX_EPOCH,
+        created: DateTime::<Utc>::UNIX_EPOCH,
+        is_directory,
+        extension,
+        attributes: 0,
+        score: None,
+        source: None,
+    }
+}
+
+fn corrupt() -> anyhow::Error {
+    anyhow!("Corrupt locate database: offset out of range")
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn slice(&self, start: usize, len: usize) -> Result<&'a [u8]> {
+        let end = start.checked_add(len).ok_or_else(corrupt)?;
+        self.data.get(start..end).ok_or_else(corrupt)
+    }
+
+    fn array_at<const N: usize>(&self, start: usize) -> Result<[u8; N]> {
+        Ok(self.slice(start, N)?.try_into()?)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let taken = self.slice(self.pos, len)?;
+        self.pos += len;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into()?)
+    }
+
+    /// A NUL-terminated string; invalid UTF-8 is replaced
+    fn c_str(&mut self) -> Result<String> {
+        let rest = &self.data[self.pos..];
+        let len = rest
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or_else(|| anyhow!("Corrupt locate database: unterminated path"))?;
+        self.pos += len + 1;
+        Ok(String::from_utf8_lossy(&rest[..len]).into_owned())
+    }
+}
+
+/// An mlocate database of `directories`, each listing its files and subdirectories
+#[cfg(test)]
+pub(crate) fn mlocate_database(root: &str, directories: &[(&str, &[(&str, bool)])]) -> Vec<u8> {
+    let mut data = MLOCATE_MAGIC.to_vec();
+    data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);
+    data.extend_from_slice(root.as_bytes());
+    data.push(0);
+    for (dir, entries) in directories {
+        data.extend_from_slice(&[0; 16]);
+        data.extend_from_slice(dir.as_bytes());
+        data.push(0);
+        for (name, is_directory) in entries.iter() {
+            data.push(*is_directory as u8);
+            data.extend_from_slice(name.as_bytes());
+            data.push(0);
+        }
+        data.push(2);
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(entries: &[FileEntry]) -> Vec<(&str, bool)> {
+        entries.iter().map(|entry| (entry.path.as_str(), entry.is_directory)).collect()
+    }
+
+    #[test]
+    fn test_reads_mlocate_and_plocate_databases() {
+        let mlocate = mlocate_database(
+            "/",
+            &[
+                ("/", &[("home", true), ("swapfile", false)]),
+                ("/home", &[("me", true)]),
+                ("/home/me", &[("notes.TXT", false), ("music", true)]),
+                ("/home/me/music", &[]),
+            ],
+        );
+        let entries = parse_locate_database(&mlocate, |path| path.starts_with("/home")).unwrap();
+        assert_eq!(
+            paths(&entries),
+            vec![("/home", true), ("/home/me", true), ("/home/me/notes.TXT", false), ("/home/me/music", true)]
+        );
+        let notes = &entries[2];
+        assert_eq!((notes.name.as_str(), notes.extension.as_deref(), notes.size), ("notes.TXT", Some("txt"), 0));
+        assert_eq!(notes.id, FileEntry::path_id(Path::new("/home/me/notes.TXT")));
+
+        // Two blocks of paths after the header, then the filename index pointing at them
+        let blocks = [
+            zstd::encode_all(&b"/\0/home\0/home/me\0"[..], 3).unwrap(),
+            zstd::encode_all(&b"/home/me/a.rs\0/home/me/empty\0"[..], 3).unwrap(),
+        ];
+        let mut plocate = vec![0u8; 112];
+        plocate[..8].copy_from_slice(PLOCATE_MAGIC);
+        plocate[8..12].copy_from_slice(&1u32.to_le_bytes());
+        plocate[20..24].copy_from_slice(&(blocks.len() as u32).to_le_bytes());
+        let mut offsets = Vec::new();
+        for block in &blocks {
+            offsets.push(plocate.len() as u64);
+            plocate.extend_from_slice(block);
+        }
+        offsets.push(plocate.len() as u64);
+        let index_offset = plocate.len() as u64;
+        plocate[32..40].copy_from_slice(&index_offset.to_le_bytes());
+        for offset in offsets {
+            plocate.extend_from_slice(&offset.to_le_bytes());
+        }
+        let entries = parse_locate_database(&plocate, |path| path != "/").unwrap();
+        // Without types, only paths with something inside them are known to be directories
+        assert_eq!(
+            paths(&entries),
+            vec![("/home", true), ("/home/me", true), ("/home/me/a.rs", false), ("/home/me/empty", false)]
+        );
+
+        assert!(parse_locate_database(&mlocate[..mlocate.len() - 3], |_| true).is_err());
+        assert!(parse_locate_database(b"Filename,Size\r\n", |_| true).is_err());
+    }
+}
@@ -1,8 +1,13 @@
 use anyhow::Result;
 use everything_clone_backend::{EverythingClone, init_logging, SearchQuery, SearchFilters};
+use everything_clone_backend::export::{ExportColumn, ExportFormat, ExportOptions};
+use std::path::Path;
 use tokio::signal;
 use tracing::{info, error};
 
+const EXPORT_USAGE: &str =
+    "Usage: backend export <query> <output> [--format csv|tsv|jsonl|efu] [--columns name,path,size,...]";
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize logging
@@ -10,10 +15,16 @@ async fn main() -> Result<()> {
     
     info!("Starting Everything Plus...");
 
-    // Initialize the application (Everything SDK handles indexing automatically)
+    // Initialize the application (Everything SDK or the native index)
     let mut app = EverythingClone::new().await?;
 
-    // Start indexing (Everything SDK handles this automatically)
+    // `backend export ...` writes the matches of a query from the stored index and exits
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("export") {
+        return export(&app, &args[1..]).await;
+    }
+
+    // Start indexing (a no-op when Everything handles it)
     if let Err(e) = app.start_indexing().await {
         error!("Failed to start indexing: {}", e);
         return Err(e);
@@ -38,6 +49,7 @@ async fn main() -> Result<()> {
         },
         limit: Some(10),
         offset: None,
+        sort: Default::default(),
     };
 
     match app.search(&search_query).await {
@@ -58,4 +70,47 @@ async fn main() -> Result<()> {
     info!("Shutdown signal received, exiting...");
 
     Ok(())
-}
\ No newline at end of file
+}
+/// Write the matches of a query to a file, in the format its extension names unless
+/// `--format` is given
+async fn export(app: &EverythingClone, args: &[String]) -> Result<()> {
+    let usage = || anyhow::anyhow!(EXPORT_USAGE);
+    let parse = |value: &str| serde_json::Value::String(value.trim().to_lowercase());
+
+    let mut positional = Vec::new();
+    let mut format = None;
+    let mut options = ExportOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                let value = args.next().ok_or_else(usage)?;
+                format = Some(serde_json::from_value::<ExportFormat>(parse(value))?);
+            }
+            "--columns" => {
+                let value = args.next().ok_or_else(usage)?;
+                options.columns = value
+                    .split(',')
+                    .map(|column| serde_json::from_value::<ExportColumn>(parse(column)))
+                    .collect::<Result<_, _>>()?;
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let [query, output] = positional.as_slice() else {
+        return Err(usage());
+    };
+
+    let output = Path::new(output.as_str());
+    options.format = format.or_else(|| ExportFormat::from_path(output)).unwrap_or_default();
+    let search_query = SearchQuery {
+        query: query.to_string(),
+        filters: SearchFilters::default(),
+        limit: None,
+        offset: None,
+        sort: Default::default(),
+    };
+    let written = app.export_search(&search_query, output, options).await?;
+    info!("Exported {} results to {}", written, output.display());
+    Ok(())
+}
@@ -0,0 +1,288 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tracing::debug;
+
+use crate::config::ContentConfig;
+use crate::search::query::CompiledQuery;
+use crate::search::sort::Sorter;
+use crate::search::SearchEngine;
+use crate::stream::ResultSink;
+use crate::types::{FileEntry, ResultSetInfo, SearchFilters, SearchQuery, Sort, SortField};
+
+/// Rows materialized per result set; `total_count` still reports every match
+const MAX_RESULT_SET_ROWS: u32 = 100_000;
+/// Open result sets kept before the least recently used one is dropped
+const MAX_OPEN_RESULT_SETS: usize = 16;
+
+/// The rows of one query, with the refinement filter and sort applied as a view
+struct ResultSet {
+    query: SearchQuery,
+    entries: Vec<FileEntry>,
+    /// Indices into `entries` that pass the refinement filter, in sort order
+    view: Vec<usize>,
+    sorter: Sorter,
+    /// Whether `entries` are in the engine's relevance ranking, best first
+    ranked: bool,
+    total_count: u64,
+    query_time_ms: u64,
+    last_used: u64,
+}
+
+impl ResultSet {
+    fn info(&self, handle: u64) -> ResultSetInfo {
+        ResultSetInfo {
+            handle,
+            total_count: self.total_count,
+            row_count: self.view.len() as u64,
+            truncated: self.total_count > self.entries.len() as u64,
+            sort: self.sorter.sort(),
+            query_time_ms: self.query_time_ms,
+        }
+    }
+
+    /// Put `view` in the current sort order. Relevance keeps the engine's ranking when the
+    /// set holds it, since the ranking depends on more than the rows themselves.
+    fn order(&self, view: &mut [usize]) {
+        let sort = self.sorter.sort();
+        if self.ranked && sort.field == SortField::Relevance {
+            view.sort_unstable();
+            if sort.descending {
+                view.reverse();
+            }
+        } else {
+            self.sorter.sort_indices(&self.entries, view);
+        }
+    }
+}
+
+#[derive(Default)]
+struct OpenSets {
+    sets: HashMap<u64, ResultSet>,
+    next_handle: u64,
+    clock: u64,
+}
+
+impl OpenSets {
+    fn get(&mut self, handle: u64) -> Result<&mut ResultSet> {
+        self.clock += 1;
+        let clock = self.clock;
+        let set = self
+            .sets
+            .get_mut(&handle)
+            .ok_or_else(|| anyhow::anyhow!("Result set {} is not open", handle))?;
+        set.last_used = clock;
+        Ok(set)
+    }
+}
+
+/// Search results held behind handles, so a client can page through, re-sort and refine
+/// them without running the query again
+pub struct ResultSetStore {
+    engine: Arc<SearchEngine>,
+    open: Mutex<OpenSets>,
+    content: ContentConfig,
+}
+
+impl ResultSetStore {
+    pub fn new(engine: Arc<SearchEngine>) -> Self {
+        Self {
+            engine,
+            open: Mutex::new(OpenSets::default()),
+            content: ContentConfig::default(),
+        }
+    }
+
+    /// Read files for `content:` refinements as configured by `content`
+    pub fn with_content(mut self, content: ContentConfig) -> Self {
+        self.content = content;
+        self
+    }
+
+    /// Run `query` and hold its rows, in the query's sort order
+    pub async fn open(&self, query: &SearchQuery) -> Result<ResultSetInfo> {
+        self.open_with(query, None).await
+    }
+
+    /// Like `open`, but streams matches to `sink` while the query runs; nothing is held if
+    /// the sink is cancelled first
+    pub async fn open_streaming(&self, query: &SearchQuery, sink: &ResultSink) -> Result<ResultSetInfo> {
+        self.open_with(query, Some(sink)).await
+    }
+
+    async fn open_with(&self, query: &SearchQuery, sink: Option<&ResultSink>) -> Result<ResultSetInfo> {
+        // The sorter compiles the query, so `saved:` terms are expanded first
+        let mut query = self.engine.expand_query(query).await?;
+        query.limit = Some(MAX_RESULT_SET_ROWS);
+        query.offset = None;
+        let sorter = Sorter::for_query(&query)?;
+
+        // Rows are held best first, so either direction of relevance is a plain walk
+        let ranked = query.sort.field == SortField::Relevance;
+        let mut engine_query = query.clone();
+        engine_query.sort.descending &= !ranked;
+        let result = match sink {
+            Some(sink) => self.engine.search_streaming(&engine_query, sink).await?,
+            None => self.engine.search(&engine_query).await?,
+        };
+
+        let mut open = self.open.lock().unwrap();
+        if open.sets.len() >= MAX_OPEN_RESULT_SETS {
+            if let Some(oldest) = open.sets.iter().min_by_key(|(_, set)| set.last_used).map(|(handle, _)| *handle) {
+                debug!("Dropping least recently used result set {}", oldest);
+                open.sets.remove(&oldest);
+            }
+        }
+
+        open.next_handle += 1;
+        open.clock += 1;
+        let handle = open.next_handle;
+        let mut set = ResultSet {
+            query,
+            view: (0..result.entries.len()).collect(),
+            entries: result.entries,
+            sorter,
+            ranked,
+            total_count: result.total_count,
+            query_time_ms: result.query_time_ms,
+            last_used: open.clock,
+        };
+        let mut view = std::mem::take(&mut set.view);
+        set.order(&mut view);
+        set.view = view;
+        let info = set.info(handle);
+        open.sets.insert(handle, set);
+        Ok(info)
+    }
+
+    /// Up to `limit` rows of the set's current view, starting at `offset`
+    pub fn fetch(&self, handle: u64, offset: u64, limit: u64) -> Result<Vec<FileEntry>> {
+        let mut open = self.open.lock().unwrap();
+        let set = open.get(handle)?;
+        Ok(set
+            .view
+            .iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .map(|&i| set.entries[i].clone())
+            .collect())
+    }
+
+    pub fn sort(&self, handle: u64, sort: Sort) -> Result<ResultSetInfo> {
+        let mut open = self.open.lock().unwrap();
+        let set = open.get(handle)?;
+        if set.sorter.sort() != sort {
+            set.sorter = set.sorter.with_sort(sort);
+            let mut view = std::mem::take(&mut set.view);
+            set.order(&mut view);
+            set.view = view;
+        }
+        Ok(set.info(handle))
+    }
+
+    /// Narrow the set to the rows that also match `text`, written in the query language.
+    /// Each call replaces the previous refinement; empty text restores every row.
+    pub fn filter(&self, handle: u64, text: &str) -> Result<ResultSetInfo> {
+        let mut open = self.open.lock().unwrap();
+        let set = open.get(handle)?;
+
+        let refinement = SearchQuery {
+            query: text.trim().to_string(),
+            filters: SearchFilters {
+                case_sensitive: set.query.filters.case_sensitive,
+                use_regex: set.query.filters.use_regex,
+                // Rows the original filters let through stay visible
+                include_hidden: true,
+                ..Default::default()
+            },
+            limit: None,
+            offset: None,
+            sort: Default::default(),
+        };
+        let compiled = CompiledQuery::compile(&refinement)?.with_content(self.content.clone());
+
+        let mut view: Vec<usize> = (0..set.entries.len())
+            .filter(|&i| refinement.query.is_empty() || compiled.matches(&set.entries[i]))
+            .collect();
+        set.order(&mut view);
+        set.view = view;
+        Ok(set.info(handle))
+    }
+
+    /// Release a result set; closing an unknown handle is not an error
+    pub fn close(&self, handle: u64) {
+        self.open.lock().unwrap().sets.remove(&handle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use crate::test_support::{entry, query};
+
+    struct Fixture {
+        _dir: tempfile::TempDir,
+        store: ResultSetStore,
+        info: ResultSetInfo,
+    }
+
+    /// A result set of the three names containing "e", opened with a one-row limit
+    async fn fixture() -> Fixture {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(Database::new(&dir.path().join("index.db")).await.unwrap());
+        db.batch_insert_file_entries(&[
+            FileEntry { size: 300, ..entry("/r/report.pdf") },
+            FileEntry { size: 100, ..entry("/r/Budget.xlsx") },
+            FileEntry { size: 200, ..entry("/r/archive.zip") },
+            FileEntry { size: 400, ..entry("/r/todo.txt") },
+        ])
+        .await
+        .unwrap();
+
+        let store = ResultSetStore::new(Arc::new(SearchEngine::new(vec![db])));
+        let info = store.open(&SearchQuery { limit: Some(1), ..query("e") }).await.unwrap();
+        Fixture { _dir: dir, store, info }
+    }
+
+    fn names(rows: Vec<FileEntry>) -> Vec<String> {
+        rows.into_iter().map(|entry| entry.name).collect()
+    }
+
+    #[tokio::test]
+    async fn test_open_holds_every_match_despite_the_limit() {
+        let Fixture { _dir, info, .. } = fixture().await;
+        assert_eq!((info.total_count, info.row_count, info.truncated), (3, 3, false));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_pages_through_the_rows() {
+        let Fixture { _dir, store, info } = fixture().await;
+        assert_eq!(names(store.fetch(info.handle, 1, 10).unwrap()), vec!["Budget.xlsx", "report.pdf"]);
+    }
+
+    #[tokio::test]
+    async fn test_sort_reorders_the_held_rows() {
+        let Fixture { _dir, store, info } = fixture().await;
+        store.sort(info.handle, Sort { field: SortField::Size, descending: true }).unwrap();
+        assert_eq!(names(store.fetch(info.handle, 0, 2).unwrap()), vec!["report.pdf", "archive.zip"]);
+    }
+
+    #[tokio::test]
+    async fn test_filter_refines_in_the_current_order_and_empty_clears_it() {
+        let Fixture { _dir, store, info } = fixture().await;
+        store.sort(info.handle, Sort { field: SortField::Size, descending: true }).unwrap();
+
+        let refined = store.filter(info.handle, "ext:pdf|ext:xlsx").unwrap();
+        assert_eq!((refined.total_count, refined.row_count), (3, 2));
+        assert_eq!(names(store.fetch(info.handle, 0, 10).unwrap()), vec!["report.pdf", "Budget.xlsx"]);
+        assert_eq!(store.filter(info.handle, "").unwrap().row_count, 3);
+    }
+
+    #[tokio::test]
+    async fn test_closed_handles_are_gone() {
+        let Fixture { _dir, store, info } = fixture().await;
+        store.close(info.handle);
+        assert!(store.fetch(info.handle, 0, 10).is_err());
+    }
+}
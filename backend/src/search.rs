@@ -1,41 +1,291 @@
+pub mod query;
+pub mod fuzzy;
+pub mod sort;
+pub mod ranking;
+pub mod history;
+pub mod completion;
+pub mod cache;
+pub mod saved;
+
 use anyhow::Result;
-use regex::Regex;
+use futures::future::join_all;
+use std::collections::HashSet;
+use std::path::Path;
 use std::sync::Arc;
-use tracing::debug;
+use std::time::Instant;
+use tracing::{debug, warn};
 
-use crate::everything_sdk::EverythingSDK;
-use crate::types::{SearchQuery, SearchResult, SearchError};
+use crate::backend::{BackendStatus, SearchBackend};
+use crate::stream::ResultSink;
+use crate::types::{Completion, HistoryEntry, IndexStats, SavedSearch, SearchError, SearchQuery, SearchResult, SortField};
+use cache::QueryCache;
+use query::CompiledQuery;
+use completion::Completer;
+use history::SearchHistory;
+use ranking::{Ranker, RANK_CANDIDATES};
+use saved::SavedSearches;
+use sort::Sorter;
 
 pub struct SearchEngine {
-    sdk: Arc<EverythingSDK>,
+    backends: Vec<Arc<dyn SearchBackend>>,
+    ranker: Option<Ranker>,
+    history: Option<SearchHistory>,
+    completer: Option<Completer>,
+    cache: Option<QueryCache>,
+    saved: Option<SavedSearches>,
 }
 
 impl SearchEngine {
-    pub fn new(sdk: Arc<EverythingSDK>) -> Self {
-        Self { sdk }
+    pub fn new(backends: Vec<Arc<dyn SearchBackend>>) -> Self {
+        Self {
+            backends,
+            ranker: None,
+            history: None,
+            completer: None,
+            cache: None,
+            saved: None,
+        }
+    }
+
+    /// Rank relevance-sorted results with `ranker` instead of by match alone
+    pub fn with_ranker(mut self, ranker: Ranker) -> Self {
+        self.ranker = Some(ranker);
+        self
+    }
+
+    /// Complete queries with `completer`
+    pub fn with_completer(mut self, completer: Completer) -> Self {
+        self.completer = Some(completer);
+        self
+    }
+
+    /// Answer repeated and narrowing queries from `cache` while the backends are unchanged
+    pub fn with_cache(mut self, cache: QueryCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Resolve `saved:` terms from `saved`
+    pub fn with_saved_searches(mut self, saved: SavedSearches) -> Self {
+        self.saved = Some(saved);
+        self
+    }
+
+    /// Keep past queries in `history`
+    pub fn with_history(mut self, history: SearchHistory) -> Self {
+        self.history = Some(history);
+        self
     }
 
     pub async fn search(&self, query: &SearchQuery) -> Result<SearchResult> {
+        self.search_with(query, None).await
+    }
+
+    /// Like `search`, but streams matches to `sink` as backends find them, unsorted. Fails
+    /// with `SearchError::Cancelled` once the sink is cancelled.
+    pub async fn search_streaming(&self, query: &SearchQuery, sink: &ResultSink) -> Result<SearchResult> {
+        let result = self.search_with(query, Some(sink)).await;
+        if sink.is_cancelled() {
+            return Err(SearchError::Cancelled.into());
+        }
+        result
+    }
+
+    async fn search_with(&self, query: &SearchQuery, sink: Option<&ResultSink>) -> Result<SearchResult> {
         debug!("Performing search with query: {:?}", query);
         
         // Validate and preprocess query
-        let processed_query = self.preprocess_query(query)?;
-        
-        // Execute search using the SDK
-        self.sdk.search(&processed_query).await
+        let processed_query = self.preprocess_query(&self.expand_query(query).await?)?;
+
+        match &self.ranker {
+            Some(ranker) if processed_query.sort.field == SortField::Relevance => {
+                self.search_ranked(ranker, &processed_query, sink).await
+            }
+            _ => self.search_backends(&processed_query, sink).await,
+        }
     }
 
-    fn preprocess_query(&self, query: &SearchQuery) -> Result<SearchQuery> {
-        let mut processed = query.clone();
+    async fn search_backends(&self, query: &SearchQuery, sink: Option<&ResultSink>) -> Result<SearchResult> {
+        // Read before searching, so a change during the search invalidates its result
+        let cache = self.cache.as_ref().zip(self.generation());
+        if let Some((cache, generation)) = cache {
+            if let Some(result) = cache.get(query, generation) {
+                debug!("Answered query from the cache");
+                if let Some(sink) = sink {
+                    sink.send(result.entries.clone());
+                }
+                return Ok(result);
+            }
+        }
 
-        // Validate regex if enabled
-        if query.filters.use_regex {
-            if let Err(e) = Regex::new(&query.query) {
-                return Err(SearchError::InvalidQuery(
-                    format!("Invalid regex pattern: {}", e)
-                ).into());
+        let result = self.query_backends(query, sink).await?;
+
+        if let Some((cache, generation)) = cache {
+            if !sink.is_some_and(ResultSink::is_cancelled) {
+                cache.insert(query, generation, &result);
             }
         }
+        Ok(result)
+    }
+
+    /// Like `search`, but neither ranked nor cached, for scans that read a whole result
+    /// once and would only flood the cache
+    pub async fn search_uncached(&self, query: &SearchQuery) -> Result<SearchResult> {
+        let processed_query = self.preprocess_query(&self.expand_query(query).await?)?;
+        self.query_backends(&processed_query, None).await
+    }
+
+    async fn query_backends(&self, query: &SearchQuery, sink: Option<&ResultSink>) -> Result<SearchResult> {
+        let backends: Vec<Arc<dyn SearchBackend>> =
+            self.backends.iter().filter(|backend| !backend.is_empty()).cloned().collect();
+        match backends.as_slice() {
+            [] => Err(anyhow::anyhow!("No search backend is available")),
+            [backend] => search_backend(backend, query, sink).await,
+            backends => self.search_all(backends, query, sink).await,
+        }
+    }
+
+    /// Sum of the backends' change generations, which only grows; `None` when a backend
+    /// cannot tell whether it changed
+    fn generation(&self) -> Option<u64> {
+        self.backends.iter().map(|backend| backend.generation()).sum()
+    }
+
+    /// Rank the best `RANK_CANDIDATES` matches, followed by the rest in match order, then
+    /// return the requested page. The ranked rows are the same whatever the page, so offset
+    /// paging never repeats or skips a row. A sink gets the matches as found, unranked.
+    async fn search_ranked(
+        &self,
+        ranker: &Ranker,
+        query: &SearchQuery,
+        sink: Option<&ResultSink>,
+    ) -> Result<SearchResult> {
+        let start_time = Instant::now();
+        let offset = query.offset.unwrap_or(0) as usize;
+        let limit = query.limit.unwrap_or(1000) as usize;
+
+        let mut candidate_query = query.clone();
+        candidate_query.offset = None;
+        candidate_query.limit = Some(window_limit(offset.saturating_add(limit).max(RANK_CANDIDATES)));
+
+        let mut result = self.search_backends(&candidate_query, sink).await?;
+        let mut rest = result.entries.split_off(RANK_CANDIDATES.min(result.entries.len()));
+        if let Err(e) = ranker.rank(query, &mut result.entries).await {
+            warn!("Failed to rank results, keeping match order: {}", e);
+        }
+        result.entries.append(&mut rest);
+        result.entries = result.entries.into_iter().skip(offset).take(limit).collect();
+        result.query_time_ms = start_time.elapsed().as_millis() as u64;
+        Ok(result)
+    }
+
+    /// Query every backend and merge the results, dropping duplicate paths
+    async fn search_all(
+        &self,
+        backends: &[Arc<dyn SearchBackend>],
+        query: &SearchQuery,
+        sink: Option<&ResultSink>,
+    ) -> Result<SearchResult> {
+        let start_time = Instant::now();
+        let offset = query.offset.unwrap_or(0) as usize;
+        let limit = query.limit.unwrap_or(1000) as usize;
+
+        // Each backend has to supply enough rows to fill the requested page on its own
+        let mut backend_query = query.clone();
+        backend_query.offset = None;
+        backend_query.limit = Some(window_limit(offset.saturating_add(limit)));
+
+        let searches = backends.iter().map(|backend| search_backend(backend, &backend_query, sink));
+        let results = join_all(searches).await;
+
+        let mut seen = HashSet::new();
+        let mut entries = Vec::new();
+        let mut total_count = 0;
+        let mut last_error = None;
+
+        for (backend, result) in backends.iter().zip(results) {
+            match result {
+                Ok(result) => {
+                    total_count += result.total_count;
+                    for entry in result.entries {
+                        if seen.insert(entry.path.clone()) {
+                            entries.push(entry);
+                        } else {
+                            total_count = total_count.saturating_sub(1);
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!("Backend '{}' failed: {}", backend.name(), e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        if let Some(e) = last_error {
+            if seen.is_empty() {
+                return Err(e);
+            }
+        }
+
+        Sorter::for_query(query)?.sort_entries(&mut entries);
+        let entries = entries.into_iter().skip(offset).take(limit).collect();
+
+        Ok(SearchResult {
+            entries,
+            total_count,
+            query_time_ms: start_time.elapsed().as_millis() as u64,
+        })
+    }
+
+    /// Combined statistics of all backends; empty ones are left out, so they do not
+    /// report a last index time
+    pub async fn stats(&self) -> Result<IndexStats> {
+        let mut combined: Option<IndexStats> = None;
+
+        for backend in self.backends.iter().filter(|backend| !backend.is_empty()) {
+            let stats = match backend.stats().await {
+                Ok(stats) => stats,
+                Err(e) => {
+                    warn!("Failed to get stats from backend '{}': {}", backend.name(), e);
+                    continue;
+                }
+            };
+
+            combined = Some(match combined {
+                None => stats,
+                Some(mut total) => {
+                    total.total_files += stats.total_files;
+                    total.total_directories += stats.total_directories;
+                    total.index_size_bytes += stats.index_size_bytes;
+                    total.indexed_paths.extend(stats.indexed_paths);
+                    total.last_index_time = total.last_index_time.max(stats.last_index_time);
+                    total
+                }
+            });
+        }
+
+        combined.ok_or_else(|| anyhow::anyhow!("No search backend is available"))
+    }
+
+    /// Health and capabilities of every configured backend
+    pub async fn backend_status(&self) -> Vec<BackendStatus> {
+        let mut statuses = Vec::with_capacity(self.backends.len());
+        for backend in &self.backends {
+            statuses.push(BackendStatus {
+                name: backend.name().to_string(),
+                health: backend.health().await,
+                capabilities: backend.capabilities(),
+            });
+        }
+        statuses
+    }
+
+    fn preprocess_query(&self, query: &SearchQuery) -> Result<SearchQuery> {
+        let mut processed = query.clone();
+
+        // Reject malformed queries and regexes before they reach a backend
+        CompiledQuery::compile(query)?;
 
         // Clean up query string
         processed.query = query.query.trim().to_string();
@@ -48,40 +298,240 @@ impl SearchEngine {
         Ok(processed)
     }
 
-    pub async fn suggest_completions(&self, partial_query: &str) -> Result<Vec<String>> {
-        // TODO: Implement query completion suggestions
-        // This could use the FTS table to find common prefixes
-        
-        let suggestions = vec![
-            format!("{}*.txt", partial_query),
-            format!("{}*.pdf", partial_query),
-            format!("{}*.doc", partial_query),
-        ];
+    /// `query` with its `saved:` terms replaced by the searches they name; unchanged
+    /// without saved searches
+    pub async fn expand_query(&self, query: &SearchQuery) -> Result<SearchQuery> {
+        match &self.saved {
+            Some(saved) => saved.expand(query).await,
+            None => Ok(query.clone()),
+        }
+    }
+
+    /// Up to `limit` completions of a partly typed query; empty without a completer
+    pub async fn suggest_completions(&self, partial_query: &str, limit: usize) -> Result<Vec<Completion>> {
+        let Some(completer) = &self.completer else {
+            return Ok(Vec::new());
+        };
+        let history = self.get_recent_searches(usize::MAX).await?;
+        completer.complete(partial_query, &history, limit).await
+    }
+
+    /// Up to `limit` past queries, by frecency; empty without a history
+    pub async fn get_recent_searches(&self, limit: usize) -> Result<Vec<HistoryEntry>> {
+        match &self.history {
+            Some(history) => history.recent(limit).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub async fn save_search_history(&self, query: &SearchQuery) -> Result<()> {
+        match &self.history {
+            Some(history) => history.record(query).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Forget one past query; returns whether it was in the history
+    pub async fn delete_search_history(&self, query: &str) -> Result<bool> {
+        match &self.history {
+            Some(history) => history.delete(query).await,
+            None => Ok(false),
+        }
+    }
+
+    pub async fn clear_search_history(&self) -> Result<()> {
+        match &self.history {
+            Some(history) => history.clear().await,
+            None => Ok(()),
+        }
+    }
+
+    pub async fn saved_searches(&self) -> Result<Vec<SavedSearch>> {
+        match &self.saved {
+            Some(saved) => saved.list().await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub async fn create_saved_search(&self, search: &SavedSearch) -> Result<()> {
+        self.saved_store()?.create(search).await
+    }
+
+    pub async fn update_saved_search(&self, name: &str, search: &SavedSearch) -> Result<()> {
+        self.saved_store()?.update(name, search).await
+    }
+
+    /// Returns whether there was a saved search called `name`
+    pub async fn delete_saved_search(&self, name: &str) -> Result<bool> {
+        match &self.saved {
+            Some(saved) => saved.delete(name).await,
+            None => Ok(false),
+        }
+    }
+
+    /// Returns how many searches were imported from `path`
+    pub async fn import_saved_searches(&self, path: &Path) -> Result<usize> {
+        self.saved_store()?.import(path).await
+    }
+
+    /// Returns how many searches were exported to `path`
+    pub async fn export_saved_searches(&self, path: &Path) -> Result<usize> {
+        self.saved_store()?.export(path).await
+    }
 
-        Ok(suggestions)
+    fn saved_store(&self) -> Result<&SavedSearches> {
+        self.saved
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Saved searches are not available"))
     }
 
-    pub async fn get_recent_searches(&self) -> Result<Vec<String>> {
-        // TODO: Implement recent search history
-        Ok(Vec::new())
+    /// Stop or resume recording searches
+    pub fn set_private_mode(&self, private: bool) {
+        if let Some(history) = &self.history {
+            history.set_private(private);
+        }
+    }
+
+    pub fn private_mode(&self) -> bool {
+        self.history.as_ref().is_some_and(|history| history.is_private())
     }
+}
 
-    pub async fn save_search_history(&self, query: &str) -> Result<()> {
-        // TODO: Implement search history persistence
-        debug!("Saving search to history: {}", query);
-        Ok(())
+/// A row count as a query limit, clamped to the largest limit a query can carry
+fn window_limit(rows: usize) -> u32 {
+    u32::try_from(rows).unwrap_or(u32::MAX)
+}
+
+async fn search_backend(
+    backend: &Arc<dyn SearchBackend>,
+    query: &SearchQuery,
+    sink: Option<&ResultSink>,
+) -> Result<SearchResult> {
+    match sink {
+        Some(sink) => backend.search_streaming(query, sink).await,
+        None => backend.search(query).await,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::types::SearchFilters;
+    use crate::backend::{BackendCapabilities, BackendHealth};
+    use crate::test_support::query;
+    use crate::types::{FileEntry, SearchFilters};
+    use async_trait::async_trait;
+
+    struct StubBackend {
+        paths: Vec<String>,
+    }
+
+    #[async_trait]
+    impl SearchBackend for StubBackend {
+        fn name(&self) -> &'static str {
+            "stub"
+        }
+
+        fn capabilities(&self) -> BackendCapabilities {
+            BackendCapabilities::default()
+        }
+
+        async fn search(&self, query: &SearchQuery) -> Result<SearchResult> {
+            let entries: Vec<FileEntry> = self
+                .paths
+                .iter()
+                .take(query.limit.map_or(usize::MAX, |limit| limit as usize))
+                .map(|path| FileEntry {
+                    id: path.to_string(),
+                    name: path.rsplit('/').next().unwrap().to_string(),
+                    path: path.to_string(),
+                    size: 0,
+                    modified: chrono::Utc::now(),
+                    created: chrono::Utc::now(),
+                    is_directory: false,
+                    extension: None,
+                    attributes: 0,
+                    score: None,
+                    source: None,
+                })
+                .collect();
+            Ok(SearchResult { total_count: self.paths.len() as u64, entries, query_time_ms: 0 })
+        }
+
+        async fn stats(&self) -> Result<IndexStats> {
+            Err(anyhow::anyhow!("no stats"))
+        }
+
+        async fn health(&self) -> BackendHealth {
+            BackendHealth::Healthy
+        }
+    }
+
+    #[tokio::test]
+    async fn test_merges_backends_without_duplicates() {
+        let engine = SearchEngine::new(vec![
+            Arc::new(StubBackend { paths: vec!["/a/beta".into(), "/a/alpha".into()] }),
+            Arc::new(StubBackend { paths: vec!["/a/alpha".into(), "/b/gamma".into()] }),
+        ]);
+
+        let query = SearchQuery {
+            query: "a".to_string(),
+            filters: SearchFilters::default(),
+            limit: Some(2),
+            offset: Some(1),
+            sort: Default::default(),
+        };
+
+        let result = engine.search(&query).await.unwrap();
+        assert_eq!(result.total_count, 3);
+        let paths: Vec<_> = result.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["/a/beta", "/b/gamma"]);
+    }
+
+    #[tokio::test]
+    async fn test_rows_asked_of_backends_stop_at_the_largest_limit() {
+        let engine = SearchEngine::new(vec![
+            Arc::new(StubBackend { paths: vec!["/a/beta".into(), "/a/alpha".into()] }),
+            Arc::new(StubBackend { paths: vec!["/a/alpha".into(), "/b/gamma".into()] }),
+        ]);
+
+        // offset + limit is past u32::MAX, which must not wrap around to a handful of rows
+        let query = SearchQuery { limit: Some(u32::MAX), offset: Some(1), ..query("a") };
+        assert_eq!(engine.search(&query).await.unwrap().entries.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_ranked_pages_past_the_candidates_do_not_reorder_earlier_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(crate::database::Database::new(&dir.path().join("index.db")).await.unwrap());
+        let paths: Vec<String> = (0..RANK_CANDIDATES + 100).map(|i| format!("/d/file{:04}", i)).collect();
+        // Only a match beyond the ranked candidates has been opened
+        db.record_file_open(paths.last().unwrap()).await.unwrap();
+        let engine = SearchEngine::new(vec![Arc::new(StubBackend { paths })])
+            .with_ranker(Ranker::new(db, crate::config::RankingConfig::default()));
+
+        let page = |offset: usize, limit: usize| {
+            let query = SearchQuery {
+                limit: Some(limit as u32),
+                offset: Some(offset as u32),
+                sort: crate::types::Sort { field: SortField::Relevance, descending: false },
+                ..query("file")
+            };
+            let engine = &engine;
+            async move {
+                let result = engine.search(&query).await.unwrap();
+                result.entries.into_iter().map(|entry| entry.path).collect::<Vec<_>>()
+            }
+        };
+        let all = page(0, RANK_CANDIDATES + 100).await;
+        let mut paged = page(0, 300).await;
+        paged.extend(page(300, 300).await);
+        paged.extend(page(600, 300).await);
+        assert_eq!(paged, all);
+    }
 
     #[test]
     fn test_regex_validation() {
-        let sdk = Arc::new(EverythingSDK::new().unwrap());
-        let engine = SearchEngine::new(sdk);
+        let engine = SearchEngine::new(Vec::new());
 
         let valid_query = SearchQuery {
             query: r"test\d+".to_string(),
@@ -91,6 +541,7 @@ mod tests {
             },
             limit: None,
             offset: None,
+            sort: Default::default(),
         };
 
         let invalid_query = SearchQuery {
@@ -101,6 +552,7 @@ mod tests {
             },
             limit: None,
             offset: None,
+            sort: Default::default(),
         };
 
         assert!(engine.preprocess_query(&valid_query).is_ok());
@@ -0,0 +1,274 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::search::query::{CompiledQuery, Expr, Term};
+use crate::search::sort::Sorter;
+use crate::types::{SearchQuery, SearchResult};
+
+/// Results of recent queries, valid for one index generation. Queries that only narrow a
+/// cached query, such as `abcd` after `abc`, are answered by filtering
+/// its results when they are complete.
+pub struct QueryCache {
+    capacity: usize,
+    max_rows: usize,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    /// Generation every cached result was read at
+    generation: Option<u64>,
+    /// Least recently used first
+    entries: VecDeque<CachedResult>,
+    /// Rows held by `entries`
+    rows: usize,
+}
+
+struct CachedResult {
+    query: SearchQuery,
+    /// Compiled when cached, so a relative date that has moved on since is not a hit
+    expr: Expr,
+    result: SearchResult,
+}
+
+impl CachedResult {
+    /// Holds every match of its query, not just a page of them
+    fn is_complete(&self) -> bool {
+        self.query.offset.unwrap_or(0) == 0 && self.result.entries.len() as u64 == self.result.total_count
+    }
+}
+
+impl QueryCache {
+    pub fn new(capacity: usize, max_rows: usize) -> Self {
+        Self {
+            capacity,
+            max_rows,
+            state: Mutex::default(),
+        }
+    }
+
+    /// The result of `query` at `generation`, from the same query or one it narrows
+    pub fn get(&self, query: &SearchQuery, generation: u64) -> Option<SearchResult> {
+        let start_time = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        if state.generation != Some(generation) {
+            state.clear(generation);
+            return None;
+        }
+
+        let compiled = CompiledQuery::compile(query).ok()?;
+        let hit = state
+            .entries
+            .iter()
+            .rposition(|cached| same_query(&cached.query, query) && cached.expr == compiled.expr);
+        if let Some(index) = hit {
+            let cached = state.entries.remove(index)?;
+            let mut result = cached.result.clone();
+            state.entries.push_back(cached);
+            result.query_time_ms = start_time.elapsed().as_millis() as u64;
+            return Some(result);
+        }
+
+        // Filtering needs every match in memory and no per-file work beyond the entry itself
+        if compiled.regex.is_some() || compiled.fuzzy || reads_files(&compiled.expr) {
+            return None;
+        }
+        let index = state.entries.iter().rposition(|cached| {
+            cached.is_complete()
+                && cached.query.filters == query.filters
+                && implies(&compiled.expr, &cached.expr, compiled.case_sensitive)
+        })?;
+        let cached = state.entries.remove(index)?;
+        let mut entries: Vec<_> = cached
+            .result
+            .entries
+            .iter()
+            .filter(|entry| compiled.matches(entry))
+            .cloned()
+            .collect();
+        state.entries.push_back(cached);
+        drop(state);
+
+        let total_count = entries.len() as u64;
+        Sorter::for_query(query).ok()?.sort_entries(&mut entries);
+        let offset = query.offset.unwrap_or(0) as usize;
+        let limit = query.limit.unwrap_or(1000) as usize;
+        Some(SearchResult {
+            entries: entries.into_iter().skip(offset).take(limit).collect(),
+            total_count,
+            query_time_ms: start_time.elapsed().as_millis() as u64,
+        })
+    }
+
+    /// Remember the result of `query` read at `generation`, evicting the least recently
+    /// used results when full. Results of an older generation than the cache, and results
+    /// with more rows than the whole cache may hold, are dropped.
+    pub fn insert(&self, query: &SearchQuery, generation: u64, result: &SearchResult) {
+        let Ok(compiled) = CompiledQuery::compile(query) else {
+            return;
+        };
+
+        let mut state = self.state.lock().unwrap();
+        if state.generation > Some(generation) {
+            return;
+        }
+        if state.generation != Some(generation) {
+            state.clear(generation);
+        }
+
+        if let Some(index) = state.entries.iter().position(|cached| same_query(&cached.query, query)) {
+            state.remove(index);
+        }
+        let rows = result.entries.len();
+        if self.capacity == 0 || rows > self.max_rows {
+            return;
+        }
+        while state.entries.len() >= self.capacity || state.rows + rows > self.max_rows {
+            state.remove(0);
+        }
+        state.rows += rows;
+        state.entries.push_back(CachedResult {
+            query: query.clone(),
+            expr: compiled.expr,
+            result: result.clone(),
+        });
+    }
+}
+
+impl CacheState {
+    fn clear(&mut self, generation: u64) {
+        self.entries.clear();
+        self.rows = 0;
+        self.generation = Some(generation);
+    }
+
+    fn remove(&mut self, index: usize) -> Option<CachedResult> {
+        let cached = self.entries.remove(index)?;
+        self.rows -= cached.result.entries.len();
+        Some(cached)
+    }
+}
+
+fn same_query(a: &SearchQuery, b: &SearchQuery) -> bool {
+    a.query == b.query && a.filters == b.filters && a.limit == b.limit && a.offset == b.offset && a.sort == b.sort
+}
+
+/// Whether matching the expression reads the files themselves
+fn reads_files(expr: &Expr) -> bool {
+    match expr {
+        Expr::And(children) | Expr::Or(children) => children.iter().any(reads_files),
+        Expr::Not(child) => reads_files(child),
+        Expr::Term(term) => matches!(term, Term::Content(_) | Term::Hash(_)),
+    }
+}
+
+/// Whether every entry matching `narrow` also matches `broad`, as far as can be told from
+/// their structure. False negatives only cost a cache miss.
+fn implies(narrow: &Expr, broad: &Expr, case_sensitive: bool) -> bool {
+    if narrow == broad {
+        return true;
+    }
+
+    match (narrow, broad) {
+        (_, Expr::And(children)) => children.iter().all(|child| implies(narrow, child, case_sensitive)),
+        (Expr::Or(children), _) => children.iter().all(|child| implies(child, broad, case_sensitive)),
+        (Expr::And(children), _) => children.iter().any(|child| implies(child, broad, case_sensitive)),
+        (_, Expr::Or(children)) => children.iter().any(|child| implies(narrow, child, case_sensitive)),
+        (Expr::Not(narrow), Expr::Not(broad)) => implies(broad, narrow, case_sensitive),
+        (Expr::Term(Term::Text(narrow)), Expr::Term(Term::Text(broad))) => {
+            // Text with a separator is matched against the path, without one against the name
+            let has_separator = |text: &str| text.contains(['/', '\\']);
+            has_separator(&narrow.text) == has_separator(&broad.text)
+                && contains_text(&narrow.text, &broad.text, narrow.wildcard || broad.wildcard, case_sensitive)
+        }
+        (Expr::Term(Term::Path(narrow)), Expr::Term(Term::Path(broad))) => {
+            contains_text(&narrow.text, &broad.text, narrow.wildcard || broad.wildcard, case_sensitive)
+        }
+        _ => false,
+    }
+}
+
+/// Whatever contains `narrow` contains `broad`
+fn contains_text(narrow: &str, broad: &str, wildcard: bool, case_sensitive: bool) -> bool {
+    if wildcard {
+        return false;
+    }
+    if case_sensitive {
+        narrow.contains(broad)
+    } else {
+        narrow.to_lowercase().contains(&broad.to_lowercase())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{entry, query};
+
+    fn result(names: &[&str]) -> SearchResult {
+        let entries: Vec<_> = names.iter().map(|name| entry(&format!("/docs/{}", name))).collect();
+        SearchResult { total_count: entries.len() as u64, entries, query_time_ms: 0 }
+    }
+
+    fn names(result: Option<SearchResult>) -> Option<Vec<String>> {
+        result.map(|result| result.entries.into_iter().map(|entry| entry.name).collect())
+    }
+
+    fn cached_rep() -> QueryCache {
+        let cache = QueryCache::new(8, 100);
+        cache.insert(&query("rep"), 1, &result(&["report.md", "Repo.txt", "prep.rs"]));
+        cache
+    }
+
+    #[test]
+    fn test_narrower_queries_filter_the_cached_results() {
+        let cache = cached_rep();
+        assert_eq!(
+            names(cache.get(&query("repo"), 1)),
+            Some(vec!["Repo.txt".to_string(), "report.md".to_string()])
+        );
+        assert_eq!(names(cache.get(&query("rep ext:md"), 1)), Some(vec!["report.md".to_string()]));
+    }
+
+    #[test]
+    fn test_queries_not_narrower_than_a_cached_one_miss() {
+        let cache = cached_rep();
+        assert_eq!(names(cache.get(&query("re"), 1)), None);
+        assert_eq!(names(cache.get(&query("rep | notes"), 1)), None);
+    }
+
+    #[test]
+    fn test_a_page_is_only_served_for_its_own_query() {
+        let cache = QueryCache::new(8, 100);
+        let paged = SearchQuery { limit: Some(1), ..query("notes") };
+        let page = SearchResult { total_count: 2, ..result(&["notes.md"]) };
+        cache.insert(&paged, 1, &page);
+
+        assert_eq!(names(cache.get(&paged, 1)), Some(vec!["notes.md".to_string()]));
+        assert_eq!(names(cache.get(&query("notes.md"), 1)), None);
+    }
+
+    #[test]
+    fn test_cached_rows_are_bounded() {
+        let cache = QueryCache::new(8, 3);
+        cache.insert(&query("a"), 1, &result(&["a1", "a2"]));
+        cache.insert(&query("b"), 1, &result(&["b1", "b2"]));
+        // The oldest result made room for the newest
+        assert_eq!(names(cache.get(&query("a"), 1)), None);
+        assert_eq!(names(cache.get(&query("b"), 1)), Some(vec!["b1".to_string(), "b2".to_string()]));
+
+        // More rows than the cache holds are not cached, and evict nothing
+        cache.insert(&query("c"), 1, &result(&["c1", "c2", "c3", "c4"]));
+        assert_eq!(names(cache.get(&query("c"), 1)), None);
+        assert!(cache.get(&query("b"), 1).is_some());
+    }
+
+    #[test]
+    fn test_results_read_before_an_index_change_are_gone_once_it_is_seen() {
+        let cache = cached_rep();
+        assert_eq!(names(cache.get(&query("rep"), 2)), None);
+        cache.insert(&query("rep"), 1, &result(&["report.md"]));
+        assert_eq!(names(cache.get(&query("repo"), 2)), None);
+    }
+}
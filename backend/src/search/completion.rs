@@ -0,0 +1,279 @@
+use anyhow::Result;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::database::Database;
+use crate::search::query::{quote, MODIFIERS};
+use crate::types::{Completion, CompletionKind, HistoryEntry};
+
+/// How long extension counts are reused before the index is counted again
+const EXTENSION_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Extensions in the index with their number of files, most common first
+type ExtensionCounts = Arc<Vec<(String, u64)>>;
+
+/// Completes partly typed queries from the index, the query language and past queries
+pub struct Completer {
+    db: Arc<Database>,
+    extensions: Mutex<Option<(Instant, ExtensionCounts)>>,
+}
+
+impl Completer {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self {
+            db,
+            extensions: Mutex::new(None),
+        }
+    }
+
+    /// Up to `limit` completions of `partial`: past queries that continue it, most used
+    /// first, then completions of the word being typed, most common first
+    pub async fn complete(&self, partial: &str, history: &[HistoryEntry], limit: usize) -> Result<Vec<Completion>> {
+        let needle = partial.trim_start().to_lowercase();
+        let mut completions: Vec<Completion> = history
+            .iter()
+            .filter(|entry| {
+                let query = entry.query.to_lowercase();
+                query.starts_with(&needle) && query != needle
+            })
+            .map(|entry| Completion {
+                query: entry.query.clone(),
+                label: entry.query.clone(),
+                kind: CompletionKind::History,
+                count: entry.use_count,
+            })
+            .take(limit)
+            .collect();
+
+        if let Some(word) = LastWord::of(partial) {
+            completions.extend(self.complete_word(&word, limit).await?);
+        }
+
+        let mut seen = HashSet::new();
+        completions.retain(|completion| seen.insert(completion.query.clone()));
+        completions.truncate(limit);
+        Ok(completions)
+    }
+
+    async fn complete_word(&self, word: &LastWord<'_>, limit: usize) -> Result<Vec<Completion>> {
+        let completion = |completed: String, label: String, kind, count| Completion {
+            query: format!("{}{}", word.head, completed),
+            label,
+            kind,
+            count,
+        };
+
+        match word.modifier.as_deref() {
+            Some("ext") => {
+                let (listed, typed) = match word.value.rfind([';', ',']) {
+                    Some(end) => word.value.split_at(end + 1),
+                    None => ("", word.value),
+                };
+                let listed_ext: Vec<String> = listed
+                    .split([';', ','])
+                    .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                    .collect();
+                let typed = typed.trim_start_matches('.').to_lowercase();
+
+                Ok(self
+                    .extension_counts()
+                    .await?
+                    .iter()
+                    .filter(|(ext, _)| ext.starts_with(&typed) && *ext != typed && !listed_ext.contains(ext))
+                    .take(limit)
+                    .map(|(ext, count)| {
+                        completion(format!("ext:{}{}", listed, ext), ext.clone(), CompletionKind::Extension, *count)
+                    })
+                    .collect())
+            }
+            Some(modifier @ ("path" | "parent")) => Ok(self
+                .complete_folder(word.value, limit)
+                .await?
+                .into_iter()
+                .map(|(path, count)| {
+                    completion(format!("{}:{}", modifier, quote(&path)), path, CompletionKind::Folder, count)
+                })
+                .collect()),
+            Some("saved") => {
+                let typed = word.value.to_lowercase();
+                Ok(self
+                    .db
+                    .saved_searches()
+                    .await?
+                    .into_iter()
+                    .filter(|saved| saved.name.to_lowercase().starts_with(&typed))
+                    .take(limit)
+                    .map(|saved| completion(format!("saved:{}", quote(&saved.name)), saved.name, CompletionKind::Saved, 0))
+                    .collect())
+            }
+            Some(_) => Ok(Vec::new()),
+            // Plain text with a separator is matched against the full path
+            None if word.value.contains(['/', '\\']) => Ok(self
+                .complete_folder(word.value, limit)
+                .await?
+                .into_iter()
+                .map(|(path, count)| completion(quote(&path), path, CompletionKind::Folder, count))
+                .collect()),
+            None if word.value.is_empty() || word.value.contains(['*', '?']) => Ok(Vec::new()),
+            None => {
+                let typed = word.value.to_ascii_lowercase();
+                let keywords = MODIFIERS
+                    .iter()
+                    .filter(|keyword| keyword.starts_with(&typed) && keyword.len() > typed.len())
+                    .map(|keyword| completion(keyword.to_string(), keyword.to_string(), CompletionKind::Keyword, 0));
+                let names = self
+                    .db
+                    .name_completions(&typed, limit + 1)
+                    .await?
+                    .into_iter()
+                    .filter(|(name, _)| !name.eq_ignore_ascii_case(&typed))
+                    .map(|(name, count)| completion(quote(&name), name, CompletionKind::Name, count));
+                Ok(keywords.chain(names).take(limit).collect())
+            }
+        }
+    }
+
+    /// Folders whose path continues `value`, or whose name starts with it when it has no
+    /// separator
+    async fn complete_folder(&self, value: &str, limit: usize) -> Result<Vec<(String, u64)>> {
+        match value.rfind(['/', '\\']) {
+            Some(separator) => {
+                let parent = value[..separator].trim_end_matches(['/', '\\']);
+                let typed = value[separator + 1..].to_ascii_lowercase();
+                self.db.folder_completions(Some(parent), &typed, limit).await
+            }
+            None if value.is_empty() => Ok(Vec::new()),
+            None => self.db.folder_completions(None, &value.to_ascii_lowercase(), limit).await,
+        }
+    }
+
+    async fn extension_counts(&self) -> Result<ExtensionCounts> {
+        if let Some((counted, counts)) = &*self.extensions.lock().unwrap() {
+            if counted.elapsed() < EXTENSION_CACHE_TTL {
+                return Ok(counts.clone());
+            }
+        }
+
+        let counts = Arc::new(self.db.extension_counts().await?);
+        *self.extensions.lock().unwrap() = Some((Instant::now(), counts.clone()));
+        Ok(counts)
+    }
+}
+
+/// The word being typed at the end of a query
+struct LastWord<'a> {
+    /// Everything before the word, including any `!`, `<` or `|` in front of it
+    head: &'a str,
+    /// Lowercase modifier name, such as `ext`, when the word starts with one
+    modifier: Option<String>,
+    /// The word after its modifier, without an opening quote
+    value: &'a str,
+}
+
+impl<'a> LastWord<'a> {
+    fn of(partial: &'a str) -> Option<Self> {
+        let mut start = 0;
+        let mut quoted = false;
+        for (i, c) in partial.char_indices() {
+            if c == '"' {
+                quoted = !quoted;
+            } else if !quoted && (c.is_whitespace() || "<|!".contains(c)) {
+                start = i + c.len_utf8();
+            }
+        }
+
+        let (head, word) = partial.split_at(start);
+        if word.is_empty() {
+            return None;
+        }
+
+        let (modifier, value) = match word.split_once(':') {
+            Some((name, value)) if MODIFIERS.contains(&format!("{}:", name.to_lowercase()).as_str()) => {
+                (Some(name.to_lowercase()), value)
+            }
+            _ => (None, word),
+        };
+        let value = value.strip_prefix('"').unwrap_or(value);
+        Some(Self { head, modifier, value })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{entry, folder};
+    use crate::types::SearchFilters;
+
+    async fn completer() -> (tempfile::TempDir, Completer) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(Database::new(&dir.path().join("index.db")).await.unwrap());
+        db.batch_insert_file_entries(&[
+            folder("/home"),
+            folder("/home/me"),
+            folder("/home/me/Projects"),
+            folder("/home/me/Pictures"),
+            entry("/home/me/Projects/report.md"),
+            entry("/home/me/Projects/Report.md"),
+            entry("/home/me/Projects/readme.md"),
+            entry("/home/me/Projects/repo notes.txt"),
+            entry("/home/me/Pictures/photo.png"),
+        ])
+        .await
+        .unwrap();
+        (dir, Completer::new(db))
+    }
+
+    async fn complete(partial: &str, history: &[HistoryEntry]) -> Vec<(String, u64)> {
+        let (_dir, completer) = completer().await;
+        completer
+            .complete(partial, history, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|completion| (completion.query, completion.count))
+            .collect()
+    }
+
+    fn completions(expected: &[(&str, u64)]) -> Vec<(String, u64)> {
+        expected.iter().map(|(query, count)| (query.to_string(), *count)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_names_keep_the_operator_and_are_quoted_when_needed() {
+        assert_eq!(complete("!rep", &[]).await, completions(&[("!Report.md", 2), ("!\"repo notes.txt\"", 1)]));
+    }
+
+    #[tokio::test]
+    async fn test_keywords() {
+        assert_eq!(complete("pa", &[]).await, completions(&[("path:", 0), ("parent:", 0)]));
+    }
+
+    #[tokio::test]
+    async fn test_extensions_after_a_separator() {
+        assert_eq!(complete("x ext:png;M", &[]).await, completions(&[("x ext:png;md", 3)]));
+    }
+
+    #[tokio::test]
+    async fn test_folders_in_path_terms() {
+        assert_eq!(
+            complete("path:/home/me/P", &[]).await,
+            completions(&[("path:/home/me/Projects", 4), ("path:/home/me/Pictures", 1)])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_history_comes_before_names() {
+        let history = [HistoryEntry {
+            query: "report ext:md".to_string(),
+            filters: SearchFilters::default(),
+            use_count: 7,
+            first_used: chrono::Utc::now(),
+            last_used: chrono::Utc::now(),
+        }];
+        assert_eq!(
+            complete("repo", &history).await,
+            completions(&[("report ext:md", 7), ("Report.md", 2), ("\"repo notes.txt\"", 1)])
+        );
+    }
+}
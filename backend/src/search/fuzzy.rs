@@ -0,0 +1,162 @@
+//! Fuzzy subsequence matching, scored like fzf: every matched character earns points, with
+//! bonuses for matches at word boundaries and in consecutive runs, and penalties for gaps.
+
+const SCORE_MATCH: i32 = 16;
+const SCORE_GAP_START: i32 = -3;
+const SCORE_GAP_EXTENSION: i32 = -1;
+/// Match right after a separator or at the start of the target
+const BONUS_BOUNDARY: i32 = SCORE_MATCH / 2;
+/// Uppercase after lowercase, or a digit after a non-digit
+const BONUS_CAMEL: i32 = BONUS_BOUNDARY + SCORE_GAP_EXTENSION;
+/// Minimum bonus of a match that directly follows another
+const BONUS_CONSECUTIVE: i32 = -(SCORE_GAP_START + SCORE_GAP_EXTENSION);
+/// The first pattern character's bonus counts this many times
+const BONUS_FIRST_CHAR_MULTIPLIER: i32 = 2;
+
+#[derive(Clone, Copy, PartialEq)]
+enum CharClass {
+    Separator,
+    Lower,
+    Upper,
+    Digit,
+    Other,
+}
+
+fn char_class(c: char) -> CharClass {
+    if c.is_lowercase() {
+        CharClass::Lower
+    } else if c.is_uppercase() {
+        CharClass::Upper
+    } else if c.is_numeric() {
+        CharClass::Digit
+    } else if c.is_alphabetic() {
+        CharClass::Other
+    } else {
+        CharClass::Separator
+    }
+}
+
+fn bonus(prev: CharClass, class: CharClass) -> i32 {
+    match (prev, class) {
+        (_, CharClass::Separator) => 0,
+        (CharClass::Separator, _) => BONUS_BOUNDARY,
+        (CharClass::Lower, CharClass::Upper) => BONUS_CAMEL,
+        (prev, CharClass::Digit) if prev != CharClass::Digit => BONUS_CAMEL,
+        _ => 0,
+    }
+}
+
+fn fold(c: char, case_sensitive: bool) -> char {
+    if case_sensitive {
+        c
+    } else {
+        c.to_lowercase().next().unwrap_or(c)
+    }
+}
+
+/// Score of the best alignment of `pattern` as a subsequence of `target`, or `None` if the
+/// characters of `pattern` do not all appear in `target` in order
+pub fn fuzzy_score(pattern: &str, target: &str, case_sensitive: bool) -> Option<i32> {
+    let pattern: Vec<char> = pattern.chars().map(|c| fold(c, case_sensitive)).collect();
+    let original: Vec<char> = target.chars().collect();
+    let target: Vec<char> = original.iter().map(|&c| fold(c, case_sensitive)).collect();
+
+    if pattern.is_empty() {
+        return Some(0);
+    }
+    let mut rest = target.iter();
+    if !pattern.iter().all(|p| rest.any(|t| t == p)) {
+        return None;
+    }
+
+    let mut prev_class = CharClass::Separator;
+    let bonuses: Vec<i32> = original
+        .iter()
+        .map(|&c| {
+            let class = char_class(c);
+            let bonus = bonus(prev_class, class);
+            prev_class = class;
+            bonus
+        })
+        .collect();
+
+    // Best score with pattern[..=i] matched and pattern[i] at each target position, and the
+    // bonus of the first character of the consecutive run ending there
+    let n = target.len();
+    let mut scores: Vec<Option<i32>> = vec![None; n];
+    let mut run_bonus = vec![0; n];
+
+    for (i, &p) in pattern.iter().enumerate() {
+        let mut row: Vec<Option<i32>> = vec![None; n];
+        let mut row_run_bonus = vec![0; n];
+        // Best `score[j'] - SCORE_GAP_EXTENSION * j'` over j' < j - 1, so a gap from j' to j
+        // costs SCORE_GAP_START + SCORE_GAP_EXTENSION * (j - j' - 2)
+        let mut best_gap: Option<i32> = None;
+
+        for j in i..n {
+            if j >= 2 {
+                if let Some(score) = scores[j - 2] {
+                    let key = score - SCORE_GAP_EXTENSION * (j as i32 - 2);
+                    best_gap = Some(best_gap.map_or(key, |best| best.max(key)));
+                }
+            }
+            if target[j] != p {
+                continue;
+            }
+
+            let bonus = bonuses[j];
+            if i == 0 {
+                row[j] = Some(SCORE_MATCH + bonus * BONUS_FIRST_CHAR_MULTIPLIER);
+                row_run_bonus[j] = bonus;
+                continue;
+            }
+
+            let consecutive = (j >= 1).then(|| scores[j - 1]).flatten().map(|score| {
+                let run = run_bonus[j - 1].max(bonus);
+                (score + SCORE_MATCH + run.max(BONUS_CONSECUTIVE), run)
+            });
+            let gap = best_gap
+                .map(|key| key + SCORE_GAP_START + SCORE_GAP_EXTENSION * (j as i32 - 2) + SCORE_MATCH + bonus);
+
+            (row[j], row_run_bonus[j]) = match (consecutive, gap) {
+                (Some((consecutive, run)), Some(gap)) if consecutive >= gap => (Some(consecutive), run),
+                (Some((consecutive, run)), None) => (Some(consecutive), run),
+                (_, gap) => (gap, bonus),
+            };
+        }
+
+        scores = row;
+        run_bonus = row_run_bonus;
+    }
+
+    scores.into_iter().flatten().max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pattern_must_appear_in_order() {
+        assert!(fuzzy_score("cfgldr", "config_loader.rs", false).is_some());
+        assert!(fuzzy_score("cfgldr", "loader_config.rs", false).is_none());
+        assert!(fuzzy_score("CL", "config_loader.rs", true).is_none());
+    }
+
+    #[test]
+    fn test_word_starts_and_camel_humps_beat_matches_inside_words() {
+        let score = |target| fuzzy_score("cfgldr", target, false).unwrap();
+        assert!(score("config_loader.rs") > score("xconfigxloaderx.rs"));
+        assert!(score("ConfigLoader.ts") > score("xconfigxloader.ts"));
+    }
+
+    #[test]
+    fn test_consecutive_letters_beat_gaps() {
+        assert!(fuzzy_score("load", "loader.rs", false) > fuzzy_score("load", "l_o_a_d.rs", false));
+    }
+
+    #[test]
+    fn test_empty_pattern_matches_anything() {
+        assert_eq!(fuzzy_score("", "anything", false), Some(0));
+    }
+}
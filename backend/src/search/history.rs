@@ -0,0 +1,144 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::config::HistoryConfig;
+use crate::database::Database;
+use crate::types::{HistoryEntry, SearchQuery};
+
+/// Past queries, deduplicated by their text and ordered by frecency
+pub struct SearchHistory {
+    db: Arc<Database>,
+    config: HistoryConfig,
+    /// While set, nothing is recorded
+    private: AtomicBool,
+}
+
+impl SearchHistory {
+    pub fn new(db: Arc<Database>, config: HistoryConfig) -> Self {
+        Self {
+            private: AtomicBool::new(config.private),
+            db,
+            config,
+        }
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.private.load(Ordering::Relaxed)
+    }
+
+    pub fn set_private(&self, private: bool) {
+        self.private.store(private, Ordering::Relaxed);
+    }
+
+    /// Count a run of `query`; blank queries and runs in private mode are ignored
+    pub async fn record(&self, query: &SearchQuery) -> Result<()> {
+        let text = normalize(&query.query);
+        if text.is_empty() || self.is_private() {
+            return Ok(());
+        }
+
+        self.db.record_search(&text, &query.filters).await?;
+        self.db.prune_search_history(self.config.max_entries).await?;
+        Ok(())
+    }
+
+    /// Up to `limit` past queries, the most frequently and recently used first
+    pub async fn recent(&self, limit: usize) -> Result<Vec<HistoryEntry>> {
+        let mut entries = self.db.search_history().await?;
+        let now = Utc::now();
+        entries.sort_by(|a, b| {
+            self.frecency(b, now)
+                .total_cmp(&self.frecency(a, now))
+                .then_with(|| b.last_used.cmp(&a.last_used))
+        });
+        entries.truncate(limit);
+        Ok(entries)
+    }
+
+    pub async fn delete(&self, query: &str) -> Result<bool> {
+        self.db.delete_search(&normalize(query)).await
+    }
+
+    pub async fn clear(&self) -> Result<()> {
+        self.db.prune_search_history(0).await?;
+        Ok(())
+    }
+
+    /// Uses of the query, each worth half as much every `half_life_days` since its last use
+    fn frecency(&self, entry: &HistoryEntry, now: DateTime<Utc>) -> f64 {
+        let days = (now - entry.last_used).num_seconds().max(0) as f64 / 86_400.0;
+        entry.use_count as f64 * 0.5f64.powf(days / self.config.half_life_days.max(f64::EPSILON))
+    }
+}
+
+/// Queries that differ only in surrounding or repeated whitespace are the same query
+fn normalize(query: &str) -> String {
+    query.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::query;
+    use crate::types::SearchFilters;
+
+    async fn history() -> (tempfile::TempDir, SearchHistory) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(Database::new(&dir.path().join("index.db")).await.unwrap());
+        (dir, SearchHistory::new(db, HistoryConfig::default()))
+    }
+
+    async fn queries(history: &SearchHistory) -> Vec<String> {
+        history.recent(10).await.unwrap().into_iter().map(|entry| entry.query).collect()
+    }
+
+    #[tokio::test]
+    async fn test_repeats_are_merged_and_ranked_by_use() {
+        let (_dir, history) = history().await;
+        history.record(&query("invoice")).await.unwrap();
+        for _ in 0..3 {
+            history.record(&query("report  ")).await.unwrap();
+        }
+        history.record(&query("  report")).await.unwrap();
+
+        let recent = history.recent(10).await.unwrap();
+        assert_eq!(queries(&history).await, vec!["report", "invoice"]);
+        assert_eq!(recent[0].use_count, 4);
+    }
+
+    #[tokio::test]
+    async fn test_a_repeat_keeps_the_latest_filters() {
+        let (_dir, history) = history().await;
+        history.record(&query("report")).await.unwrap();
+        let regex = SearchFilters { use_regex: true, ..Default::default() };
+        history.record(&SearchQuery { filters: regex, ..query("report") }).await.unwrap();
+
+        assert!(history.recent(10).await.unwrap()[0].filters.use_regex);
+    }
+
+    #[tokio::test]
+    async fn test_blank_queries_and_private_searches_are_not_recorded() {
+        let (_dir, history) = history().await;
+        history.record(&query("   ")).await.unwrap();
+        history.set_private(true);
+        history.record(&query("secret")).await.unwrap();
+        history.set_private(false);
+
+        assert!(queries(&history).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_delete_and_clear() {
+        let (_dir, history) = history().await;
+        history.record(&query("report")).await.unwrap();
+        history.record(&query("invoice")).await.unwrap();
+
+        assert!(history.delete("report").await.unwrap());
+        assert!(!history.delete("report").await.unwrap());
+        assert_eq!(queries(&history).await, vec!["invoice"]);
+        history.clear().await.unwrap();
+        assert!(queries(&history).await.is_empty());
+    }
+}
@@ -0,0 +1,1001 @@
+//! Everything-compatible query language.
+//!
+//! Whitespace is AND, `|` is OR (binding tighter than AND, as in Everything), `!` negates
+//! the following term and `< >` groups. Quoted phrases keep their spaces, `*` and `?` are
+//! wildcards matched against the whole name. Supported modifiers are `ext:`, `size:`,
+//! `dm:`/`datemodified:`, `dc:`/`datecreated:`, `folder:`, `file:`, `path:`, `parent:`,
+//! `content:`, `hash:` and `saved:`.
+
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, Local, LocalResult, NaiveDate, TimeZone, Utc};
+use regex::{Regex, RegexBuilder};
+use std::fmt;
+use std::path::Path;
+
+use crate::config::ContentConfig;
+use crate::content;
+use crate::hashing;
+use crate::search::fuzzy::fuzzy_score;
+use crate::types::{FileEntry, SearchError, SearchFilters, SearchQuery};
+
+const KB: i64 = 1024;
+const MB: i64 = 1024 * KB;
+const GB: i64 = 1024 * MB;
+const TB: i64 = 1024 * GB;
+
+/// Query-language modifiers, used for validation and completion
+pub const MODIFIERS: &[&str] = &[
+    "ext:", "size:", "dm:", "datemodified:", "dc:", "datecreated:", "folder:", "file:", "path:", "parent:",
+    "content:", "hash:", "saved:",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// Every child must match; an empty list matches everything
+    And(Vec<Expr>),
+    /// At least one child must match
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    Term(Term),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    /// Plain text, matched against the name, or the full path if it contains a separator
+    Text(TextPattern),
+    /// `path:` text matched against the full path
+    Path(TextPattern),
+    /// `parent:` exact parent directory
+    Parent(String),
+    /// `ext:` list of lowercase extensions without the dot
+    Extension(Vec<String>),
+    Size(SizeRange),
+    DateModified(DateRange),
+    DateCreated(DateRange),
+    FolderOnly,
+    FileOnly,
+    /// `content:` text the file contents must contain
+    Content(String),
+    /// Plain text in fuzzy mode, matched as a subsequence of the name (or full path)
+    Fuzzy(TextPattern),
+    /// `hash:` lowercase hex prefix of the hash of the file contents
+    Hash(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextPattern {
+    pub text: String,
+    /// Contains `*` or `?` and must match the whole string
+    pub wildcard: bool,
+}
+
+/// Inclusive size bounds in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SizeRange {
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+}
+
+/// Date bounds, `start` inclusive and `end` exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DateRange {
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+/// A `SearchQuery` reduced to one expression plus the flags that apply to all of it
+#[derive(Debug, Clone)]
+pub struct CompiledQuery {
+    pub expr: Expr,
+    pub case_sensitive: bool,
+    pub include_hidden: bool,
+    /// Set when `use_regex` is on; the query text is then matched against names as a regex
+    pub regex: Option<Regex>,
+    /// Plain text terms are fuzzy and results carry a score
+    pub fuzzy: bool,
+    /// Files `content:` terms read when matching entries one by one
+    pub content: ContentConfig,
+}
+
+impl CompiledQuery {
+    pub fn compile(query: &SearchQuery) -> Result<Self> {
+        Self::compile_at(query, Local::now())
+    }
+
+    /// Compile with relative dates (`dm:today`) resolved against `now`
+    pub fn compile_at(query: &SearchQuery, now: DateTime<Local>) -> Result<Self> {
+        let filters = &query.filters;
+        let mut terms = Vec::new();
+
+        let regex = if filters.use_regex {
+            let regex = RegexBuilder::new(&query.query)
+                .case_insensitive(!filters.case_sensitive)
+                .build()
+                .map_err(|e| SearchError::InvalidQuery(format!("Invalid regex pattern: {}", e)))?;
+            Some(regex)
+        } else {
+            let mut expr = parse_query_at(&query.query, now)?;
+            if filters.search_content {
+                expr = with_content(expr);
+            }
+            if filters.fuzzy {
+                expr = with_fuzzy(expr);
+            }
+            terms.push(expr);
+            None
+        };
+
+        terms.extend(filter_terms(filters));
+
+        let expr = match terms.len() {
+            1 => terms.remove(0),
+            _ => Expr::And(terms),
+        };
+
+        Ok(Self {
+            expr,
+            case_sensitive: filters.case_sensitive,
+            include_hidden: filters.include_hidden,
+            fuzzy: filters.fuzzy && regex.is_none(),
+            regex,
+            content: ContentConfig::default(),
+        })
+    }
+
+    /// Read files for `content:` terms as configured by `content`
+    pub fn with_content(mut self, content: ContentConfig) -> Self {
+        self.content = content;
+        self
+    }
+
+    pub fn matches(&self, entry: &FileEntry) -> bool {
+        if !self.include_hidden && is_hidden(entry) {
+            return false;
+        }
+        if let Some(regex) = &self.regex {
+            if !regex.is_match(&entry.name) {
+                return false;
+            }
+        }
+        self.expr.matches(entry, self.case_sensitive, &self.content)
+    }
+
+    /// Summed fuzzy score of a matching entry, or `None` if no fuzzy term matched it
+    pub fn score(&self, entry: &FileEntry) -> Option<i32> {
+        self.expr.fuzzy_score(entry, self.case_sensitive)
+    }
+}
+
+/// Hidden by Windows attribute or by the Unix dot-file convention
+pub fn is_hidden(entry: &FileEntry) -> bool {
+    const FILE_ATTRIBUTE_HIDDEN: i32 = 0x2;
+    entry.name.starts_with('.') || entry.attributes & FILE_ATTRIBUTE_HIDDEN != 0
+}
+
+/// Let plain text terms match file contents as well as names
+pub(crate) fn with_content(expr: Expr) -> Expr {
+    match expr {
+        Expr::And(children) => Expr::And(children.into_iter().map(with_content).collect()),
+        Expr::Or(children) => Expr::Or(children.into_iter().map(with_content).collect()),
+        Expr::Not(child) => Expr::Not(Box::new(with_content(*child))),
+        Expr::Term(Term::Text(pattern)) if !pattern.wildcard => {
+            let content = Term::Content(pattern.text.clone());
+            Expr::Or(vec![Expr::Term(Term::Text(pattern)), Expr::Term(content)])
+        }
+        term => term,
+    }
+}
+
+/// Turn plain text terms into fuzzy terms
+pub(crate) fn with_fuzzy(expr: Expr) -> Expr {
+    match expr {
+        Expr::And(children) => Expr::And(children.into_iter().map(with_fuzzy).collect()),
+        Expr::Or(children) => Expr::Or(children.into_iter().map(with_fuzzy).collect()),
+        Expr::Not(child) => Expr::Not(Box::new(with_fuzzy(*child))),
+        Expr::Term(Term::Text(pattern)) if !pattern.wildcard => Expr::Term(Term::Fuzzy(pattern)),
+        term => term,
+    }
+}
+
+/// The `SearchFilters` constraints expressed as query terms
+pub fn filter_terms(filters: &SearchFilters) -> Vec<Expr> {
+    let mut terms = Vec::new();
+
+    if !filters.file_types.is_empty() {
+        let extensions = filters
+            .file_types
+            .iter()
+            .map(|t| t.trim_start_matches('.').to_lowercase())
+            .collect();
+        terms.push(Expr::Term(Term::Extension(extensions)));
+    }
+
+    if filters.size_min.is_some() || filters.size_max.is_some() {
+        terms.push(Expr::Term(Term::Size(SizeRange {
+            min: filters.size_min,
+            max: filters.size_max,
+        })));
+    }
+
+    if filters.date_from.is_some() || filters.date_to.is_some() {
+        terms.push(Expr::Term(Term::DateModified(DateRange {
+            start: filters.date_from,
+            // `date_to` is inclusive
+            end: filters.date_to.map(|to| to + Duration::nanoseconds(1)),
+        })));
+    }
+
+    if filters.directories_only {
+        terms.push(Expr::Term(Term::FolderOnly));
+    } else if filters.files_only {
+        terms.push(Expr::Term(Term::FileOnly));
+    }
+
+    terms
+}
+
+pub fn parse_query(input: &str) -> Result<Expr> {
+    parse_query_at(input, Local::now())
+}
+
+pub fn parse_query_at(input: &str, now: DateTime<Local>) -> Result<Expr> {
+    parse_query_with(input, now, None)
+}
+
+/// Like `parse_query_at`, with each `saved:<name>` replaced by what `saved` resolves the
+/// name to. Without a resolver `saved:` is an error.
+pub fn parse_query_with(input: &str, now: DateTime<Local>, saved: Option<SavedResolver<'_>>) -> Result<Expr> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser { tokens, pos: 0, now, saved };
+    let expr = parser.parse_and()?;
+
+    if parser.pos < parser.tokens.len() {
+        return Err(SearchError::InvalidQuery("Unmatched '>' in query".to_string()).into());
+    }
+
+    Ok(expr)
+}
+
+impl Expr {
+    pub fn matches(&self, entry: &FileEntry, case_sensitive: bool, content: &ContentConfig) -> bool {
+        match self {
+            Expr::And(children) => children.iter().all(|c| c.matches(entry, case_sensitive, content)),
+            Expr::Or(children) => children.iter().any(|c| c.matches(entry, case_sensitive, content)),
+            Expr::Not(child) => !child.matches(entry, case_sensitive, content),
+            Expr::Term(term) => term.matches(entry, case_sensitive, content),
+        }
+    }
+
+    /// Sum of the scores of the fuzzy terms that match, ignoring negated terms; the best
+    /// alternative counts for OR
+    fn fuzzy_score(&self, entry: &FileEntry, case_sensitive: bool) -> Option<i32> {
+        match self {
+            Expr::And(children) => children
+                .iter()
+                .filter_map(|c| c.fuzzy_score(entry, case_sensitive))
+                .reduce(|a, b| a + b),
+            Expr::Or(children) => children.iter().filter_map(|c| c.fuzzy_score(entry, case_sensitive)).max(),
+            Expr::Not(_) => None,
+            Expr::Term(Term::Fuzzy(pattern)) => fuzzy_score(&pattern.text, text_target(&pattern.text, entry), case_sensitive),
+            Expr::Term(_) => None,
+        }
+    }
+
+    /// Whether the expression contains no terms and so matches every entry
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Expr::And(children) if children.iter().all(Expr::is_empty))
+    }
+
+    /// The expression with its `hash:` terms dropped, matching at least what it matches, for
+    /// backends that cannot hash files; their results are then checked with `matches`
+    pub fn without_hash(&self) -> Expr {
+        let everything = Expr::And(Vec::new());
+        match self {
+            Expr::And(children) => {
+                let mut children: Vec<Expr> =
+                    children.iter().map(Expr::without_hash).filter(|child| !child.is_empty()).collect();
+                match children.len() {
+                    1 => children.remove(0),
+                    _ => Expr::And(children),
+                }
+            }
+            Expr::Or(children) => {
+                let children: Vec<Expr> = children.iter().map(Expr::without_hash).collect();
+                match children.iter().any(Expr::is_empty) {
+                    true => everything,
+                    false => Expr::Or(children),
+                }
+            }
+            // Dropping a term below NOT would narrow the match
+            Expr::Not(child) if child.without_hash() != **child => everything,
+            Expr::Term(Term::Hash(_)) => everything,
+            expr => expr.clone(),
+        }
+    }
+}
+
+impl Term {
+    pub fn matches(&self, entry: &FileEntry, case_sensitive: bool, content: &ContentConfig) -> bool {
+        match self {
+            Term::Text(pattern) => pattern.matches(text_target(&pattern.text, entry), case_sensitive),
+            Term::Path(pattern) => pattern.matches(&entry.path, case_sensitive),
+            Term::Parent(parent) => {
+                let entry_parent = parent_of(&entry.path, &entry.name);
+                let parent = parent.trim_end_matches(['/', '\\']);
+                if case_sensitive {
+                    entry_parent == parent
+                } else {
+                    entry_parent.to_lowercase() == parent.to_lowercase()
+                }
+            }
+            Term::Extension(extensions) => entry
+                .extension
+                .as_ref()
+                .is_some_and(|ext| extensions.iter().any(|e| e.eq_ignore_ascii_case(ext))),
+            Term::Size(range) => {
+                !entry.is_directory
+                    && range.min.is_none_or(|min| entry.size >= min)
+                    && range.max.is_none_or(|max| entry.size <= max)
+            }
+            Term::DateModified(range) => range.contains(entry.modified),
+            Term::DateCreated(range) => range.contains(entry.created),
+            Term::FolderOnly => entry.is_directory,
+            Term::FileOnly => !entry.is_directory,
+            Term::Content(text) => {
+                !entry.is_directory && content::file_contains(Path::new(&entry.path), text, case_sensitive, content)
+            }
+            Term::Fuzzy(pattern) => {
+                fuzzy_score(&pattern.text, text_target(&pattern.text, entry), case_sensitive).is_some()
+            }
+            Term::Hash(prefix) => !entry.is_directory && hashing::file_has_hash(Path::new(&entry.path), prefix),
+        }
+    }
+}
+
+impl TextPattern {
+    pub fn new(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            wildcard: text.contains(['*', '?']),
+        }
+    }
+
+    pub fn matches(&self, target: &str, case_sensitive: bool) -> bool {
+        if case_sensitive {
+            self.matches_exact_case(target, &self.text)
+        } else {
+            self.matches_exact_case(&target.to_lowercase(), &self.text.to_lowercase())
+        }
+    }
+
+    fn matches_exact_case(&self, target: &str, pattern: &str) -> bool {
+        if self.wildcard {
+            wildcard_match(pattern, target)
+        } else {
+            target.contains(pattern)
+        }
+    }
+}
+
+impl DateRange {
+    pub fn contains(&self, time: DateTime<Utc>) -> bool {
+        self.start.is_none_or(|start| time >= start) && self.end.is_none_or(|end| time < end)
+    }
+}
+
+/// Parent directory of `path`, given that its last component is `name`
+pub fn parent_of<'a>(path: &'a str, name: &str) -> &'a str {
+    path.strip_suffix(name)
+        .map(|parent| parent.trim_end_matches(['/', '\\']))
+        .unwrap_or(path)
+}
+
+fn has_separator(text: &str) -> bool {
+    text.contains(['/', '\\'])
+}
+
+/// Plain text is matched against the name, or the full path if it contains a separator
+fn text_target<'a>(text: &str, entry: &'a FileEntry) -> &'a str {
+    if has_separator(text) {
+        &entry.path
+    } else {
+        &entry.name
+    }
+}
+
+/// Glob match of the whole `target` against `pattern` with `*` and `?`
+pub fn wildcard_match(pattern: &str, target: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let target: Vec<char> = target.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < target.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == target[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// `literal` is set when the word starts with a quote, so a colon in it is not a modifier
+    Word { text: String, literal: bool },
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            c if c.is_whitespace() => i += 1,
+            '|' => {
+                tokens.push(Token::Or);
+                i += 1;
+            }
+            '!' => {
+                tokens.push(Token::Not);
+                i += 1;
+            }
+            '<' => {
+                tokens.push(Token::Open);
+                i += 1;
+            }
+            '>' => {
+                tokens.push(Token::Close);
+                i += 1;
+            }
+            _ => {
+                let mut text = String::new();
+                let literal = chars[i] == '"';
+                let mut in_quotes = false;
+
+                while i < chars.len() {
+                    let c = chars[i];
+                    if c == '"' {
+                        in_quotes = !in_quotes;
+                    } else if in_quotes {
+                        text.push(c);
+                    } else if c.is_whitespace()
+                        || c == '|'
+                        || ((c == '<' || c == '>') && !in_operator_position(&text))
+                    {
+                        break;
+                    } else {
+                        text.push(c);
+                    }
+                    i += 1;
+                }
+
+                if in_quotes {
+                    return Err(SearchError::InvalidQuery("Unterminated quote in query".to_string()).into());
+                }
+                tokens.push(Token::Word { text, literal });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// `<` and `>` directly after a modifier's colon are comparison operators, not grouping
+fn in_operator_position(word: &str) -> bool {
+    match word.split_once(':') {
+        Some((_, value)) => value.chars().all(|c| matches!(c, '<' | '>' | '=')),
+        None => false,
+    }
+}
+
+/// Expression of the saved search with the given name
+pub type SavedResolver<'a> = &'a dyn Fn(&str) -> Result<Expr>;
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    now: DateTime<Local>,
+    saved: Option<SavedResolver<'a>>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut children = Vec::new();
+
+        while let Some(token) = self.peek() {
+            if *token == Token::Close {
+                break;
+            }
+            children.push(self.parse_or()?);
+        }
+
+        Ok(match children.len() {
+            1 => children.remove(0),
+            _ => Expr::And(children),
+        })
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut children = vec![self.parse_unary()?];
+
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            children.push(self.parse_unary()?);
+        }
+
+        Ok(match children.len() {
+            1 => children.remove(0),
+            _ => Expr::Or(children),
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        match self.tokens.get(self.pos).cloned() {
+            Some(Token::Not) => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.parse_unary()?)))
+            }
+            Some(Token::Open) => {
+                self.pos += 1;
+                let expr = self.parse_and()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(SearchError::InvalidQuery("Unmatched '<' in query".to_string()).into());
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            Some(Token::Word { text, literal }) => {
+                self.pos += 1;
+                if literal {
+                    Ok(Expr::Term(Term::Text(TextPattern::new(&text))))
+                } else {
+                    self.parse_word(&text)
+                }
+            }
+            Some(Token::Or) => Err(SearchError::InvalidQuery("'|' must be between two terms".to_string()).into()),
+            Some(Token::Close) | None => {
+                Err(SearchError::InvalidQuery("Expected a search term".to_string()).into())
+            }
+        }
+    }
+
+    fn parse_word(&self, text: &str) -> Result<Expr> {
+        let Some((modifier, value)) = text.split_once(':') else {
+            return Ok(Expr::Term(Term::Text(TextPattern::new(text))));
+        };
+
+        let term = match modifier.to_lowercase().as_str() {
+            "ext" => Term::Extension(parse_extensions(value)?),
+            "size" => Term::Size(parse_size(value)?),
+            "dm" | "datemodified" => Term::DateModified(parse_dates(value, self.now)?),
+            "dc" | "datecreated" => Term::DateCreated(parse_dates(value, self.now)?),
+            "path" => Term::Path(TextPattern::new(require_value("path", value)?)),
+            "parent" => Term::Parent(require_value("parent", value)?.to_string()),
+            "content" => Term::Content(require_value("content", value)?.to_string()),
+            "hash" => Term::Hash(parse_hash(value)?),
+            "saved" => {
+                let name = require_value("saved", value)?;
+                return match self.saved {
+                    Some(resolve) => resolve(name),
+                    None => Err(SearchError::InvalidQuery("'saved:' is not available here".to_string()).into()),
+                };
+            }
+            "folder" | "file" => {
+                let kind = if modifier.eq_ignore_ascii_case("folder") { Term::FolderOnly } else { Term::FileOnly };
+                if value.is_empty() {
+                    kind
+                } else {
+                    return Ok(Expr::And(vec![
+                        Expr::Term(kind),
+                        Expr::Term(Term::Text(TextPattern::new(value))),
+                    ]));
+                }
+            }
+            // Not a modifier, e.g. a drive letter
+            _ => Term::Text(TextPattern::new(text)),
+        };
+
+        Ok(Expr::Term(term))
+    }
+}
+
+fn require_value<'a>(modifier: &str, value: &'a str) -> Result<&'a str> {
+    if value.is_empty() {
+        return Err(SearchError::InvalidQuery(format!("'{}:' needs a value", modifier)).into());
+    }
+    Ok(value)
+}
+
+fn parse_extensions(value: &str) -> Result<Vec<String>> {
+    let extensions: Vec<String> = value
+        .split([';', ','])
+        .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+        .filter(|ext| !ext.is_empty())
+        .collect();
+
+    if extensions.is_empty() {
+        return Err(SearchError::InvalidQuery("'ext:' needs at least one extension".to_string()).into());
+    }
+    Ok(extensions)
+}
+
+fn parse_hash(value: &str) -> Result<String> {
+    let hash = require_value("hash", value)?.to_ascii_lowercase();
+    if !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(SearchError::InvalidQuery(format!("'hash:' needs a hex hash, not '{}'", value)).into());
+    }
+    Ok(hash)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Equal,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+fn split_comparison(value: &str) -> (Comparison, &str) {
+    for (prefix, comparison) in [
+        (">=", Comparison::GreaterOrEqual),
+        ("<=", Comparison::LessOrEqual),
+        (">", Comparison::Greater),
+        ("<", Comparison::Less),
+        ("=", Comparison::Equal),
+    ] {
+        if let Some(rest) = value.strip_prefix(prefix) {
+            return (comparison, rest);
+        }
+    }
+    (Comparison::Equal, value)
+}
+
+fn parse_size(value: &str) -> Result<SizeRange> {
+    let invalid = || SearchError::InvalidQuery(format!("Invalid size: '{}'", value));
+    let (comparison, rest) = split_comparison(value);
+
+    if let Some((from, to)) = rest.split_once("..") {
+        let (from_min, _) = parse_size_value(from).ok_or_else(invalid)?;
+        let (_, to_max) = parse_size_value(to).ok_or_else(invalid)?;
+        return Ok(SizeRange { min: Some(from_min), max: to_max });
+    }
+
+    let (min, max) = parse_size_value(rest).ok_or_else(invalid)?;
+    Ok(match comparison {
+        Comparison::Equal => SizeRange { min: Some(min), max },
+        Comparison::Greater => SizeRange { min: Some(max.ok_or_else(invalid)? + 1), max: None },
+        Comparison::GreaterOrEqual => SizeRange { min: Some(min), max: None },
+        Comparison::Less => SizeRange { min: None, max: Some(min - 1) },
+        Comparison::LessOrEqual => SizeRange { min: None, max: Some(max.ok_or_else(invalid)?) },
+    })
+}
+
+/// A size or Everything size keyword as inclusive bounds
+fn parse_size_value(value: &str) -> Option<(i64, Option<i64>)> {
+    let value = value.trim().to_lowercase();
+
+    let keyword = match value.as_str() {
+        "empty" => Some((0, Some(0))),
+        "tiny" => Some((0, Some(10 * KB))),
+        "small" => Some((10 * KB + 1, Some(100 * KB))),
+        "medium" => Some((100 * KB + 1, Some(MB))),
+        "large" => Some((MB + 1, Some(16 * MB))),
+        "huge" => Some((16 * MB + 1, Some(128 * MB))),
+        "gigantic" => Some((128 * MB + 1, None)),
+        _ => None,
+    };
+    if keyword.is_some() {
+        return keyword;
+    }
+
+    let digits_end = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let number: f64 = value[..digits_end].parse().ok()?;
+    let multiplier = match value[digits_end..].trim() {
+        "" | "b" => 1,
+        "k" | "kb" => KB,
+        "m" | "mb" => MB,
+        "g" | "gb" => GB,
+        "t" | "tb" => TB,
+        _ => return None,
+    };
+
+    let bytes = (number * multiplier as f64) as i64;
+    Some((bytes, Some(bytes)))
+}
+
+fn parse_dates(value: &str, now: DateTime<Local>) -> Result<DateRange> {
+    let invalid = || SearchError::InvalidQuery(format!("Invalid date: '{}'", value));
+    let (comparison, rest) = split_comparison(value);
+
+    if let Some((from, to)) = rest.split_once("..") {
+        let (start, _) = parse_date_value(from, now).ok_or_else(invalid)?;
+        let (_, end) = parse_date_value(to, now).ok_or_else(invalid)?;
+        return Ok(DateRange { start: Some(start), end: Some(end) });
+    }
+
+    let (start, end) = parse_date_value(rest, now).ok_or_else(invalid)?;
+    Ok(match comparison {
+        Comparison::Equal => DateRange { start: Some(start), end: Some(end) },
+        Comparison::Greater => DateRange { start: Some(end), end: None },
+        Comparison::GreaterOrEqual => DateRange { start: Some(start), end: None },
+        Comparison::Less => DateRange { start: None, end: Some(start) },
+        Comparison::LessOrEqual => DateRange { start: None, end: Some(end) },
+    })
+}
+
+/// A date or Everything date keyword as a half-open interval
+fn parse_date_value(value: &str, now: DateTime<Local>) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let value = value.trim().to_lowercase();
+    let today = now.date_naive();
+    let year_start = |year: i32| NaiveDate::from_ymd_opt(year, 1, 1);
+    let month_start = |year: i32, month: u32| NaiveDate::from_ymd_opt(year, month, 1);
+    let next_month = |date: NaiveDate| {
+        if date.month() == 12 {
+            month_start(date.year() + 1, 1)
+        } else {
+            month_start(date.year(), date.month() + 1)
+        }
+    };
+    let week_start = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+
+    let (start, end) = match value.as_str() {
+        "today" => (today, today + Duration::days(1)),
+        "yesterday" => (today - Duration::days(1), today),
+        "thisweek" => (week_start, week_start + Duration::days(7)),
+        "lastweek" => (week_start - Duration::days(7), week_start),
+        "thismonth" => {
+            let start = month_start(today.year(), today.month())?;
+            (start, next_month(start)?)
+        }
+        "lastmonth" => {
+            let end = month_start(today.year(), today.month())?;
+            (month_start((end - Duration::days(1)).year(), (end - Duration::days(1)).month())?, end)
+        }
+        "thisyear" => (year_start(today.year())?, year_start(today.year() + 1)?),
+        "lastyear" => (year_start(today.year() - 1)?, year_start(today.year())?),
+        _ => {
+            let parts: Vec<&str> = value.split(['-', '/']).collect();
+            let numbers: Vec<u32> = parts.iter().map(|p| p.parse().ok()).collect::<Option<_>>()?;
+            match numbers.as_slice() {
+                [year] => (year_start(*year as i32)?, year_start(*year as i32 + 1)?),
+                [year, month] => {
+                    let start = month_start(*year as i32, *month)?;
+                    (start, next_month(start)?)
+                }
+                [year, month, day] => {
+                    let start = NaiveDate::from_ymd_opt(*year as i32, *month, *day)?;
+                    (start, start + Duration::days(1))
+                }
+                _ => return None,
+            }
+        }
+    };
+
+    Some((local_midnight(start)?, local_midnight(end)?))
+}
+
+fn local_midnight(date: NaiveDate) -> Option<DateTime<Utc>> {
+    let midnight = date.and_hms_opt(0, 0, 0)?;
+    match Local.from_local_datetime(&midnight) {
+        LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => Some(time.with_timezone(&Utc)),
+        LocalResult::None => None,
+    }
+}
+
+/// Formats the expression back into Everything syntax
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::And(children) => {
+                let parts: Vec<String> = children.iter().map(|c| c.to_string()).collect();
+                write!(f, "{}", parts.join(" "))
+            }
+            Expr::Or(children) => {
+                let parts: Vec<String> = children.iter().map(|c| c.to_string()).collect();
+                write!(f, "<{}>", parts.join("|"))
+            }
+            Expr::Not(child) => match child.as_ref() {
+                Expr::And(_) => write!(f, "!<{}>", child),
+                _ => write!(f, "!{}", child),
+            },
+            Expr::Term(term) => write!(f, "{}", term),
+        }
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Term::Text(pattern) => write!(f, "{}", quote(&pattern.text)),
+            Term::Path(pattern) => write!(f, "path:{}", quote(&pattern.text)),
+            Term::Parent(parent) => write!(f, "parent:{}", quote(parent)),
+            Term::Extension(extensions) => write!(f, "ext:{}", extensions.join(";")),
+            Term::Size(range) => match (range.min, range.max) {
+                (Some(min), Some(max)) if min == max => write!(f, "size:{}", min),
+                (Some(min), Some(max)) => write!(f, "size:{}..{}", min, max),
+                (Some(min), None) => write!(f, "size:>={}", min),
+                (None, Some(max)) => write!(f, "size:<={}", max),
+                (None, None) => Ok(()),
+            },
+            Term::DateModified(range) => write_date_range(f, "dm", range),
+            Term::DateCreated(range) => write_date_range(f, "dc", range),
+            Term::FolderOnly => write!(f, "folder:"),
+            Term::FileOnly => write!(f, "file:"),
+            Term::Content(text) => write!(f, "content:{}", quote(text)),
+            Term::Hash(prefix) => write!(f, "hash:{}", prefix),
+            // Everything has no fuzzy matching; a wildcard between every character finds the
+            // same entries, and the backend scores them
+            Term::Fuzzy(pattern) => {
+                let mut wildcard = String::from("*");
+                for c in pattern.text.chars().filter(|c| !matches!(c, '*' | '?')) {
+                    wildcard.push(c);
+                    wildcard.push('*');
+                }
+                write!(f, "{}", quote(&wildcard))
+            }
+        }
+    }
+}
+
+fn write_date_range(f: &mut fmt::Formatter<'_>, modifier: &str, range: &DateRange) -> fmt::Result {
+    let format = |time: DateTime<Utc>| {
+        let local = time.with_timezone(&Local);
+        if local.time() == chrono::NaiveTime::MIN {
+            local.format("%Y-%m-%d").to_string()
+        } else {
+            local.format("%Y-%m-%dT%H:%M:%S").to_string()
+        }
+    };
+
+    let mut parts = Vec::new();
+    if let Some(start) = range.start {
+        parts.push(format!("{}:>={}", modifier, format(start)));
+    }
+    if let Some(end) = range.end {
+        parts.push(format!("{}:<{}", modifier, format(end)));
+    }
+    write!(f, "{}", parts.join(" "))
+}
+
+/// `text` as one word of the query language, quoted when it would otherwise be split or
+/// read as a modifier
+pub(crate) fn quote(text: &str) -> String {
+    if text.is_empty() || text.contains(|c: char| c.is_whitespace() || "|<>!\":".contains(c)) {
+        format!("\"{}\"", text.replace('"', ""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{entry, folder};
+
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 5, 15, 12, 0, 0).unwrap()
+    }
+
+    fn parse(input: &str) -> Expr {
+        parse_query_at(input, now()).unwrap()
+    }
+
+    fn text(value: &str) -> Expr {
+        Expr::Term(Term::Text(TextPattern::new(value)))
+    }
+
+    #[test]
+    fn test_or_binds_tighter_than_and() {
+        assert_eq!(
+            parse("foo bar|baz !qux"),
+            Expr::And(vec![
+                text("foo"),
+                Expr::Or(vec![text("bar"), text("baz")]),
+                Expr::Not(Box::new(text("qux"))),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_grouping_quotes_and_modifiers() {
+        assert_eq!(
+            parse(r#"<"annual report"|summary> ext:pdf;.DOCX"#),
+            Expr::And(vec![
+                Expr::Or(vec![text("annual report"), text("summary")]),
+                Expr::Term(Term::Extension(vec!["pdf".to_string(), "docx".to_string()])),
+            ])
+        );
+        assert_eq!(parse("C:\\Users"), text("C:\\Users"));
+        assert_eq!(parse(r#""size:large""#), text("size:large"));
+        assert!(parse_query_at("<foo", now()).is_err());
+        assert!(parse_query_at("foo>", now()).is_err());
+        assert!(parse_query_at("\"foo", now()).is_err());
+    }
+
+    #[test]
+    fn test_size_comparisons() {
+        assert_eq!(parse("size:>1mb"), Expr::Term(Term::Size(SizeRange { min: Some(MB + 1), max: None })));
+        assert_eq!(parse("size:<=10kb"), Expr::Term(Term::Size(SizeRange { min: None, max: Some(10 * KB) })));
+        assert_eq!(parse("size:1kb..2kb"), Expr::Term(Term::Size(SizeRange { min: Some(KB), max: Some(2 * KB) })));
+        assert_eq!(parse("size:huge"), Expr::Term(Term::Size(SizeRange { min: Some(16 * MB + 1), max: Some(128 * MB) })));
+        assert!(parse_query_at("size:lots", now()).is_err());
+    }
+
+    #[test]
+    fn test_dates_resolve_against_now() {
+        let Expr::Term(Term::DateModified(range)) = parse("dm:today") else { panic!() };
+        assert!(range.contains(now().with_timezone(&Utc)));
+        assert!(!range.contains((now() - Duration::days(1)).with_timezone(&Utc)));
+
+        let Expr::Term(Term::DateCreated(range)) = parse("dc:>2024") else { panic!() };
+        assert_eq!(range.start, local_midnight(NaiveDate::from_ymd_opt(2025, 1, 1).unwrap()));
+        assert_eq!(range.end, None);
+
+        let Expr::Term(Term::DateModified(range)) = parse("dm:2024-02..2024-03") else { panic!() };
+        assert_eq!(range.end, local_midnight(NaiveDate::from_ymd_opt(2024, 4, 1).unwrap()));
+    }
+
+    #[test]
+    fn test_matching() {
+        let readme = FileEntry { size: 2048, ..entry("/home/me/src/README.md") };
+        let src = folder("/home/me/src");
+        let content = ContentConfig::default();
+
+        assert!(parse("readme").matches(&readme, false, &content));
+        assert!(!parse("readme").matches(&readme, true, &content));
+        assert!(parse("*.md").matches(&readme, false, &content));
+        assert!(!parse("read*.m").matches(&readme, false, &content));
+        assert!(parse("me/src").matches(&readme, false, &content));
+        assert!(parse("parent:/home/me/src/ size:>1kb").matches(&readme, false, &content));
+        assert!(parse("path:home src").matches(&src, false, &content));
+        assert!(parse("folder:src").matches(&src, false, &content));
+        assert!(!parse("folder:src").matches(&readme, false, &content));
+        assert!(parse("!ext:rs <readme|license>").matches(&readme, false, &content));
+    }
+
+    #[test]
+    fn test_without_hash_only_ever_widens() {
+        assert_eq!(parse("report hash:af09 ext:pdf").without_hash(), parse("report ext:pdf"));
+        assert_eq!(parse("report <hash:af09|draft>").without_hash(), parse("report"));
+        assert_eq!(parse("report !<hash:af09 draft>").without_hash(), parse("report"));
+        assert!(parse("hash:af09").without_hash().is_empty());
+        assert_eq!(parse("<a|b> !c").without_hash(), parse("<a|b> !c"));
+    }
+
+    #[test]
+    fn test_display_round_trips() {
+        for input in [
+            "foo <bar|baz> !qux",
+            "ext:pdf;doc size:>=100",
+            r#""two words" path:src content:"a b""#,
+            "folder: dm:>=2024-05-15 dm:<2024-05-16 hash:af09",
+        ] {
+            let expr = parse(input);
+            assert_eq!(parse(&expr.to_string()), expr, "{}", input);
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+
+use crate::config::RankingConfig;
+use crate::database::{Database, FileUsage};
+use crate::search::query::CompiledQuery;
+use crate::search::sort::{relevance_needle, Sorter};
+use crate::types::{FileEntry, SearchQuery};
+
+/// Best matches ranked for each relevance-sorted query, so files that rank well on recency
+/// or usage but have a weaker match can still reach the first page. Later matches keep
+/// their match order.
+pub const RANK_CANDIDATES: usize = 500;
+
+/// Orders relevance-sorted results by a weighted blend of match quality, exact-name hits,
+/// recency, path depth and how often the user opened the file
+pub struct Ranker {
+    db: Arc<Database>,
+    config: RankingConfig,
+}
+
+impl Ranker {
+    pub fn new(db: Arc<Database>, config: RankingConfig) -> Self {
+        Self { db, config }
+    }
+
+    /// Reorder `entries`, best first unless the query's sort is descending. Ties keep the
+    /// order of `Sorter`.
+    pub async fn rank(&self, query: &SearchQuery, entries: &mut Vec<FileEntry>) -> Result<()> {
+        let ids: Vec<&str> = entries.iter().map(|entry| entry.id.as_str()).collect();
+        let usage = self.db.file_usage(&ids).await?;
+        let needle = relevance_needle(&CompiledQuery::compile(query)?);
+        let sorter = Sorter::new(query.sort, needle.clone());
+        let max_score = entries.iter().filter_map(|entry| entry.score).max();
+        let now = Utc::now();
+
+        let mut ranked: Vec<(f64, FileEntry)> = entries
+            .drain(..)
+            .map(|entry| {
+                let signals = Signals::of(&entry, needle.as_deref(), max_score, usage.get(&entry.id), now, &self.config);
+                (signals.blend(&self.config), entry)
+            })
+            .collect();
+
+        ranked.sort_by(|(a_rank, a), (b_rank, b)| {
+            let ordering = b_rank.total_cmp(a_rank);
+            let ordering = if query.sort.descending { ordering.reverse() } else { ordering };
+            ordering.then_with(|| sorter.compare(a, b))
+        });
+        entries.extend(ranked.into_iter().map(|(_, entry)| entry));
+        Ok(())
+    }
+}
+
+/// Each signal of one result, between 0 and 1
+#[derive(Debug, Default)]
+struct Signals {
+    match_quality: f64,
+    exact: f64,
+    recency: f64,
+    depth: f64,
+    usage: f64,
+}
+
+impl Signals {
+    fn of(
+        entry: &FileEntry,
+        needle: Option<&str>,
+        max_score: Option<i32>,
+        usage: Option<&FileUsage>,
+        now: DateTime<Utc>,
+        config: &RankingConfig,
+    ) -> Self {
+        let decay = |time: DateTime<Utc>| {
+            let days = (now - time).num_seconds().max(0) as f64 / 86_400.0;
+            0.5f64.powf(days / config.half_life_days.max(f64::EPSILON))
+        };
+
+        let match_quality = match (entry.score, max_score, needle) {
+            (Some(score), Some(max), _) if max > 0 => score.max(0) as f64 / max as f64,
+            (_, _, Some(needle)) => match_position(&entry.name, needle),
+            _ => 0.0,
+        };
+
+        let exact = needle.is_some_and(|needle| {
+            let stem = entry.name.rsplit_once('.').map_or(entry.name.as_str(), |(stem, _)| stem);
+            entry.name.eq_ignore_ascii_case(needle) || stem.eq_ignore_ascii_case(needle)
+        });
+
+        let depth = entry.path.split(['/', '\\']).filter(|part| !part.is_empty()).count().max(1);
+
+        Self {
+            match_quality,
+            exact: if exact { 1.0 } else { 0.0 },
+            recency: decay(entry.modified),
+            depth: 1.0 / depth as f64,
+            usage: usage.map_or(0.0, |usage| {
+                let frequency = 1.0 - 1.0 / (1.0 + usage.open_count as f64);
+                frequency * decay(usage.last_opened)
+            }),
+        }
+    }
+
+    fn blend(&self, config: &RankingConfig) -> f64 {
+        self.match_quality * config.match_weight
+            + self.exact * config.exact_weight
+            + self.recency * config.recency_weight
+            + self.depth * config.depth_weight
+            + self.usage * config.usage_weight
+    }
+}
+
+/// 1 for a prefix of `name`, 0.75 for the start of a later word, 0.5 mid-word and 0 when the
+/// query matched something other than the name
+fn match_position(name: &str, needle: &str) -> f64 {
+    let lower = name.to_ascii_lowercase();
+    let mut best: f64 = 0.0;
+
+    for (index, _) in lower.match_indices(needle) {
+        let quality = match name[..index].chars().next_back() {
+            None => 1.0,
+            Some(prev) if !prev.is_alphanumeric() => 0.75,
+            Some(prev) if prev.is_lowercase() && name[index..].starts_with(char::is_uppercase) => 0.75,
+            Some(_) => 0.5,
+        };
+        best = best.max(quality);
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{days_ago, entry, query};
+    use crate::types::{Sort, SortField};
+
+    struct Fixture {
+        _dir: tempfile::TempDir,
+        db: Arc<Database>,
+        ranker: Ranker,
+        query: SearchQuery,
+        entries: Vec<FileEntry>,
+    }
+
+    /// Four matches of "notes" of different ages and depths
+    async fn fixture() -> Fixture {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(Database::new(&dir.path().join("index.db")).await.unwrap());
+        let ranker = Ranker::new(db.clone(), RankingConfig::default());
+        let query = SearchQuery { sort: Sort { field: SortField::Relevance, descending: false }, ..query("notes") };
+        let entries = [
+            ("/archive/2019/old/meeting-notes.txt", 900),
+            ("/home/me/release_notes.md", 30),
+            ("/home/me/notes.md", 2),
+            ("/home/me/projects/app/src/keynotes.rs", 1),
+        ]
+        .into_iter()
+        .map(|(path, age_days)| FileEntry { modified: days_ago(age_days), ..entry(path) })
+        .collect();
+        Fixture { _dir: dir, db, ranker, query, entries }
+    }
+
+    fn names(entries: &[FileEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_rank_blends_match_recency_and_depth() {
+        let Fixture { _dir, ranker, query, mut entries, .. } = fixture().await;
+        ranker.rank(&query, &mut entries).await.unwrap();
+        assert_eq!(names(&entries), vec!["notes.md", "release_notes.md", "keynotes.rs", "meeting-notes.txt"]);
+    }
+
+    #[tokio::test]
+    async fn test_a_file_opened_often_overtakes_newer_and_shallower_matches() {
+        let Fixture { _dir, db, ranker, query, mut entries } = fixture().await;
+        for _ in 0..5 {
+            db.record_file_open(&entries[0].id).await.unwrap();
+        }
+        ranker.rank(&query, &mut entries).await.unwrap();
+        assert_eq!(names(&entries), vec!["notes.md", "meeting-notes.txt", "release_notes.md", "keynotes.rs"]);
+    }
+}
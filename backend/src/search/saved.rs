@@ -0,0 +1,284 @@
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::database::Database;
+use crate::search::query::{filter_terms, parse_query_with, with_content, with_fuzzy, Expr};
+use crate::types::{SavedSearch, SearchError, SearchQuery};
+
+/// Searches saved by name, which other queries can refer to as `saved:<name>`
+pub struct SavedSearches {
+    db: Arc<Database>,
+}
+
+impl SavedSearches {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self { db }
+    }
+
+    pub async fn list(&self) -> Result<Vec<SavedSearch>> {
+        self.db.saved_searches().await
+    }
+
+    /// Save a new search; fails if one of the same name exists
+    pub async fn create(&self, saved: &SavedSearch) -> Result<()> {
+        let saved = self.validate(saved, None).await?;
+        if !self.db.insert_saved_search(&saved, false).await? {
+            return Err(anyhow::anyhow!("A saved search named '{}' already exists", saved.name));
+        }
+        Ok(())
+    }
+
+    /// Replace the search called `name`, possibly under a new name
+    pub async fn update(&self, name: &str, saved: &SavedSearch) -> Result<()> {
+        let saved = self.validate(saved, Some(name)).await?;
+        if !self.db.update_saved_search(name, &saved).await? {
+            return Err(anyhow::anyhow!("No saved search named '{}'", name));
+        }
+        Ok(())
+    }
+
+    /// Returns whether there was a search called `name`; fails if another search refers to it
+    pub async fn delete(&self, name: &str) -> Result<bool> {
+        let mut searches = self.list().await?;
+        searches.retain(|saved| !same_name(&saved.name, name));
+        check_expansions(searches, &[])?;
+        self.db.delete_saved_search(name).await
+    }
+
+    /// Save every search in the JSON file at `path`, replacing searches of the same name.
+    /// Returns how many were imported.
+    pub async fn import(&self, path: &Path) -> Result<usize> {
+        let contents = tokio::fs::read_to_string(path).await?;
+        let imported: Vec<SavedSearch> = serde_json::from_str::<Vec<SavedSearch>>(&contents)?
+            .into_iter()
+            .map(|saved| SavedSearch { name: saved.name.trim().to_string(), ..saved })
+            .collect();
+
+        // Imported searches may refer to each other, so all of them are checked together
+        let mut searches = self.list().await?;
+        searches.retain(|saved| !imported.iter().any(|other| same_name(&other.name, &saved.name)));
+        searches.extend(imported.iter().cloned());
+        if imported.iter().any(|saved| saved.name.is_empty()) {
+            return Err(SearchError::InvalidQuery("A saved search needs a name".to_string()).into());
+        }
+        let names: Vec<&str> = imported.iter().map(|saved| saved.name.as_str()).collect();
+        check_expansions(searches, &names)?;
+
+        for saved in &imported {
+            self.db.insert_saved_search(saved, true).await?;
+        }
+        Ok(imported.len())
+    }
+
+    /// Write every saved search to `path` as JSON, in the format `import` reads
+    pub async fn export(&self, path: &Path) -> Result<usize> {
+        let saved = self.list().await?;
+        tokio::fs::write(path, serde_json::to_string_pretty(&saved)?).await?;
+        Ok(saved.len())
+    }
+
+    /// `query` with each `saved:<name>` replaced by the saved query, together with those of
+    /// its filters that can be written as terms. Case sensitivity and hidden files follow
+    /// `query`; saved regex searches cannot be used as terms.
+    pub async fn expand(&self, query: &SearchQuery) -> Result<SearchQuery> {
+        if query.filters.use_regex || !query.query.to_lowercase().contains("saved:") {
+            return Ok(query.clone());
+        }
+
+        let expander = Expander::new(self.list().await?, Local::now());
+        let expr = parse_query_with(&query.query, expander.now, Some(&|name| expander.resolve(name)))?;
+        Ok(SearchQuery {
+            query: expr.to_string(),
+            ..query.clone()
+        })
+    }
+
+    /// Trim the name and check that the search can be expanded once saved in place of
+    /// `replacing`
+    async fn validate(&self, saved: &SavedSearch, replacing: Option<&str>) -> Result<SavedSearch> {
+        let saved = SavedSearch {
+            name: saved.name.trim().to_string(),
+            ..saved.clone()
+        };
+        if saved.name.is_empty() {
+            return Err(SearchError::InvalidQuery("A saved search needs a name".to_string()).into());
+        }
+
+        let mut searches = self.list().await?;
+        searches.retain(|other| {
+            !same_name(&other.name, &saved.name) && replacing.is_none_or(|replacing| !same_name(&other.name, replacing))
+        });
+        searches.push(saved.clone());
+        check_expansions(searches, &[&saved.name])?;
+        Ok(saved)
+    }
+}
+
+/// Saved search names ignore ASCII case, like the `NOCASE` names in the database
+fn same_name(a: &str, b: &str) -> bool {
+    a.eq_ignore_ascii_case(b)
+}
+
+/// Check that every search in `searches` expands, so that no change leaves a `saved:` term
+/// that is dangling, cyclic or names a regex search. The `changed` searches come first and
+/// report their own errors.
+fn check_expansions(searches: Vec<SavedSearch>, changed: &[&str]) -> Result<()> {
+    let expander = Expander::new(searches, Local::now());
+    let mut expandable: Vec<(bool, &str)> = expander
+        .searches
+        .values()
+        .filter(|saved| !saved.filters.use_regex)
+        .map(|saved| (!changed.iter().any(|name| same_name(name, &saved.name)), saved.name.as_str()))
+        .collect();
+    expandable.sort_unstable();
+
+    for (unchanged, name) in expandable {
+        if let Err(e) = expander.resolve(name) {
+            if !unchanged {
+                return Err(e);
+            }
+            return Err(SearchError::InvalidQuery(format!("Saved search '{}' would no longer expand", name)).into());
+        }
+    }
+    Ok(())
+}
+
+/// Resolves `saved:` terms, including those inside saved searches
+struct Expander {
+    searches: HashMap<String, SavedSearch>,
+    now: DateTime<Local>,
+    /// ASCII-lowercased names being expanded, to reject searches that refer to themselves
+    expanding: RefCell<Vec<String>>,
+}
+
+impl Expander {
+    fn new(searches: Vec<SavedSearch>, now: DateTime<Local>) -> Self {
+        Self {
+            searches: searches.into_iter().map(|saved| (saved.name.to_ascii_lowercase(), saved)).collect(),
+            now,
+            expanding: RefCell::default(),
+        }
+    }
+
+    fn resolve(&self, name: &str) -> Result<Expr> {
+        let key = name.to_ascii_lowercase();
+        let saved = self
+            .searches
+            .get(&key)
+            .ok_or_else(|| SearchError::InvalidQuery(format!("No saved search named '{}'", name)))?;
+        if saved.filters.use_regex {
+            return Err(SearchError::InvalidQuery(format!("Saved search '{}' is a regex and cannot be a term", name)).into());
+        }
+        if self.expanding.borrow().contains(&key) {
+            return Err(SearchError::InvalidQuery(format!("Saved search '{}' refers to itself", name)).into());
+        }
+
+        self.expanding.borrow_mut().push(key);
+        let expr = parse_query_with(&saved.query, self.now, Some(&|name| self.resolve(name)));
+        self.expanding.borrow_mut().pop();
+
+        let mut expr = expr?;
+        if saved.filters.search_content {
+            expr = with_content(expr);
+        }
+        if saved.filters.fuzzy {
+            expr = with_fuzzy(expr);
+        }
+        let mut terms = vec![expr];
+        terms.extend(filter_terms(&saved.filters));
+        Ok(match terms.len() {
+            1 => terms.remove(0),
+            _ => Expr::And(terms),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::query;
+    use crate::types::{SearchFilters, Sort, SortField};
+
+    fn saved(name: &str, query: &str) -> SavedSearch {
+        SavedSearch {
+            name: name.to_string(),
+            query: query.to_string(),
+            filters: SearchFilters::default(),
+            sort: Sort { field: SortField::Size, descending: true },
+        }
+    }
+
+    /// "Pictures", with type filters, and "big", which refers to it
+    async fn searches() -> (tempfile::TempDir, SavedSearches) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(Database::new(&dir.path().join("index.db")).await.unwrap());
+        let searches = SavedSearches::new(db);
+
+        let pictures = SearchFilters { file_types: vec!["png".to_string(), "jpg".to_string()], ..Default::default() };
+        searches.create(&SavedSearch { filters: pictures, ..saved("Pictures", "holiday | trip") }).await.unwrap();
+        searches.create(&saved("big", "saved:pictures size:>1mb")).await.unwrap();
+        (dir, searches)
+    }
+
+    #[tokio::test]
+    async fn test_saved_terms_expand_with_their_filters() {
+        let (_dir, searches) = searches().await;
+        let expanded = searches.expand(&query("!saved:BIG 2024")).await.unwrap();
+        assert_eq!(expanded.query, "!<<holiday|trip> ext:png;jpg size:>=1048577> 2024");
+    }
+
+    #[tokio::test]
+    async fn test_names_are_unique_ignoring_case() {
+        let (_dir, searches) = searches().await;
+        assert!(searches.create(&saved("BIG", "x")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cycles_and_unknown_references_are_rejected() {
+        let (_dir, searches) = searches().await;
+        assert!(searches.create(&saved("loop", "saved:loop")).await.is_err());
+        assert!(searches.create(&saved("missing", "saved:nope")).await.is_err());
+        assert!(searches.update("pictures", &saved("pictures", "saved:big")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_referenced_searches_cannot_be_renamed_or_deleted() {
+        let (_dir, searches) = searches().await;
+        assert!(searches.update("pictures", &saved(" pics ", "photo")).await.is_err());
+        let filters = SearchFilters { use_regex: true, ..Default::default() };
+        let regex = SavedSearch { filters, ..saved("pictures", "x") };
+        assert!(searches.update("pictures", &regex).await.is_err());
+        assert!(searches.delete("PICTURES").await.is_err());
+        assert!(searches.expand(&query("saved:big")).await.is_ok());
+
+        searches.update("big", &saved("big", "size:>1mb")).await.unwrap();
+        searches.update("pictures", &saved(" pics ", "photo")).await.unwrap();
+        assert!(searches.delete("pics").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_names_only_ignore_ascii_case() {
+        let (_dir, searches) = searches().await;
+        searches.create(&saved("Äpfel", "apple")).await.unwrap();
+        searches.create(&saved("äpfel", "pear")).await.unwrap();
+        assert_eq!(searches.expand(&query("saved:ÄPFEL")).await.unwrap().query, "apple");
+        assert_eq!(searches.expand(&query("saved:äPFEL")).await.unwrap().query, "pear");
+    }
+
+    #[tokio::test]
+    async fn test_export_and_import_round_trip() {
+        let (dir, searches) = searches().await;
+        let file = dir.path().join("saved.json");
+        assert_eq!(searches.export(&file).await.unwrap(), 2);
+        assert!(searches.delete("big").await.unwrap());
+        assert!(searches.delete("pictures").await.unwrap());
+        assert!(!searches.delete("pictures").await.unwrap());
+        assert_eq!(searches.import(&file).await.unwrap(), 2);
+        let names: Vec<_> = searches.list().await.unwrap().into_iter().map(|saved| saved.name).collect();
+        assert_eq!(names, vec!["big", "Pictures"]);
+    }
+}
@@ -0,0 +1,231 @@
+use anyhow::Result;
+use std::cmp::Ordering;
+
+use crate::search::query::{CompiledQuery, Expr, Term};
+use crate::types::{FileEntry, SearchQuery, Sort, SortField};
+
+/// Name of the SQLite collation registered with `natural_cmp`
+pub const NATURAL_COLLATION: &str = "natural_order";
+
+/// Orders entries by a `Sort`, the same way every backend does
+#[derive(Debug, Clone)]
+pub struct Sorter {
+    sort: Sort,
+    /// Lowercase text that relevance is measured against
+    needle: Option<String>,
+}
+
+impl Sorter {
+    pub fn new(sort: Sort, needle: Option<String>) -> Self {
+        Self { sort, needle }
+    }
+
+    pub fn for_query(query: &SearchQuery) -> Result<Self> {
+        let compiled = CompiledQuery::compile(query)?;
+        Ok(Self::new(query.sort, relevance_needle(&compiled)))
+    }
+
+    pub fn sort(&self) -> Sort {
+        self.sort
+    }
+
+    pub fn needle(&self) -> Option<&str> {
+        self.needle.as_deref()
+    }
+
+    /// Same sorter with a different order, relevant to the same query
+    pub fn with_sort(&self, sort: Sort) -> Self {
+        Self::new(sort, self.needle.clone())
+    }
+
+    /// The sort column, then name and path to break ties, so descending order is the exact
+    /// reverse of ascending order
+    pub fn compare(&self, a: &FileEntry, b: &FileEntry) -> Ordering {
+        let primary = match self.sort.field {
+            SortField::Name | SortField::Path => Ordering::Equal,
+            SortField::Size => a.size.cmp(&b.size),
+            SortField::Extension => match (&a.extension, &b.extension) {
+                (Some(a), Some(b)) => natural_cmp(a, b),
+                (a, b) => a.is_some().cmp(&b.is_some()),
+            },
+            SortField::DateModified => a.modified.cmp(&b.modified),
+            SortField::DateCreated => a.created.cmp(&b.created),
+            // Fuzzy scores come first, higher is better; entries without one follow
+            SortField::Relevance if a.score.is_some() || b.score.is_some() => b
+                .score
+                .cmp(&a.score)
+                .then_with(|| a.name.chars().count().cmp(&b.name.chars().count())),
+            SortField::Relevance => match self.needle.as_deref() {
+                Some(needle) => relevance_rank(&a.name, needle)
+                    .cmp(&relevance_rank(&b.name, needle))
+                    .then_with(|| a.name.chars().count().cmp(&b.name.chars().count())),
+                None => Ordering::Equal,
+            },
+        };
+
+        let name = if self.sort.field == SortField::Path {
+            Ordering::Equal
+        } else {
+            natural_cmp(&a.name, &b.name)
+        };
+
+        let ordering = primary
+            .then(name)
+            .then_with(|| natural_cmp(&a.path, &b.path))
+            .then_with(|| a.path.cmp(&b.path));
+
+        if self.sort.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+
+    pub fn sort_entries(&self, entries: &mut [FileEntry]) {
+        entries.sort_by(|a, b| self.compare(a, b));
+    }
+
+    /// Order `indices` into `entries`
+    pub fn sort_indices(&self, entries: &[FileEntry], indices: &mut [usize]) {
+        indices.sort_by(|&a, &b| self.compare(&entries[a], &entries[b]));
+    }
+}
+
+/// Case-insensitive comparison that orders runs of digits by their numeric value, so
+/// `file2` sorts before `file10`
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+
+    loop {
+        let (Some(x), Some(y)) = (a.chars().next(), b.chars().next()) else {
+            return b.is_empty().cmp(&a.is_empty());
+        };
+
+        if x.is_ascii_digit() && y.is_ascii_digit() {
+            let a_end = a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len());
+            let b_end = b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len());
+            let (a_digits, b_digits) = (a[..a_end].trim_start_matches('0'), b[..b_end].trim_start_matches('0'));
+
+            let ordering = a_digits.len().cmp(&b_digits.len()).then_with(|| a_digits.cmp(b_digits));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+            (a, b) = (&a[a_end..], &b[b_end..]);
+        } else {
+            let ordering = x.to_lowercase().cmp(y.to_lowercase());
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+            (a, b) = (&a[x.len_utf8()..], &b[y.len_utf8()..]);
+        }
+    }
+}
+
+/// How well `name` matches `needle`, lower is better: the whole name, the name without its
+/// extension, a prefix, anywhere in the name, or elsewhere (the path or another term).
+/// ASCII case folding only, to agree with SQLite's `LOWER`.
+pub fn relevance_rank(name: &str, needle: &str) -> u8 {
+    let (name, needle) = (name.as_bytes(), needle.as_bytes());
+    let is_prefix = name.len() >= needle.len() && name[..needle.len()].eq_ignore_ascii_case(needle);
+
+    if is_prefix && name.len() == needle.len() {
+        0
+    } else if is_prefix && name[needle.len()] == b'.' {
+        1
+    } else if is_prefix {
+        2
+    } else if name.windows(needle.len()).any(|window| window.eq_ignore_ascii_case(needle)) {
+        3
+    } else {
+        4
+    }
+}
+
+/// The first plain text term of the query, which relevance is ranked by
+pub fn relevance_needle(compiled: &CompiledQuery) -> Option<String> {
+    if compiled.regex.is_some() {
+        return None;
+    }
+
+    fn first_text(expr: &Expr) -> Option<&str> {
+        match expr {
+            Expr::And(children) | Expr::Or(children) => children.iter().find_map(first_text),
+            Expr::Term(Term::Text(pattern)) if !pattern.wildcard && !pattern.text.contains(['/', '\\']) => {
+                Some(&pattern.text)
+            }
+            _ => None,
+        }
+    }
+
+    first_text(&compiled.expr)
+        .filter(|text| !text.is_empty())
+        .map(|text| text.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use crate::test_support::{days_ago, entry, query};
+    use std::sync::Arc;
+
+    fn report(path: &str, size: i64, age_days: i64) -> FileEntry {
+        let time = days_ago(age_days);
+        FileEntry { size, modified: time, created: time, ..entry(path) }
+    }
+
+    fn reports() -> Vec<FileEntry> {
+        vec![
+            report("/d/report10.txt", 10, 3),
+            report("/d/report2.txt", 30, 1),
+            report("/d/Report.md", 20, 2),
+            report("/d/old/quarterly report.pdf", 30, 5),
+            report("/d/report", 0, 4),
+        ]
+    }
+
+    #[test]
+    fn test_natural_cmp_orders_digit_runs_by_value_and_ignores_case() {
+        assert_eq!(natural_cmp("file2.txt", "File10.txt"), Ordering::Less);
+        assert_eq!(natural_cmp("v010", "v9"), Ordering::Greater);
+        assert_eq!(natural_cmp("abc", "ABC"), Ordering::Equal);
+    }
+
+    #[tokio::test]
+    async fn test_index_orders_like_the_sorter_for_every_sort() {
+        let entries = reports();
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(Database::new(&dir.path().join("index.db")).await.unwrap());
+        db.batch_insert_file_entries(&entries).await.unwrap();
+
+        let fields = [
+            SortField::Name,
+            SortField::Path,
+            SortField::Size,
+            SortField::Extension,
+            SortField::DateModified,
+            SortField::DateCreated,
+            SortField::Relevance,
+        ];
+        for field in fields {
+            for descending in [false, true] {
+                let query = SearchQuery { sort: Sort { field, descending }, ..query("report") };
+
+                let mut expected = entries.clone();
+                Sorter::for_query(&query).unwrap().sort_entries(&mut expected);
+                let expected: Vec<_> = expected.into_iter().map(|e| e.path).collect();
+                let actual: Vec<_> = db.search(&query).await.unwrap().entries.into_iter().map(|e| e.path).collect();
+                assert_eq!(actual, expected, "{:?} descending={}", field, descending);
+            }
+        }
+    }
+
+    #[test]
+    fn test_relevance_puts_exact_names_then_prefixes_first() {
+        let by_relevance = Sorter::new(Sort { field: SortField::Relevance, descending: false }, Some("report".into()));
+        let mut ranked = reports();
+        by_relevance.sort_entries(&mut ranked);
+        let names: Vec<_> = ranked.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["report", "Report.md", "report2.txt", "report10.txt", "quarterly report.pdf"]);
+    }
+}
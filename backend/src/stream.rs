@@ -0,0 +1,116 @@
+//! Delivery of search results in batches while the search is still running
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+use crate::types::FileEntry;
+
+/// Receives the entries of a streamed search as backends find them, and tells the search
+/// when to stop. Entries are sent at most once per path, however many backends find them.
+#[derive(Clone)]
+pub struct ResultSink {
+    batches: mpsc::UnboundedSender<Vec<FileEntry>>,
+    cancel: CancelHandle,
+    sent: Arc<Mutex<HashSet<String>>>,
+}
+
+/// Stops the search of a `ResultSink` from anywhere
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Whether both handles stop the same search
+    pub fn same_as(&self, other: &CancelHandle) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl ResultSink {
+    /// A sink and the receiving end of its batches, which closes once every clone of the
+    /// sink is dropped
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<Vec<FileEntry>>) {
+        let (batches, receiver) = mpsc::unbounded_channel();
+        let sink = Self {
+            batches,
+            cancel: CancelHandle::default(),
+            sent: Arc::default(),
+        };
+        (sink, receiver)
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    /// The search was cancelled, or nobody is receiving its batches any more
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled() || self.batches.is_closed()
+    }
+
+    /// Send the entries that were not sent before; returns false once the search should stop
+    pub fn send(&self, mut entries: Vec<FileEntry>) -> bool {
+        if self.is_cancelled() {
+            return false;
+        }
+
+        let mut sent = self.sent.lock().unwrap();
+        entries.retain(|entry| sent.insert(entry.path.clone()));
+        drop(sent);
+
+        entries.is_empty() || self.batches.send(entries).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WalkConfig;
+    use crate::fallback::FallbackSearch;
+    use crate::search::SearchEngine;
+    use crate::types::{SearchError, SearchQuery};
+
+    #[tokio::test]
+    async fn test_streams_batches_and_stops_when_cancelled() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..600 {
+            std::fs::write(dir.path().join(format!("note{}.txt", i)), "").unwrap();
+        }
+        let config = WalkConfig { roots: vec![dir.path().to_path_buf()], max_depth: 4, time_budget_ms: 10_000 };
+        let walk = Arc::new(FallbackSearch::new(config, Vec::new()));
+        // The same files from two backends are only streamed once
+        let engine = SearchEngine::new(vec![walk.clone(), walk]);
+        let query = SearchQuery {
+            query: "note".to_string(),
+            filters: Default::default(),
+            limit: Some(1000),
+            offset: None,
+            sort: Default::default(),
+        };
+
+        let (sink, mut batches) = ResultSink::new();
+        let result = engine.search_streaming(&query, &sink).await.unwrap();
+        drop(sink);
+        let mut streamed = Vec::new();
+        while let Some(batch) = batches.recv().await {
+            streamed.push(batch.len());
+        }
+        assert_eq!(result.entries.len(), 600);
+        assert_eq!(streamed.iter().sum::<usize>(), 600);
+        assert!(streamed.len() > 1, "matches arrive in several batches");
+
+        let (sink, _batches) = ResultSink::new();
+        sink.cancel_handle().cancel();
+        let error = engine.search_streaming(&query, &sink).await.unwrap_err();
+        assert!(matches!(error.downcast_ref(), Some(SearchError::Cancelled)));
+    }
+}
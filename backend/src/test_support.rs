@@ -0,0 +1,60 @@
+//! Fixtures shared by the unit tests
+
+use chrono::{DateTime, Duration, Utc};
+use std::path::Path;
+
+use crate::types::{FileEntry, SearchQuery};
+
+/// A file at `path` of size 0, modified now, named and typed after its last component.
+/// Other fields are set with struct update syntax.
+pub(crate) fn entry(path: &str) -> FileEntry {
+    let name = path.rsplit(['/', '\\']).find(|part| !part.is_empty()).unwrap_or(path);
+    let now = Utc::now();
+    FileEntry {
+        id: FileEntry::path_id(Path::new(path)),
+        name: name.to_string(),
+        path: path.to_string(),
+        size: 0,
+        modified: now,
+        created: now,
+        is_directory: false,
+        extension: Path::new(name).extension().map(|ext| ext.to_string_lossy().to_lowercase()),
+        attributes: 0,
+        score: None,
+        source: None,
+    }
+}
+
+/// A folder at `path`
+pub(crate) fn folder(path: &str) -> FileEntry {
+    FileEntry { is_directory: true, extension: None, ..entry(path) }
+}
+
+/// The entry the indexer would store for the existing file at `path`
+pub(crate) fn on_disk(path: &Path) -> FileEntry {
+    let metadata = std::fs::metadata(path).unwrap();
+    let modified = metadata.modified().unwrap().into();
+    FileEntry {
+        id: FileEntry::file_id(path, &metadata),
+        size: if metadata.is_dir() { 0 } else { metadata.len() as i64 },
+        modified,
+        created: modified,
+        ..if metadata.is_dir() { folder(&path.to_string_lossy()) } else { entry(&path.to_string_lossy()) }
+    }
+}
+
+/// A query for `text` with default filters and sort, and no paging
+pub(crate) fn query(text: &str) -> SearchQuery {
+    SearchQuery {
+        query: text.to_string(),
+        filters: Default::default(),
+        limit: None,
+        offset: None,
+        sort: Default::default(),
+    }
+}
+
+/// The time `days` days ago
+pub(crate) fn days_ago(days: i64) -> DateTime<Utc> {
+    Utc::now() - Duration::days(days)
+}
@@ -1,8 +1,13 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::fs::Metadata;
+use std::path::Path;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileEntry {
+    /// Identifies the file across queries, backends and restarts. On Unix it is derived
+    /// from the device and inode, so it survives renames; hard-linked files and all files
+    /// on other platforms use the normalized path. See `FileEntry::file_id`.
     pub id: String,
     pub name: String,
     pub path: String,
@@ -12,6 +17,48 @@ pub struct FileEntry {
     pub is_directory: bool,
     pub extension: Option<String>,
     pub attributes: i32,
+    /// Fuzzy match score, higher is better; only set by fuzzy searches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<i32>,
+    /// Name of the imported file list the entry comes from; `None` for live sources
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+impl FileEntry {
+    /// The id of the file at `path`, given its (non-following) metadata
+    pub fn file_id(path: &Path, metadata: &Metadata) -> String {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            // Hard links share an inode, but each path needs its own entry
+            if metadata.is_dir() || metadata.nlink() <= 1 {
+                return hash_id(format!("inode:{}:{}", metadata.dev(), metadata.ino()).as_bytes());
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = metadata;
+
+        Self::path_id(path)
+    }
+
+    /// The id of the file at `path` when its metadata is unknown. Only matches `file_id`
+    /// where ids are path based.
+    pub fn path_id(path: &Path) -> String {
+        let path = path.to_string_lossy();
+        let path = path.trim_end_matches(['/', '\\']);
+
+        // Windows paths are case-insensitive and accept either separator
+        #[cfg(windows)]
+        let path = path.replace('/', "\\").to_lowercase();
+
+        hash_id(format!("path:{}", path).as_bytes())
+    }
+}
+
+/// First 64 bits of the BLAKE3 hash, as hex
+fn hash_id(input: &[u8]) -> String {
+    blake3::hash(input).to_hex()[..16].to_string()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,9 +67,11 @@ pub struct SearchQuery {
     pub filters: SearchFilters,
     pub limit: Option<u32>,
     pub offset: Option<u32>,
+    #[serde(default)]
+    pub sort: Sort,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct SearchFilters {
     pub file_types: Vec<String>,
     pub size_min: Option<i64>,
@@ -35,24 +84,10 @@ pub struct SearchFilters {
     pub search_content: bool,
     pub directories_only: bool,
     pub files_only: bool,
-}
-
-impl Default for SearchFilters {
-    fn default() -> Self {
-        Self {
-            file_types: Vec::new(),
-            size_min: None,
-            size_max: None,
-            date_from: None,
-            date_to: None,
-            include_hidden: false,
-            case_sensitive: false,
-            use_regex: false,
-            search_content: false,
-            directories_only: false,
-            files_only: false,
-        }
-    }
+    /// Match plain text as a subsequence, so `cfgldr` finds `config_loader.rs`, and score
+    /// each result
+    #[serde(default)]
+    pub fuzzy: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -62,6 +97,191 @@ pub struct SearchResult {
     pub query_time_ms: u64,
 }
 
+/// Column that orders search results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    #[default]
+    Name,
+    Path,
+    Size,
+    Extension,
+    DateModified,
+    DateCreated,
+    /// Fuzzy score, or else how closely the name matches the query's first text term;
+    /// best matches first unless descending
+    Relevance,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sort {
+    pub field: SortField,
+    #[serde(default)]
+    pub descending: bool,
+}
+
+/// Summary of a result set held by the backend; its rows are fetched by `handle`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResultSetInfo {
+    pub handle: u64,
+    /// Matches of the query, including any beyond the rows held in the set
+    pub total_count: u64,
+    /// Rows that pass the refinement filter, in the current sort order
+    pub row_count: u64,
+    /// Whether the set holds fewer rows than the query matched
+    pub truncated: bool,
+    pub sort: Sort,
+    pub query_time_ms: u64,
+}
+
+/// A query from the search history, with the filters it was last run with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub query: String,
+    pub filters: SearchFilters,
+    pub use_count: u64,
+    pub first_used: DateTime<Utc>,
+    pub last_used: DateTime<Utc>,
+}
+
+/// A query saved by name with its filters and sort order. `saved:<name>` inside another
+/// query stands for it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedSearch {
+    /// Unique, ignoring case
+    pub name: String,
+    pub query: String,
+    #[serde(default)]
+    pub filters: SearchFilters,
+    #[serde(default)]
+    pub sort: Sort,
+}
+
+/// Where a completion comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompletionKind {
+    History,
+    Keyword,
+    Name,
+    Extension,
+    Folder,
+    Saved,
+}
+
+/// A suggested continuation of a partly typed query
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Completion {
+    /// The whole query with the completion applied
+    pub query: String,
+    /// The completed word, or the past query for history completions
+    pub label: String,
+    pub kind: CompletionKind,
+    /// Uses of a past query, or entries with the name or extension, or inside the folder
+    pub count: u64,
+}
+
+/// Files with identical contents
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateGroup {
+    /// Size of each file in bytes
+    pub size: u64,
+    /// BLAKE3 hash of the contents, hex encoded
+    pub hash: String,
+    /// Sorted by path
+    pub files: Vec<FileEntry>,
+    /// Bytes that removing all but one copy would free
+    pub wasted_bytes: u64,
+}
+
+/// Duplicate groups found among a set of files, the most wasteful first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateReport {
+    pub groups: Vec<DuplicateGroup>,
+    pub wasted_bytes: u64,
+    /// Files considered, including those without a file of the same size
+    pub files_scanned: u64,
+    /// Files that could not be read, and so are in no group
+    pub files_skipped: u64,
+    pub query_time_ms: u64,
+}
+
+/// Stage of a duplicate search
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicatePhase {
+    /// Hashing the start of files that share their size
+    PartialHash,
+    /// Hashing the whole of files whose starts match
+    FullHash,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DuplicateProgress {
+    pub phase: DuplicatePhase,
+    /// Files of the phase hashed so far
+    pub files_done: u64,
+    pub files_total: u64,
+    /// Bytes of the phase read so far
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+}
+
+/// An imported Everything file list
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileListInfo {
+    pub name: String,
+    /// The `.efu` file it was loaded from
+    pub path: String,
+    pub entry_count: u64,
+    pub loaded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileOperationKind {
+    Rename,
+    Move,
+    Copy,
+    /// Move to the system trash
+    Trash,
+}
+
+/// One file or folder handled by an operation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileOperationStep {
+    pub source: String,
+    /// Where the file went; `None` when it was trashed
+    pub destination: Option<String>,
+}
+
+/// A file or folder an operation could not handle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileOperationFailure {
+    pub path: String,
+    pub error: String,
+}
+
+/// An operation recorded in the journal, so it can be undone
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileOperationRecord {
+    pub id: i64,
+    pub kind: FileOperationKind,
+    /// The files handled, in the order they were handled
+    pub steps: Vec<FileOperationStep>,
+    pub performed_at: DateTime<Utc>,
+}
+
+/// What an operation, or the undoing of one, did to each file it was given
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileOperationReport {
+    pub kind: FileOperationKind,
+    /// Journal entry of the operation; `None` when nothing was done
+    pub operation_id: Option<i64>,
+    pub completed: Vec<FileOperationStep>,
+    pub failed: Vec<FileOperationFailure>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IndexStats {
     pub total_files: u64,
@@ -81,4 +301,7 @@ pub enum SearchError {
     
     #[error("Everything SDK error: {0}")]
     EverythingSdk(String),
+
+    #[error("Search was cancelled")]
+    Cancelled,
 }
@@ -12,7 +12,7 @@ import './App.css'
 
 function MainApp() {
   const { theme, toggleTheme } = useTheme()
-  const { query, setQuery, results, filters, setFilters, viewMode, setViewMode, isLoading, backendReady } = useSearch()
+  const { query, setQuery, results, filters, setFilters, viewMode, setViewMode, isLoading, backendReady, rememberSearch } = useSearch()
   const [showSettings, setShowSettings] = useState(false)
   const [showFilters, setShowFilters] = useState(false)
 
@@ -144,6 +144,7 @@ function MainApp() {
           query={query}
           viewMode={viewMode}
           isLoading={isLoading}
+          onOpen={rememberSearch}
         />
 
         {/* Settings Dialog */}
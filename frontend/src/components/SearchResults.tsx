@@ -8,9 +8,11 @@ interface SearchResultsProps {
   query: string
   viewMode: ViewMode
   isLoading: boolean
+  /** Called after a result is opened */
+  onOpen?: () => void
 }
 
-export function SearchResults({ results, query, viewMode, isLoading }: SearchResultsProps) {
+export function SearchResults({ results, query, viewMode, isLoading, onOpen }: SearchResultsProps) {
   if (isLoading) {
     return (
       <div className="flex items-center justify-center py-12">
@@ -51,6 +53,7 @@ export function SearchResults({ results, query, viewMode, isLoading }: SearchRes
       try {
         // Open the file or folder directly
         await TauriAPI.openFile(result.path)
+        onOpen?.()
       } catch (error) {
         console.error('Failed to open file:', error)
         // Show error to user
@@ -67,6 +70,7 @@ export function SearchResults({ results, query, viewMode, isLoading }: SearchRes
       try {
         // Open the file location (folder containing the file, or the folder itself)
         await TauriAPI.openFileLocation(result.path)
+        onOpen?.()
       } catch (error) {
         console.error('Failed to open file location:', error)
         // Show error to user
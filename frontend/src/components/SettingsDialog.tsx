@@ -1,9 +1,10 @@
-import { useState } from 'react'
+import { useEffect, useState } from 'react'
 import { Dialog, DialogContent, DialogHeader, DialogTitle } from './ui/dialog'
 import { Button } from './ui/button'
 import { AppSettings, Theme } from '../types'
 import { Folder, Plus, Trash2, ToggleLeft, ToggleRight, Check } from 'lucide-react'
 import { useTheme } from '../hooks/useTheme'
+import { TauriAPI, isTauri } from '../lib/tauri'
 
 interface SettingsDialogProps {
   open: boolean
@@ -25,6 +26,19 @@ export function SettingsDialog({ open, onOpenChange }: SettingsDialogProps) {
   const { theme, applyTheme, themes } = useTheme();
   const [settings, setSettings] = useState<AppSettings>({ ...defaultSettings, theme: theme as Theme });
   const [newPath, setNewPath] = useState('')
+  const [privateMode, setPrivateMode] = useState(false)
+
+  // History settings live in the backend and apply immediately
+  useEffect(() => {
+    if (open && isTauri) {
+      TauriAPI.getPrivateMode().then(setPrivateMode)
+    }
+  }, [open])
+
+  const togglePrivateMode = async () => {
+    await TauriAPI.setPrivateMode(!privateMode)
+    setPrivateMode(!privateMode)
+  }
 
   const updateSetting = <K extends keyof AppSettings>(
     key: K,
@@ -213,6 +227,40 @@ export function SettingsDialog({ open, onOpenChange }: SettingsDialogProps) {
             </div>
           </div>
 
+          {/* Search History */}
+          <div>
+            <h3 className="text-lg font-medium mb-3">Search History</h3>
+            <div className="space-y-3">
+              <div className="flex items-center justify-between">
+                <div>
+                  <p className="text-sm font-medium">Private Mode</p>
+                  <p className="text-xs text-muted-foreground">
+                    Stop recording searches until private mode is turned off
+                  </p>
+                </div>
+                <Button variant="ghost" size="sm" onClick={togglePrivateMode} disabled={!isTauri}>
+                  {privateMode ? (
+                    <ToggleRight className="w-6 h-6 text-primary" />
+                  ) : (
+                    <ToggleLeft className="w-6 h-6 text-muted-foreground" />
+                  )}
+                </Button>
+              </div>
+
+              <div className="flex items-center justify-between">
+                <div>
+                  <p className="text-sm font-medium">Clear History</p>
+                  <p className="text-xs text-muted-foreground">
+                    Forget every past search
+                  </p>
+                </div>
+                <Button variant="outline" size="sm" onClick={() => TauriAPI.clearSearchHistory()} disabled={!isTauri}>
+                  <Trash2 className="w-4 h-4" />
+                </Button>
+              </div>
+            </div>
+          </div>
+
           {/* System Integration */}
           <div>
             <h3 className="text-lg font-medium mb-3">System Integration</h3>
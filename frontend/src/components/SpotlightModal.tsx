@@ -9,7 +9,7 @@ import { Button } from './ui/button';
 type FilterType = 'all' | 'files' | 'folders';
 
 const SpotlightModal: React.FC = () => {
-  const { query, setQuery, results, isLoading, setFilters } = useSearch();
+  const { query, setQuery, results, isLoading, setFilters, rememberSearch } = useSearch();
   const [selectedIndex, setSelectedIndex] = useState(0);
   const [activeFilter, setActiveFilter] = useState<FilterType>('all');
   const [calculatorResult, setCalculatorResult] = useState<string | null>(null);
@@ -61,6 +61,7 @@ const SpotlightModal: React.FC = () => {
           } else {
             TauriAPI.openFile(selectedItem.path);
           }
+          rememberSearch();
           appWindow.hide();
         } else {
           // If no results, check if the query is a URL
@@ -77,7 +78,7 @@ const SpotlightModal: React.FC = () => {
 
     window.addEventListener('keydown', handleKeyDown);
     return () => window.removeEventListener('keydown', handleKeyDown);
-  }, [results, selectedIndex, calculatorResult]);
+  }, [results, selectedIndex, calculatorResult, rememberSearch]);
 
   useEffect(() => {
     inputRef.current?.focus();
@@ -107,6 +108,8 @@ const SpotlightModal: React.FC = () => {
       ...prev,
       filesOnly: activeFilter === 'files',
       directoriesOnly: activeFilter === 'folders',
+      // Keyboard-driven, so the best fuzzy match should be the top hit
+      fuzzy: true,
     }));
   }, [activeFilter, setFilters]);
 
@@ -176,7 +179,14 @@ const SpotlightModal: React.FC = () => {
                           ? 'bg-primary text-primary-foreground'
                           : 'hover:bg-accent/50'
                       }`}
-                      onClick={() => isUrl ? TauriAPI.openLink(item.path) : TauriAPI.openFile(item.path)}
+                      onClick={() => {
+                        if (isUrl) {
+                          TauriAPI.openLink(item.path);
+                        } else {
+                          TauriAPI.openFile(item.path);
+                          rememberSearch();
+                        }
+                      }}
                       onMouseEnter={() => setSelectedIndex(index)}
                       onDoubleClick={() => isUrl ? TauriAPI.openLink(item.path) : TauriAPI.openFileLocation(item.path)}
                       title={`Click to open • Double-click to show in folder\n${item.path}`}
@@ -1,7 +1,11 @@
-import { useState, useEffect, useCallback } from 'react'
+import { useState, useEffect, useCallback, useRef } from 'react'
 import { debounce } from '../lib/utils'
 import { TauriAPI, isTauri } from '../lib/tauri'
-import type { FileResult, SearchFilters, ViewMode } from '../types'
+import { appWindow } from '@tauri-apps/api/window'
+import type { FileResult, ResultSetInfo, SearchBatch, SearchFilters, SearchFinished, Sort, ViewMode } from '../types'
+
+// Rows fetched from the backend result set per request
+const PAGE_SIZE = 200
 
 // Mock data for browser development (when not in Tauri)
 const mockResults: FileResult[] = [
@@ -54,6 +58,14 @@ const mockResults: FileResult[] = [
 export function useSearch() {
   const [query, setQuery] = useState('')
   const [results, setResults] = useState<FileResult[]>([])
+  const [resultSet, setResultSet] = useState<ResultSetInfo | null>(null)
+  const resultSetRef = useRef<ResultSetInfo | null>(null)
+  // Kept across queries, so a column sort sticks while typing
+  const sortRef = useRef<Sort | undefined>(undefined)
+  // Id of the latest streamed search; events of earlier searches are ignored
+  const queryIdRef = useRef(0)
+  // Whether the latest search has streamed any rows yet
+  const streamedRef = useRef(false)
   const [isLoading, setIsLoading] = useState(false)
   const [backendReady, setBackendReady] = useState(false)
   const [viewMode, setViewMode] = useState<ViewMode>('list')
@@ -80,6 +92,7 @@ export function useSearch() {
     search_content: frontendFilters.searchContent,
     directories_only: frontendFilters.directoriesOnly,
     files_only: frontendFilters.filesOnly,
+    fuzzy: frontendFilters.fuzzy ?? false,
   })
 
   // Convert backend results to frontend format
@@ -92,6 +105,8 @@ export function useSearch() {
       modified: new Date(result.modified),
       type: result.is_directory ? 'folder' : 'file',
       extension: result.extension,
+      score: result.score,
+      source: result.source,
     }))
   }
 
@@ -137,6 +152,16 @@ export function useSearch() {
     }
   }
 
+  // Release the previous result set and track the new one
+  const replaceResultSet = (info: ResultSetInfo | null) => {
+    const previous = resultSetRef.current
+    if (previous && previous.handle !== info?.handle) {
+      TauriAPI.closeResultSet(previous.handle)
+    }
+    resultSetRef.current = info
+    setResultSet(info)
+  }
+
   // Tauri search function
   const tauriSearch = async (searchQuery: string, searchFilters: SearchFilters) => {
     const urlRegex = /^(https?:\/\/)?([\da-z.-]+)\.([a-z.]{2,6})([/\w .-]*)*\/?$/;
@@ -153,6 +178,9 @@ export function useSearch() {
       return;
     }
     if (!searchQuery.trim()) {
+      queryIdRef.current += 1
+      TauriAPI.cancelSearch()
+      replaceResultSet(null)
       setResults([])
       setIsLoading(false)
       return
@@ -168,27 +196,101 @@ export function useSearch() {
         if (!isReady) {
           console.log('Backend still initializing, skipping search')
           setResults([])
+          setIsLoading(false)
           return
         }
         setBackendReady(true)
       }
 
       const backendFilters = convertFiltersToBackend(searchFilters)
-      const result = await TauriAPI.searchFiles(searchQuery, backendFilters as any)
-      const convertedResults = convertResultsFromBackend(result.entries)
-      setResults(convertedResults)
+      // Fuzzy results are only useful ranked by score unless a column sort was picked
+      const sort = sortRef.current ?? (searchFilters.fuzzy ? { field: 'relevance' as const, descending: false } : undefined)
+      // Rows stream in through the search events; loading ends with 'search-finished'
+      queryIdRef.current += 1
+      streamedRef.current = false
+      await TauriAPI.startSearch(queryIdRef.current, searchQuery, backendFilters as any, sort)
     } catch (error) {
       console.error('Search error:', error)
       if (error instanceof Error && error.message.includes('still initializing')) {
         console.log('Backend still initializing, will retry when ready')
         setBackendReady(false)
       }
+      replaceResultSet(null)
       setResults([])
-    } finally {
       setIsLoading(false)
     }
   }
 
+  // Show streamed rows as they arrive, then switch to the sorted result set
+  useEffect(() => {
+    if (!isTauri) return
+
+    const unlisteners = [
+      appWindow.listen<SearchBatch>('search-batch', ({ payload }) => {
+        if (payload.query_id !== queryIdRef.current) return
+        const rows = convertResultsFromBackend(payload.entries)
+        // The first batch replaces the previous query's rows
+        setResults(previous => (streamedRef.current ? [...previous, ...rows] : rows).slice(0, PAGE_SIZE))
+        streamedRef.current = true
+      }),
+      appWindow.listen<SearchFinished>('search-finished', async ({ payload }) => {
+        const info = payload.result_set
+        if (payload.query_id !== queryIdRef.current) {
+          if (info) TauriAPI.closeResultSet(info.handle)
+          return
+        }
+        if (info) {
+          const rows = await TauriAPI.fetchResultRows(info.handle, 0, PAGE_SIZE)
+          if (payload.query_id !== queryIdRef.current) {
+            TauriAPI.closeResultSet(info.handle)
+            return
+          }
+          replaceResultSet(info)
+          setResults(convertResultsFromBackend(rows))
+        } else if (!payload.cancelled) {
+          console.error('Search error:', payload.error)
+          replaceResultSet(null)
+          setResults([])
+        }
+        setIsLoading(false)
+      }),
+    ]
+
+    return () => {
+      unlisteners.forEach(unlisten => unlisten.then(stop => stop()))
+    }
+  }, [])
+
+  // Append the next page of the current result set
+  const loadMore = async () => {
+    const info = resultSetRef.current
+    if (!info || results.length >= info.row_count) return
+    const rows = await TauriAPI.fetchResultRows(info.handle, results.length, PAGE_SIZE)
+    setResults(previous => [...previous, ...convertResultsFromBackend(rows)])
+  }
+
+  // Re-sort or narrow the current result set without running the query again
+  const reloadResultSet = async (update: (handle: number) => Promise<ResultSetInfo>) => {
+    const info = resultSetRef.current
+    if (!info) return
+    const updated = await update(info.handle)
+    const rows = await TauriAPI.fetchResultRows(updated.handle, 0, PAGE_SIZE)
+    replaceResultSet(updated)
+    setResults(convertResultsFromBackend(rows))
+  }
+
+  const sortResults = (sort: Sort) => {
+    sortRef.current = sort
+    return reloadResultSet(handle => TauriAPI.sortResultSet(handle, sort))
+  }
+  // Add the current query to the search history, e.g. once a result is opened
+  const rememberSearch = () => {
+    if (!isTauri || !query.trim()) return
+    TauriAPI.recordSearch(query, convertFiltersToBackend(filters) as any)
+  }
+
+  const refineResults = (text: string) => reloadResultSet(handle => TauriAPI.filterResultSet(handle, text))
+
   // Debounced search function
   const debouncedSearch = useCallback(
     debounce(async (searchQuery: string, searchFilters: SearchFilters) => {
@@ -250,6 +352,11 @@ export function useSearch() {
     query,
     setQuery,
     results,
+    totalCount: resultSet?.total_count ?? results.length,
+    loadMore,
+    sortResults,
+    refineResults,
+    rememberSearch,
     filters,
     setFilters,
     viewMode,
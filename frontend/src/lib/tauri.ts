@@ -1,7 +1,7 @@
 import { invoke } from '@tauri-apps/api/tauri'
 import { listen } from '@tauri-apps/api/event'
 import { appWindow } from '@tauri-apps/api/window'
-import type { SearchFilters, SearchResult, IndexStats } from '../types'
+import type { SearchFilters, SearchResult, IndexStats, BackendStatus, ResultSetInfo, Sort, HistoryEntry, Completion, DuplicateReport, SavedSearch, FileOperationReport, FileOperationRecord, ExportOptions, FileListInfo } from '../types'
 
 // Backend filter format (matches Rust structs)
 interface BackendSearchFilters {
@@ -16,14 +16,24 @@ interface BackendSearchFilters {
   search_content: boolean
   directories_only: boolean
   files_only: boolean
+  fuzzy: boolean
 }
 
 export class TauriAPI {
-  static async searchFiles(query: string, filters: BackendSearchFilters): Promise<SearchResult> {
+  static async searchFiles(
+    query: string,
+    filters: BackendSearchFilters,
+    limit?: number,
+    offset?: number,
+    sort?: Sort,
+  ): Promise<SearchResult> {
     try {
       const result = await invoke<SearchResult>('search_files', {
         query,
         filters,
+        limit,
+        offset,
+        sort,
       })
       return result
     } catch (error) {
@@ -32,6 +42,249 @@ export class TauriAPI {
     }
   }
 
+  static async openResultSet(query: string, filters: BackendSearchFilters, sort?: Sort): Promise<ResultSetInfo> {
+    try {
+      return await invoke<ResultSetInfo>('open_result_set', { query, filters, sort })
+    } catch (error) {
+      console.error('Search failed:', error)
+      throw new Error(`Search failed: ${error}`)
+    }
+  }
+
+  // Results arrive as 'search-batch' and 'search-finished' events on the calling window
+  static async startSearch(queryId: number, query: string, filters: BackendSearchFilters, sort?: Sort): Promise<void> {
+    try {
+      await invoke('start_search', { queryId, query, filters, sort })
+    } catch (error) {
+      console.error('Search failed:', error)
+      throw new Error(`Search failed: ${error}`)
+    }
+  }
+
+  static async cancelSearch(): Promise<void> {
+    try {
+      await invoke('cancel_search')
+    } catch (error) {
+      console.error('Failed to cancel search:', error)
+    }
+  }
+
+  // Rows come back in backend format, like SearchResult.entries
+  static async fetchResultRows(handle: number, offset: number, limit: number): Promise<any[]> {
+    try {
+      return await invoke<any[]>('fetch_result_rows', { handle, offset, limit })
+    } catch (error) {
+      console.error('Failed to fetch result rows:', error)
+      throw new Error(`Failed to fetch result rows: ${error}`)
+    }
+  }
+
+  static async sortResultSet(handle: number, sort: Sort): Promise<ResultSetInfo> {
+    try {
+      return await invoke<ResultSetInfo>('sort_result_set', { handle, sort })
+    } catch (error) {
+      console.error('Failed to sort results:', error)
+      throw new Error(`Failed to sort results: ${error}`)
+    }
+  }
+
+  static async filterResultSet(handle: number, text: string): Promise<ResultSetInfo> {
+    try {
+      return await invoke<ResultSetInfo>('filter_result_set', { handle, text })
+    } catch (error) {
+      console.error('Failed to filter results:', error)
+      throw new Error(`Failed to filter results: ${error}`)
+    }
+  }
+
+  static async closeResultSet(handle: number): Promise<void> {
+    try {
+      await invoke('close_result_set', { handle })
+    } catch (error) {
+      console.error('Failed to close result set:', error)
+    }
+  }
+
+  static async exportSearch(query: string, filters: BackendSearchFilters, path: string, options: ExportOptions, sort?: Sort): Promise<number> {
+    try {
+      return await invoke<number>('export_search', { query, filters, sort, path, options })
+    } catch (error) {
+      console.error('Failed to export results:', error)
+      throw new Error(`Failed to export results: ${error}`)
+    }
+  }
+
+  static async exportResultSet(handle: number, path: string, options: ExportOptions): Promise<number> {
+    try {
+      return await invoke<number>('export_result_set', { handle, path, options })
+    } catch (error) {
+      console.error('Failed to export result set:', error)
+      throw new Error(`Failed to export result set: ${error}`)
+    }
+  }
+
+  static async listFileLists(): Promise<FileListInfo[]> {
+    try {
+      return await invoke<FileListInfo[]>('list_file_lists')
+    } catch (error) {
+      console.error('Failed to list file lists:', error)
+      return []
+    }
+  }
+
+  static async importFileList(name: string, path: string): Promise<FileListInfo> {
+    try {
+      return await invoke<FileListInfo>('import_file_list', { name, path })
+    } catch (error) {
+      console.error('Failed to import file list:', error)
+      throw new Error(`Failed to import file list: ${error}`)
+    }
+  }
+
+  static async removeFileList(name: string): Promise<boolean> {
+    try {
+      return await invoke<boolean>('remove_file_list', { name })
+    } catch (error) {
+      console.error('Failed to remove file list:', error)
+      throw new Error(`Failed to remove file list: ${error}`)
+    }
+  }
+
+  // Without a query every indexed file is checked; progress arrives as 'duplicate-progress' events
+  static async findDuplicates(query?: string, filters?: BackendSearchFilters, minSize?: number): Promise<DuplicateReport> {
+    try {
+      return await invoke<DuplicateReport>('find_duplicates', { query, filters, minSize })
+    } catch (error) {
+      console.error('Duplicate search failed:', error)
+      throw new Error(`Duplicate search failed: ${error}`)
+    }
+  }
+
+  static async cancelDuplicates(): Promise<void> {
+    try {
+      await invoke('cancel_duplicates')
+    } catch (error) {
+      console.error('Failed to cancel duplicate search:', error)
+    }
+  }
+
+  static async completeQuery(partial: string, limit?: number): Promise<Completion[]> {
+    try {
+      return await invoke<Completion[]>('complete_query', { partial, limit })
+    } catch (error) {
+      console.error('Failed to complete query:', error)
+      return []
+    }
+  }
+
+  // Only queries the user acted on are recorded, not every keystroke
+  static async recordSearch(query: string, filters: BackendSearchFilters): Promise<void> {
+    try {
+      await invoke('record_search', { query, filters })
+    } catch (error) {
+      console.error('Failed to record search:', error)
+    }
+  }
+
+  static async getSearchHistory(limit?: number): Promise<HistoryEntry[]> {
+    try {
+      return await invoke<HistoryEntry[]>('get_search_history', { limit })
+    } catch (error) {
+      console.error('Failed to get search history:', error)
+      return []
+    }
+  }
+
+  static async deleteSearchHistory(query: string): Promise<boolean> {
+    try {
+      return await invoke<boolean>('delete_search_history', { query })
+    } catch (error) {
+      console.error('Failed to delete search history entry:', error)
+      throw new Error(`Failed to delete search history entry: ${error}`)
+    }
+  }
+
+  static async clearSearchHistory(): Promise<void> {
+    try {
+      await invoke('clear_search_history')
+    } catch (error) {
+      console.error('Failed to clear search history:', error)
+      throw new Error(`Failed to clear search history: ${error}`)
+    }
+  }
+
+  static async listSavedSearches(): Promise<SavedSearch[]> {
+    try {
+      return await invoke<SavedSearch[]>('list_saved_searches')
+    } catch (error) {
+      console.error('Failed to list saved searches:', error)
+      return []
+    }
+  }
+
+  static async createSavedSearch(saved: SavedSearch): Promise<void> {
+    try {
+      await invoke('create_saved_search', { saved })
+    } catch (error) {
+      console.error('Failed to save search:', error)
+      throw new Error(`Failed to save search: ${error}`)
+    }
+  }
+
+  static async updateSavedSearch(name: string, saved: SavedSearch): Promise<void> {
+    try {
+      await invoke('update_saved_search', { name, saved })
+    } catch (error) {
+      console.error('Failed to update saved search:', error)
+      throw new Error(`Failed to update saved search: ${error}`)
+    }
+  }
+
+  static async deleteSavedSearch(name: string): Promise<boolean> {
+    try {
+      return await invoke<boolean>('delete_saved_search', { name })
+    } catch (error) {
+      console.error('Failed to delete saved search:', error)
+      throw new Error(`Failed to delete saved search: ${error}`)
+    }
+  }
+
+  static async importSavedSearches(path: string): Promise<number> {
+    try {
+      return await invoke<number>('import_saved_searches', { path })
+    } catch (error) {
+      console.error('Failed to import saved searches:', error)
+      throw new Error(`Failed to import saved searches: ${error}`)
+    }
+  }
+
+  static async exportSavedSearches(path: string): Promise<number> {
+    try {
+      return await invoke<number>('export_saved_searches', { path })
+    } catch (error) {
+      console.error('Failed to export saved searches:', error)
+      throw new Error(`Failed to export saved searches: ${error}`)
+    }
+  }
+
+  static async setPrivateMode(enabled: boolean): Promise<void> {
+    try {
+      await invoke('set_private_mode', { enabled })
+    } catch (error) {
+      console.error('Failed to set private mode:', error)
+      throw new Error(`Failed to set private mode: ${error}`)
+    }
+  }
+
+  static async getPrivateMode(): Promise<boolean> {
+    try {
+      return await invoke<boolean>('get_private_mode')
+    } catch (error) {
+      console.error('Failed to get private mode:', error)
+      return false
+    }
+  }
+
   static async getIndexStats(): Promise<IndexStats> {
     try {
       return await invoke<IndexStats>('get_index_stats')
@@ -41,6 +294,15 @@ export class TauriAPI {
     }
   }
 
+  static async getBackendStatus(): Promise<BackendStatus[]> {
+    try {
+      return await invoke<BackendStatus[]>('get_backend_status')
+    } catch (error) {
+      console.error('Failed to get backend status:', error)
+      throw new Error(`Failed to get backend status: ${error}`)
+    }
+  }
+
   static async checkBackendStatus(): Promise<boolean> {
     try {
       return await invoke<boolean>('check_backend_status')
@@ -68,6 +330,61 @@ export class TauriAPI {
     }
   }
 
+  static async renameFile(path: string, newName: string): Promise<FileOperationReport> {
+    try {
+      return await invoke<FileOperationReport>('rename_file', { path, newName })
+    } catch (error) {
+      console.error('Failed to rename file:', error)
+      throw new Error(`Failed to rename file: ${error}`)
+    }
+  }
+
+  static async moveFiles(paths: string[], destination: string): Promise<FileOperationReport> {
+    try {
+      return await invoke<FileOperationReport>('move_files', { paths, destination })
+    } catch (error) {
+      console.error('Failed to move files:', error)
+      throw new Error(`Failed to move files: ${error}`)
+    }
+  }
+
+  static async copyFiles(paths: string[], destination: string): Promise<FileOperationReport> {
+    try {
+      return await invoke<FileOperationReport>('copy_files', { paths, destination })
+    } catch (error) {
+      console.error('Failed to copy files:', error)
+      throw new Error(`Failed to copy files: ${error}`)
+    }
+  }
+
+  static async trashFiles(paths: string[]): Promise<FileOperationReport> {
+    try {
+      return await invoke<FileOperationReport>('trash_files', { paths })
+    } catch (error) {
+      console.error('Failed to move files to the trash:', error)
+      throw new Error(`Failed to move files to the trash: ${error}`)
+    }
+  }
+
+  /** Undo the latest file operation; null when there is nothing to undo */
+  static async undoFileOperation(): Promise<FileOperationReport | null> {
+    try {
+      return await invoke<FileOperationReport | null>('undo_file_operation')
+    } catch (error) {
+      console.error('Failed to undo file operation:', error)
+      throw new Error(`Failed to undo file operation: ${error}`)
+    }
+  }
+
+  static async getFileOperations(limit?: number): Promise<FileOperationRecord[]> {
+    try {
+      return await invoke<FileOperationRecord[]>('get_file_operations', { limit })
+    } catch (error) {
+      console.error('Failed to get file operations:', error)
+      return []
+    }
+  }
+
   static async openLink(url: string): Promise<void> {
     try {
       const { open } = await import('@tauri-apps/api/shell');
@@ -139,6 +456,7 @@ export const convertFiltersToBackend = (frontendFilters: SearchFilters): Backend
   search_content: frontendFilters.searchContent,
   directories_only: false,
   files_only: false,
+  fuzzy: frontendFilters.fuzzy ?? false,
 })
 
 // Check if running in Tauri environment
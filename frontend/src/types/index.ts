@@ -1,4 +1,5 @@
 export interface FileResult {
+  /** Stable across searches, backends and restarts, so it can key selections */
   id: string
   name: string
   path: string
@@ -7,6 +8,10 @@ export interface FileResult {
   type: 'file' | 'folder' | 'url'
   extension?: string
   icon?: string
+  /** Fuzzy match score, higher is better; only set in fuzzy mode */
+  score?: number
+  /** Name of the imported file list the result comes from */
+  source?: string
 }
 
 export interface SearchFilters {
@@ -21,6 +26,8 @@ export interface SearchFilters {
   searchContent: boolean
   directoriesOnly?: boolean
   filesOnly?: boolean
+  /** Match typed characters as a subsequence, e.g. `cfgldr` finds `config_loader.rs` */
+  fuzzy?: boolean
 }
 
 export interface SearchResult {
@@ -29,6 +36,144 @@ export interface SearchResult {
   query_time_ms: number
 }
 
+export type SortField =
+  | 'name'
+  | 'path'
+  | 'size'
+  | 'extension'
+  | 'date_modified'
+  | 'date_created'
+  | 'relevance'
+
+export interface Sort {
+  field: SortField
+  descending: boolean
+}
+
+/** A search result held by the backend; rows are fetched by handle */
+export interface ResultSetInfo {
+  handle: number
+  /** Matches of the query, including any beyond the rows held in the set */
+  total_count: number
+  /** Rows that pass the refinement filter */
+  row_count: number
+  truncated: boolean
+  sort: Sort
+  query_time_ms: number
+}
+
+/** Matches of a streamed search, in the order they were found; rows are in backend format */
+export interface SearchBatch {
+  query_id: number
+  entries: any[]
+}
+
+/** Last event of a streamed search; on success its sorted rows are held in `result_set` */
+export interface SearchFinished {
+  query_id: number
+  result_set: ResultSetInfo | null
+  total_count: number
+  query_time_ms: number
+  cancelled: boolean
+  error: string | null
+}
+
+/** A past query, with the filters it was last run with; filters are in backend format */
+export interface HistoryEntry {
+  query: string
+  filters: Record<string, unknown>
+  use_count: number
+  first_used: string
+  last_used: string
+}
+
+/** A search saved by name, usable in queries as saved:<name>; filters are in backend format */
+export interface SavedSearch {
+  name: string
+  query: string
+  filters: Record<string, unknown>
+  sort: Sort
+}
+
+/** A suggested continuation of a partly typed query */
+export interface Completion {
+  /** The whole query with the completion applied */
+  query: string
+  /** The completed word, or the past query for history completions */
+  label: string
+  kind: 'history' | 'keyword' | 'name' | 'extension' | 'folder' | 'saved'
+  count: number
+}
+
+/** Files with identical contents; files are in backend format, like SearchResult.entries */
+export interface DuplicateGroup {
+  size: number
+  hash: string
+  files: any[]
+  /** Bytes that removing all but one copy would free */
+  wasted_bytes: number
+}
+
+export interface DuplicateReport {
+  groups: DuplicateGroup[]
+  wasted_bytes: number
+  files_scanned: number
+  files_skipped: number
+  query_time_ms: number
+}
+
+/** Payload of the 'duplicate-progress' event */
+export interface DuplicateProgress {
+  phase: 'partial_hash' | 'full_hash'
+  files_done: number
+  files_total: number
+  bytes_done: number
+  bytes_total: number
+}
+
+/** An imported Everything file list, searched next to the live sources */
+export interface FileListInfo {
+  name: string
+  path: string
+  entry_count: number
+  loaded_at: string
+}
+
+export type ExportFormat = 'csv' | 'tsv' | 'json_lines' | 'efu'
+
+export type ExportColumn =
+  | 'name' | 'path' | 'folder' | 'size' | 'modified' | 'created' | 'extension' | 'type' | 'attributes'
+
+/** Columns are ignored for EFU, which always has Everything's own */
+export interface ExportOptions {
+  format: ExportFormat
+  columns: ExportColumn[]
+}
+
+export type FileOperationKind = 'rename' | 'move' | 'copy' | 'trash'
+
+/** One file or folder handled by an operation; destination is null when it was trashed */
+export interface FileOperationStep {
+  source: string
+  destination: string | null
+}
+
+export interface FileOperationReport {
+  kind: FileOperationKind
+  /** Journal entry of the operation, null when nothing was done */
+  operation_id: number | null
+  completed: FileOperationStep[]
+  failed: { path: string; error: string }[]
+}
+
+/** A file operation that can still be undone */
+export interface FileOperationRecord {
+  id: number
+  kind: FileOperationKind
+  steps: FileOperationStep[]
+  performed_at: string
+}
+
 export interface IndexStats {
   total_files: number
   total_directories: number
@@ -37,6 +182,17 @@ export interface IndexStats {
   indexed_paths: string[]
 }
 
+export interface BackendStatus {
+  name: string
+  health: { status: 'healthy' } | { status: 'degraded' | 'unavailable'; message: string }
+  capabilities: {
+    regex: boolean
+    content_search: boolean
+    live_updates: boolean
+    exact_total_count: boolean
+  }
+}
+
 export interface SearchOptions {
   query: string
   filters: SearchFilters
@@ -1,15 +1,53 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use tauri::{
     Manager, Window, WindowEvent, GlobalShortcutManager, State
 };
-use everything_clone_backend::{EverythingClone, SearchQuery, SearchResult, IndexStats};
+use everything_clone_backend::{
+    BackendStatus, Completion, DuplicateReport, EverythingClone, FileEntry, FileListInfo, FileOperationRecord,
+    FileOperationReport, HistoryEntry, IndexStats, ResultSetInfo, SavedSearch, SearchQuery, SearchResult, Sort,
+};
+use everything_clone_backend::export::ExportOptions;
+use everything_clone_backend::stream::{CancelHandle, ResultSink};
 
 // Application state
 struct AppState {
     app: Arc<Mutex<Option<Arc<EverythingClone>>>>,
+    /// The streamed search running for each window, by label, with its query id
+    searches: Arc<Mutex<HashMap<String, (u64, CancelHandle)>>>,
+    /// The running duplicate search, if any
+    duplicates: Arc<Mutex<Option<CancelHandle>>>,
+}
+
+/// Matches of a streamed search, in the order the backends found them
+#[derive(Clone, serde::Serialize)]
+struct SearchBatch {
+    query_id: u64,
+    entries: Vec<FileEntry>,
+}
+
+/// Last event of a streamed search. On success the sorted rows are held in `result_set`.
+#[derive(Clone, serde::Serialize)]
+struct SearchFinished {
+    query_id: u64,
+    result_set: Option<ResultSetInfo>,
+    total_count: u64,
+    query_time_ms: u64,
+    cancelled: bool,
+    error: Option<String>,
+}
+
+/// The initialized backend, or an error while it is still starting
+fn initialized_app(state: &AppState) -> Result<Arc<EverythingClone>, String> {
+    let app_guard = state.app.lock().map_err(|e| e.to_string())?;
+    match *app_guard {
+        Some(ref app) => Ok(Arc::clone(app)),
+        None => Err("Backend is still initializing. Please wait a moment and try again.".to_string()),
+    }
 }
 
 // Tauri commands that can be called from the frontend
@@ -17,6 +55,9 @@ struct AppState {
 async fn search_files(
     query: String,
     filters: serde_json::Value,
+    limit: Option<u32>,
+    offset: Option<u32>,
+    sort: Option<Sort>,
     state: State<'_, AppState>,
 ) -> Result<SearchResult, String> {
     println!("Search called with query: '{}', filters: {}", query, filters);
@@ -34,8 +75,9 @@ async fn search_files(
     let search_query = SearchQuery {
         query,
         filters: serde_json::from_value(filters).map_err(|e| e.to_string())?,
-        limit: Some(100),
-        offset: None,
+        limit: Some(limit.unwrap_or(100)),
+        offset,
+        sort: sort.unwrap_or_default(),
     };
     
     println!("Calling backend search...");
@@ -44,6 +86,359 @@ async fn search_files(
     Ok(result)
 }
 
+/// Run a query and hold its results in the backend; rows are read with `fetch_result_rows`
+#[tauri::command]
+async fn open_result_set(
+    query: String,
+    filters: serde_json::Value,
+    sort: Option<Sort>,
+    state: State<'_, AppState>,
+) -> Result<ResultSetInfo, String> {
+    let app = initialized_app(&state)?;
+    let search_query = SearchQuery {
+        query,
+        filters: serde_json::from_value(filters).map_err(|e| e.to_string())?,
+        limit: None,
+        offset: None,
+        sort: sort.unwrap_or_default(),
+    };
+    app.result_sets.open(&search_query).await.map_err(|e| e.to_string())
+}
+
+/// Run a query in the background. Matches are streamed to the calling window as
+/// `search-batch` events, followed by one `search-finished` event; both carry `query_id`.
+/// Starting a search cancels the window's previous one.
+#[tauri::command]
+async fn start_search(
+    query_id: u64,
+    query: String,
+    filters: serde_json::Value,
+    sort: Option<Sort>,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let app = initialized_app(&state)?;
+    let search_query = SearchQuery {
+        query,
+        filters: serde_json::from_value(filters).map_err(|e| e.to_string())?,
+        limit: None,
+        offset: None,
+        sort: sort.unwrap_or_default(),
+    };
+
+    let (sink, mut batches) = ResultSink::new();
+    let label = window.label().to_string();
+    {
+        let mut searches = state.searches.lock().map_err(|e| e.to_string())?;
+        if let Some((_, previous)) = searches.insert(label.clone(), (query_id, sink.cancel_handle())) {
+            previous.cancel();
+        }
+    }
+
+    let searches = state.searches.clone();
+    tauri::async_runtime::spawn(async move {
+        let start_time = Instant::now();
+        let batch_window = window.clone();
+        let forward = tauri::async_runtime::spawn(async move {
+            while let Some(entries) = batches.recv().await {
+                let _ = batch_window.emit("search-batch", SearchBatch { query_id, entries });
+            }
+        });
+
+        let result = app.result_sets.open_streaming(&search_query, &sink).await;
+        let cancelled = sink.is_cancelled();
+        // Every batch is delivered before the final event
+        drop(sink);
+        let _ = forward.await;
+
+        if let Ok(mut searches) = searches.lock() {
+            if searches.get(&label).is_some_and(|(id, _)| *id == query_id) {
+                searches.remove(&label);
+            }
+        }
+
+        let (result_set, error) = match result {
+            Ok(info) => (Some(info), None),
+            Err(_) if cancelled => (None, None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        let finished = SearchFinished {
+            query_id,
+            total_count: result_set.as_ref().map_or(0, |info| info.total_count),
+            query_time_ms: start_time.elapsed().as_millis() as u64,
+            result_set,
+            cancelled,
+            error,
+        };
+        let _ = window.emit("search-finished", finished);
+    });
+
+    Ok(())
+}
+
+/// Stop the calling window's streamed search, if one is running
+#[tauri::command]
+async fn cancel_search(window: Window, state: State<'_, AppState>) -> Result<(), String> {
+    let mut searches = state.searches.lock().map_err(|e| e.to_string())?;
+    if let Some((_, search)) = searches.remove(window.label()) {
+        search.cancel();
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn fetch_result_rows(
+    handle: u64,
+    offset: u64,
+    limit: u64,
+    state: State<'_, AppState>,
+) -> Result<Vec<FileEntry>, String> {
+    let app = initialized_app(&state)?;
+    app.result_sets.fetch(handle, offset, limit).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn sort_result_set(handle: u64, sort: Sort, state: State<'_, AppState>) -> Result<ResultSetInfo, String> {
+    let app = initialized_app(&state)?;
+    app.result_sets.sort(handle, sort).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn filter_result_set(handle: u64, text: String, state: State<'_, AppState>) -> Result<ResultSetInfo, String> {
+    let app = initialized_app(&state)?;
+    app.result_sets.filter(handle, &text).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn close_result_set(handle: u64, state: State<'_, AppState>) -> Result<(), String> {
+    let app = initialized_app(&state)?;
+    app.result_sets.close(handle);
+    Ok(())
+}
+
+/// Write every match of `query` to the file at `path`; returns the number of rows written
+#[tauri::command]
+async fn export_search(
+    query: String,
+    filters: serde_json::Value,
+    sort: Option<Sort>,
+    path: String,
+    options: ExportOptions,
+    state: State<'_, AppState>,
+) -> Result<u64, String> {
+    let app = initialized_app(&state)?;
+    let search_query = SearchQuery {
+        query,
+        filters: serde_json::from_value(filters).map_err(|e| e.to_string())?,
+        limit: None,
+        offset: None,
+        sort: sort.unwrap_or_default(),
+    };
+    app.export_search(&search_query, std::path::Path::new(&path), options)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Write the rows of a result set, as currently sorted and filtered, to the file at `path`
+#[tauri::command]
+async fn export_result_set(
+    handle: u64,
+    path: String,
+    options: ExportOptions,
+    state: State<'_, AppState>,
+) -> Result<u64, String> {
+    let app = initialized_app(&state)?;
+    app.export_result_set(handle, std::path::Path::new(&path), options)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Imported Everything file lists, by name
+#[tauri::command]
+async fn list_file_lists(state: State<'_, AppState>) -> Result<Vec<FileListInfo>, String> {
+    let app = initialized_app(&state)?;
+    Ok(app.file_lists.lists())
+}
+
+/// Load the `.efu` file at `path` as the file list `name`, replacing a list of that name
+#[tauri::command]
+async fn import_file_list(name: String, path: String, state: State<'_, AppState>) -> Result<FileListInfo, String> {
+    let app = initialized_app(&state)?;
+    app.file_lists
+        .import(&name, std::path::Path::new(&path))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn remove_file_list(name: String, state: State<'_, AppState>) -> Result<bool, String> {
+    let app = initialized_app(&state)?;
+    app.file_lists.remove(&name).await.map_err(|e| e.to_string())
+}
+
+/// Group files with identical contents among the matches of `query`, or among all indexed
+/// files without one. Progress is sent to the calling window as `duplicate-progress`
+/// events. Starting a duplicate search cancels the previous one.
+#[tauri::command]
+async fn find_duplicates(
+    query: Option<String>,
+    filters: Option<serde_json::Value>,
+    min_size: Option<u64>,
+    window: Window,
+    state: State<'_, AppState>,
+) -> Result<DuplicateReport, String> {
+    let app = initialized_app(&state)?;
+    let search_query = match query {
+        Some(query) => Some(SearchQuery {
+            query,
+            filters: match filters {
+                Some(filters) => serde_json::from_value(filters).map_err(|e| e.to_string())?,
+                None => Default::default(),
+            },
+            limit: None,
+            offset: None,
+            sort: Default::default(),
+        }),
+        None => None,
+    };
+
+    let cancel = CancelHandle::default();
+    if let Some(previous) = state.duplicates.lock().map_err(|e| e.to_string())?.replace(cancel.clone()) {
+        previous.cancel();
+    }
+
+    let progress = |progress| {
+        let _ = window.emit("duplicate-progress", progress);
+    };
+    let report = app
+        .find_duplicates(search_query.as_ref(), min_size.unwrap_or(1), progress, &cancel)
+        .await
+        .map_err(|e| e.to_string());
+
+    if let Ok(mut duplicates) = state.duplicates.lock() {
+        if duplicates.as_ref().is_some_and(|running| running.same_as(&cancel)) {
+            *duplicates = None;
+        }
+    }
+    report
+}
+
+/// Stop the running duplicate search, if any
+#[tauri::command]
+async fn cancel_duplicates(state: State<'_, AppState>) -> Result<(), String> {
+    if let Some(running) = state.duplicates.lock().map_err(|e| e.to_string())?.take() {
+        running.cancel();
+    }
+    Ok(())
+}
+
+/// Completions of a partly typed query, cheap enough to call on every keystroke
+#[tauri::command]
+async fn complete_query(
+    partial: String,
+    limit: Option<usize>,
+    state: State<'_, AppState>,
+) -> Result<Vec<Completion>, String> {
+    let app = initialized_app(&state)?;
+    app.search_engine
+        .suggest_completions(&partial, limit.unwrap_or(8))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Remember a query the user acted on; nothing is recorded in private mode
+#[tauri::command]
+async fn record_search(query: String, filters: serde_json::Value, state: State<'_, AppState>) -> Result<(), String> {
+    let app = initialized_app(&state)?;
+    let search_query = SearchQuery {
+        query,
+        filters: serde_json::from_value(filters).map_err(|e| e.to_string())?,
+        limit: None,
+        offset: None,
+        sort: Default::default(),
+    };
+    app.search_engine.save_search_history(&search_query).await.map_err(|e| e.to_string())
+}
+
+/// Past queries, most frequently and recently used first
+#[tauri::command]
+async fn get_search_history(limit: Option<usize>, state: State<'_, AppState>) -> Result<Vec<HistoryEntry>, String> {
+    let app = initialized_app(&state)?;
+    app.search_engine
+        .get_recent_searches(limit.unwrap_or(50))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn delete_search_history(query: String, state: State<'_, AppState>) -> Result<bool, String> {
+    let app = initialized_app(&state)?;
+    app.search_engine.delete_search_history(&query).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn clear_search_history(state: State<'_, AppState>) -> Result<(), String> {
+    let app = initialized_app(&state)?;
+    app.search_engine.clear_search_history().await.map_err(|e| e.to_string())
+}
+
+/// Saved searches, by name
+#[tauri::command]
+async fn list_saved_searches(state: State<'_, AppState>) -> Result<Vec<SavedSearch>, String> {
+    let app = initialized_app(&state)?;
+    app.search_engine.saved_searches().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn create_saved_search(saved: SavedSearch, state: State<'_, AppState>) -> Result<(), String> {
+    let app = initialized_app(&state)?;
+    app.search_engine.create_saved_search(&saved).await.map_err(|e| e.to_string())
+}
+
+/// Replace the saved search called `name`, which may also rename it
+#[tauri::command]
+async fn update_saved_search(name: String, saved: SavedSearch, state: State<'_, AppState>) -> Result<(), String> {
+    let app = initialized_app(&state)?;
+    app.search_engine.update_saved_search(&name, &saved).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn delete_saved_search(name: String, state: State<'_, AppState>) -> Result<bool, String> {
+    let app = initialized_app(&state)?;
+    app.search_engine.delete_saved_search(&name).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn import_saved_searches(path: String, state: State<'_, AppState>) -> Result<usize, String> {
+    let app = initialized_app(&state)?;
+    app.search_engine
+        .import_saved_searches(std::path::Path::new(&path))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn export_saved_searches(path: String, state: State<'_, AppState>) -> Result<usize, String> {
+    let app = initialized_app(&state)?;
+    app.search_engine
+        .export_saved_searches(std::path::Path::new(&path))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_private_mode(enabled: bool, state: State<'_, AppState>) -> Result<(), String> {
+    let app = initialized_app(&state)?;
+    app.search_engine.set_private_mode(enabled);
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_private_mode(state: State<'_, AppState>) -> Result<bool, String> {
+    let app = initialized_app(&state)?;
+    Ok(app.search_engine.private_mode())
+}
+
 #[tauri::command]
 async fn check_backend_status(state: State<'_, AppState>) -> Result<bool, String> {
     let app_guard = state.app.lock().map_err(|e| e.to_string())?;
@@ -70,7 +465,20 @@ async fn get_index_stats(state: State<'_, AppState>) -> Result<IndexStats, Strin
 }
 
 #[tauri::command]
-async fn open_file(path: String) -> Result<(), String> {
+async fn get_backend_status(state: State<'_, AppState>) -> Result<Vec<BackendStatus>, String> {
+    let app = {
+        let app_guard = state.app.lock().map_err(|e| e.to_string())?;
+        match *app_guard {
+            Some(ref app) => Arc::clone(app),
+            None => return Err("Application not initialized".to_string()),
+        }
+    };
+
+    Ok(app.backend_status().await)
+}
+
+#[tauri::command]
+async fn open_file(path: String, state: State<'_, AppState>) -> Result<(), String> {
     // Check if the file exists first
     if !std::path::Path::new(&path).exists() {
         return Err(format!("File or folder does not exist: {}", path));
@@ -100,7 +508,14 @@ async fn open_file(path: String) -> Result<(), String> {
             .spawn()
             .map_err(|e| format!("Failed to open file '{}': {}", path, e))?;
     }
-    
+
+    // Opened files rank higher in later searches; failing to record that is not fatal
+    if let Ok(app) = initialized_app(&state) {
+        if let Err(e) = app.record_open(&path).await {
+            eprintln!("Failed to record opening '{}': {}", path, e);
+        }
+    }
+
     Ok(())
 }
 
@@ -168,6 +583,61 @@ async fn open_file_location(path: String) -> Result<(), String> {
     Ok(())
 }
 
+#[tauri::command]
+async fn rename_file(
+    path: String,
+    new_name: String,
+    state: State<'_, AppState>,
+) -> Result<FileOperationReport, String> {
+    let app = initialized_app(&state)?;
+    app.file_operations.rename(&path, &new_name).await.map_err(|e| e.to_string())
+}
+
+/// Move results into the folder `destination`; files that cannot be moved are reported
+/// without stopping the rest
+#[tauri::command]
+async fn move_files(
+    paths: Vec<String>,
+    destination: String,
+    state: State<'_, AppState>,
+) -> Result<FileOperationReport, String> {
+    let app = initialized_app(&state)?;
+    app.file_operations.move_to(&paths, &destination).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn copy_files(
+    paths: Vec<String>,
+    destination: String,
+    state: State<'_, AppState>,
+) -> Result<FileOperationReport, String> {
+    let app = initialized_app(&state)?;
+    app.file_operations.copy_to(&paths, &destination).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn trash_files(paths: Vec<String>, state: State<'_, AppState>) -> Result<FileOperationReport, String> {
+    let app = initialized_app(&state)?;
+    app.file_operations.trash(&paths).await.map_err(|e| e.to_string())
+}
+
+/// Undo the latest file operation; `None` when there is nothing to undo
+#[tauri::command]
+async fn undo_file_operation(state: State<'_, AppState>) -> Result<Option<FileOperationReport>, String> {
+    let app = initialized_app(&state)?;
+    app.file_operations.undo_last().await.map_err(|e| e.to_string())
+}
+
+/// Recent file operations that can still be undone, latest first
+#[tauri::command]
+async fn get_file_operations(
+    limit: Option<usize>,
+    state: State<'_, AppState>,
+) -> Result<Vec<FileOperationRecord>, String> {
+    let app = initialized_app(&state)?;
+    app.file_operations.journal(limit.unwrap_or(50)).await.map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 async fn show_window(window: Window) -> Result<(), String> {
     window.show().map_err(|e| e.to_string())?;
@@ -203,10 +673,10 @@ fn setup_global_shortcut(app: &tauri::AppHandle) -> Result<(), Box<dyn std::erro
 async fn initialize_backend(state: Arc<Mutex<Option<Arc<EverythingClone>>>>, _app_handle: tauri::AppHandle) -> Result<(), Box<dyn std::error::Error>> {
     println!("Initializing backend...");
     
-    // Initialize Everything SDK directly - no database needed
+    // Initialize Everything SDK, falling back to the native SQLite index
     let mut app = EverythingClone::new().await?;
     
-    println!("Starting indexing (Everything SDK handles this automatically)...");
+    println!("Starting indexing...");
     app.start_indexing().await?;
     
     println!("Getting initial stats...");
@@ -230,6 +700,8 @@ fn main() {
     // Create application state
     let app_state = AppState {
         app: Arc::new(Mutex::new(None)),
+        searches: Arc::new(Mutex::new(HashMap::new())),
+        duplicates: Arc::new(Mutex::new(None)),
     };
     
     let app_state_clone = app_state.app.clone();
@@ -286,10 +758,44 @@ fn main() {
         })
         .invoke_handler(tauri::generate_handler![
             search_files,
+            open_result_set,
+            start_search,
+            cancel_search,
+            fetch_result_rows,
+            sort_result_set,
+            filter_result_set,
+            close_result_set,
+            export_search,
+            export_result_set,
+            list_file_lists,
+            import_file_list,
+            remove_file_list,
+            complete_query,
+            record_search,
+            get_search_history,
+            delete_search_history,
+            clear_search_history,
+            list_saved_searches,
+            create_saved_search,
+            update_saved_search,
+            delete_saved_search,
+            import_saved_searches,
+            export_saved_searches,
+            set_private_mode,
+            get_private_mode,
+            find_duplicates,
+            cancel_duplicates,
             check_backend_status,
             get_index_stats,
+            get_backend_status,
             open_file,
             open_file_location,
+            rename_file,
+            move_files,
+            copy_files,
+            trash_files,
+            undo_file_operation,
+            get_file_operations,
             show_window,
             hide_window
         ])